@@ -1,6 +1,9 @@
+pub mod admin;
 pub mod atcoder;
 pub mod server;
 
+pub mod autocomplete;
+pub mod contest_info;
 pub mod contests;
 pub mod help;
 pub mod link_accounts;
@@ -8,10 +11,18 @@ pub mod owner;
 pub mod rating;
 pub mod register_accounts;
 pub mod role;
+pub mod server_config;
 pub mod set_ac_notify;
-pub mod set_everyone;
+pub mod set_discussion_threads;
 pub mod set_language;
+pub mod set_mention;
 pub mod set_notification_contest;
+pub mod set_notification_route;
 pub mod set_notification_submission;
-pub mod set_notify_everyone;
+pub mod set_quiet_hours;
+pub mod set_scheduled_events;
+pub mod set_timezone;
+pub mod set_weekly_schedule;
+pub mod setup;
 pub mod show_notification;
+pub mod verify_account;
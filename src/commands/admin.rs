@@ -0,0 +1,74 @@
+use crate::scheduled_events;
+use crate::scraping::contests::{update_contests, SyncMode};
+use crate::send_message::send_contest_changes;
+use crate::{Context, Error};
+
+use mysql::prelude::*;
+use poise::serenity_prelude::{CreateEmbed, CreateEmbedAuthor};
+use poise::CreateReply;
+
+/// Commands for the operators of the bot. Only the owners of the Discord application can use them.
+#[poise::command(prefix_command, slash_command, owners_only, subcommands("sync_contests", "sync_status"))]
+pub async fn admin(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Fetch every contest archive page and update the stored contests.
+#[poise::command(prefix_command, slash_command, owners_only, rename = "sync-contests")]
+async fn sync_contests(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+    let started_at = std::time::Instant::now();
+    let response = match update_contests(&ctx.data().conn, SyncMode::Full).await {
+        Ok(sync) => {
+            send_contest_changes(&ctx.data().conn, &sync.changes).await?;
+            scheduled_events::sync_events(&ctx.data().conn, ctx.http(), &sync.changes).await?;
+            format!(
+                "Synced {} archive pages ({} contests, {} changes) in {:.1}s.",
+                sync.archive_pages,
+                sync.contests_seen,
+                sync.changes.len(),
+                started_at.elapsed().as_secs_f64()
+            )
+        }
+        Err(err) => format!("The contest sync failed: {}", err),
+    };
+    ctx.send(CreateReply::default().content(response).ephemeral(true)).await?;
+    Ok(())
+}
+
+/// Show the latest contest syncs.
+#[poise::command(prefix_command, slash_command, owners_only, rename = "sync-status")]
+async fn sync_status(ctx: Context<'_>) -> Result<(), Error> {
+    let pool = ctx.data().conn.lock().await;
+    let mut conn = pool.get_conn()?;
+    type SyncRow = (String, String, i32, i32, i32, Option<String>, String, Option<u64>);
+    let syncs: Vec<SyncRow> = conn.query(
+        "SELECT mode, status, archive_pages, contests_seen, changes, error, DATE_FORMAT(started_at, '%Y-%m-%d %H:%i:%s'), duration_ms
+        FROM contest_syncs ORDER BY id DESC LIMIT 10",
+    )?;
+    let description = if syncs.is_empty() {
+        "No contest sync has run yet.".to_string()
+    } else {
+        syncs
+            .iter()
+            .map(|(mode, status, archive_pages, contests_seen, changes, error, started_at, duration_ms)| {
+                let duration = duration_ms.map(|x| format!("{:.1}s", x as f64 / 1000.0)).unwrap_or("-".to_string());
+                let mut line = format!(
+                    "`{}` {} **{}**: {} pages, {} contests, {} changes, {}",
+                    started_at, mode, status, archive_pages, contests_seen, changes, duration
+                );
+                if let Some(error) = error {
+                    line += &format!("\n> {}", error);
+                }
+                line
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let embed = CreateEmbed::default()
+        .author(CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(ctx.data().avatar_url.as_str()).url("https://atcoder-notify.com/"))
+        .title("Contest syncs")
+        .description(description);
+    ctx.send(CreateReply::default().embed(embed).ephemeral(true)).await?;
+    Ok(())
+}
@@ -8,10 +8,13 @@ use crate::commands::link_accounts::unlink_account;
 use crate::commands::rating::rating;
 use crate::commands::register_accounts::delete_account;
 use crate::commands::register_accounts::register_account;
+use crate::commands::register_accounts::register_accounts;
 use crate::commands::register_accounts::show_accounts;
 use crate::commands::set_notification_contest::{set_notification_contest, unset_notification_contest};
+use crate::commands::set_notification_route::{set_notification_route, unset_notification_route};
 use crate::commands::set_notification_submission::{set_notification_submission, unset_notification_submission};
 use crate::commands::show_notification::show_notification;
+use crate::commands::verify_account::verify_account;
 
 #[poise::command(
     prefix_command,
@@ -24,9 +27,11 @@ use crate::commands::show_notification::show_notification;
         "link_account",
         "unlink_account",
         "show_linked_account",
+        "verify_account",
         "delete_account",
         "show_accounts",
         "register_account",
+        "register_accounts",
         "contest",
         "rating"
     )
@@ -38,7 +43,7 @@ pub async fn atcoder(_ctx: Context<'_>) -> Result<(), Error> {
     prefix_command,
     slash_command,
     rename = "set-notification",
-    subcommands("set_notification_contest", "set_notification_submission")
+    subcommands("set_notification_contest", "set_notification_submission", "set_notification_route")
 )]
 pub async fn set_notification(_ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
@@ -48,7 +53,7 @@ pub async fn set_notification(_ctx: Context<'_>) -> Result<(), Error> {
     prefix_command,
     slash_command,
     rename = "unset-notification",
-    subcommands("unset_notification_contest", "unset_notification_submission")
+    subcommands("unset_notification_contest", "unset_notification_submission", "unset_notification_route")
 )]
 pub async fn unset_notification(_ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
@@ -0,0 +1,118 @@
+use std::time::Duration;
+
+use crate::Context;
+
+use mysql::prelude::*;
+use mysql::*;
+use poise::serenity_prelude as serenity;
+
+/// Discord shows at most 25 autocomplete choices.
+const MAX_CHOICES: usize = 25;
+/// Discord drops autocomplete responses after 3 seconds, so give up waiting for the database well before that.
+const POOL_WAIT: Duration = Duration::from_millis(1500);
+
+async fn get_pool(ctx: Context<'_>) -> Option<Pool> {
+    match tokio::time::timeout(POOL_WAIT, ctx.data().conn.lock()).await {
+        Ok(pool) => Some(pool.clone()),
+        Err(_) => {
+            log::warn!("autocomplete gave up waiting for the database");
+            None
+        }
+    }
+}
+
+/// Escape `partial` for use as a `LIKE` prefix. AtCoder usernames often contain `_`, which is a wildcard otherwise.
+fn like_prefix(partial: &str) -> String {
+    let mut pattern = partial.trim().replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    pattern.push('%');
+    pattern
+}
+
+async fn find_atcoder_users(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return vec![];
+    };
+    let Some(pool) = get_pool(ctx).await else {
+        return vec![];
+    };
+    let Ok(mut conn) = pool.get_conn() else {
+        return vec![];
+    };
+    conn.exec(
+        "SELECT DISTINCT
+            COALESCE(atcoder_identities.screen_name, users.atcoder_username) AS name
+        FROM
+            users
+        LEFT JOIN
+            atcoder_identities
+        ON
+            atcoder_identities.user_key = users.atcoder_username
+        WHERE users.server_id=:server_id AND users.atcoder_username LIKE :prefix
+        ORDER BY name
+        LIMIT 25",
+        params! {"server_id" => guild_id.get(), "prefix" => like_prefix(partial)},
+    )
+    .unwrap_or_default()
+}
+
+/// Suggest AtCoder users registered or linked in this server.
+pub async fn autocomplete_atcoder_user(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    find_atcoder_users(ctx, partial).await
+}
+
+/// Suggest AtCoder users for a comma separated list, completing the last entry.
+pub async fn autocomplete_atcoder_user_list(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let (head, last) = match partial.rfind(',') {
+        Some(index) => (&partial[..=index], &partial[index + 1..]),
+        None => ("", partial),
+    };
+    let mut choices = vec![];
+    if head.is_empty() && "all".starts_with(last.trim()) {
+        choices.push("all".to_string());
+    }
+    let entered: Vec<&str> = head.split(',').map(|x| x.trim()).collect();
+    for user in find_atcoder_users(ctx, last).await {
+        if !entered.iter().any(|x| x.eq_ignore_ascii_case(&user)) {
+            choices.push(format!("{}{}", head, user));
+        }
+    }
+    // Discord rejects choice values longer than 100 characters.
+    choices.retain(|x| x.len() <= 100);
+    choices.truncate(MAX_CHOICES);
+    choices
+}
+
+/// Suggest contests whose id or name starts with the input, newest first.
+pub async fn autocomplete_contest(ctx: Context<'_>, partial: &str) -> Vec<serenity::AutocompleteChoice> {
+    let Some(pool) = get_pool(ctx).await else {
+        return vec![];
+    };
+    let Ok(mut conn) = pool.get_conn() else {
+        return vec![];
+    };
+    let contests: Vec<(String, String)> = conn
+        .exec(
+            "SELECT contest_id, name FROM contests WHERE contest_id LIKE :prefix OR name LIKE :prefix ORDER BY start_at DESC LIMIT 25",
+            params! {"prefix" => like_prefix(partial)},
+        )
+        .unwrap_or_default();
+    contests
+        .into_iter()
+        .map(|(contest_id, name)| {
+            let label: String = format!("{} ({})", name, contest_id).chars().take(100).collect();
+            serenity::AutocompleteChoice::new(label, contest_id)
+        })
+        .collect()
+}
+
+/// Suggest IANA time zones containing the input, such as `Asia/Tokyo` for `tokyo`.
+pub async fn autocomplete_timezone(_ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let partial = partial.trim().to_lowercase();
+    chrono_tz::TZ_VARIANTS
+        .iter()
+        .map(|timezone| timezone.name())
+        .filter(|name| name.to_lowercase().contains(&partial))
+        .take(MAX_CHOICES)
+        .map(|name| name.to_string())
+        .collect()
+}
@@ -0,0 +1,185 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::commands::autocomplete::autocomplete_contest;
+use crate::commands::contests::{load_contests, Contest};
+use crate::scraping::contest_id::ContestId;
+use crate::scraping::contest_info::{get_contest_overview, get_standings_difficulties, get_task_list, ContestTask};
+use crate::utils::MAX_DESCRIPTION_LENGTH;
+use crate::{Context, Error};
+
+use mysql::prelude::*;
+use mysql::*;
+use poise::serenity_prelude::{CreateEmbed, CreateEmbedAuthor};
+use poise::CreateReply;
+use reqwest::Client;
+
+/// Show the schedule, writers and tasks of an AtCoder contest.
+#[poise::command(prefix_command, slash_command)]
+pub async fn info(
+    ctx: Context<'_>,
+    #[description = "contest"]
+    #[autocomplete = "autocomplete_contest"]
+    contest: String,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+    let pool = ctx.data().conn.lock().await.clone();
+    let mut conn = pool.get_conn()?;
+
+    let mut lang = "ja";
+    let selected_data: Vec<String> = match ctx.guild_id() {
+        Some(guild_id) => conn.exec(
+            r"SELECT language FROM server_settings WHERE server_id=:server_id",
+            params! {"server_id" => guild_id.get()},
+        )?,
+        None => vec![],
+    };
+    if selected_data.len() == 1 {
+        lang = selected_data[0].as_str();
+    }
+
+    let contests = load_contests(&mut conn);
+    let input = contest.trim();
+    let contest_id = ContestId::parse(input);
+    let Some(contest) = contests.iter().find(|x| Some(&x.contest_id) == contest_id.as_ref()) else {
+        let content = if lang == "ja" {
+            format!("コンテスト `{}` が見つかりませんでした。", input)
+        } else {
+            format!("Contest `{}` was not found.", input)
+        };
+        ctx.send(CreateReply::default().content(content).ephemeral(true)).await?;
+        return Ok(());
+    };
+
+    let client = Client::builder().gzip(true).cookie_provider(Arc::clone(&ctx.data().cookie_store)).build()?;
+    let overview = get_contest_overview(&client, &contest.contest_id).await.unwrap_or_else(|err| {
+        log::warn!("could not get the top page of {}: {}", contest.contest_id, err);
+        Default::default()
+    });
+    let now = chrono::Local::now();
+    let tasks = if contest.start_time <= now {
+        get_task_list(&client, &contest.contest_id).await.unwrap_or_else(|err| {
+            log::warn!("could not get the tasks of {}: {}", contest.contest_id, err);
+            vec![]
+        })
+    } else {
+        vec![]
+    };
+    let ended = contest.end_time < now;
+
+    let mut results = TaskResults {
+        difficulties: BTreeMap::new(),
+        solved: BTreeMap::new(),
+    };
+    if ended && !tasks.is_empty() {
+        let problem_difficulties = ctx.data().problem_difficulties.get(&client).await;
+        for task in &tasks {
+            if let Some(difficulty) = problem_difficulties.get(&task.task_id) {
+                results.difficulties.insert(task.task_id.clone(), *difficulty);
+            }
+        }
+        // AtCoder Problems takes a while to estimate new contests, so fall back to the standings.
+        if results.difficulties.len() < tasks.len() {
+            let is_rated = contest.rating_raw.trim() != "-";
+            let standings_difficulties = get_standings_difficulties(&client, &contest.contest_id, is_rated).await.unwrap_or_default();
+            for task in &tasks {
+                if let Some(difficulty) = standings_difficulties.get(&task.assignment) {
+                    results.difficulties.entry(task.task_id.clone()).or_insert(*difficulty);
+                }
+            }
+        }
+        if let Some(guild_id) = ctx.guild_id() {
+            for task in &tasks {
+                let count: Option<i64> = conn.exec_first(
+                    "SELECT COUNT(DISTINCT users.atcoder_username)
+                    FROM submission_data
+                    JOIN users ON users.atcoder_username = submission_data.user_id
+                    WHERE users.server_id=:server_id AND submission_data.problem_id=:problem_id",
+                    params! {"server_id" => guild_id.get(), "problem_id" => &task.task_id},
+                )?;
+                results.solved.insert(task.task_id.clone(), count.unwrap_or(0));
+            }
+        }
+    }
+
+    let writers = if overview.writers.is_empty() {
+        "-".to_string()
+    } else {
+        overview.writers.join(", ")
+    };
+    let description = if tasks.is_empty() {
+        if lang == "ja" {
+            "問題一覧はコンテスト開始後に表示されます。".to_string()
+        } else {
+            "The task list is shown after the contest starts.".to_string()
+        }
+    } else {
+        task_lines(contest, &tasks, &overview.points, ended.then_some(&results), lang)
+    };
+    let embed = CreateEmbed::default()
+        .author(CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(ctx.data().avatar_url.as_str()).url("https://atcoder-notify.com/"))
+        .title(&contest.name)
+        .url(contest.contest_id.url())
+        .description(description)
+        .field(
+            if lang == "ja" { "開始" } else { "Start" },
+            format!("<t:{}:F>", contest.start_time.timestamp()),
+            true,
+        )
+        .field(
+            if lang == "ja" { "終了" } else { "End" },
+            format!("<t:{}:F>", contest.end_time.timestamp()),
+            true,
+        )
+        .field(
+            if lang == "ja" { "時間" } else { "Duration" },
+            format!("{}:{:02}", contest.duration / 60, contest.duration % 60),
+            true,
+        )
+        .field(if lang == "ja" { "レート変動範囲" } else { "Rated range" }, &contest.rating_raw, true)
+        .field(if lang == "ja" { "作問者" } else { "Writers" }, writers, false);
+
+    ctx.send(CreateReply::default().embed(embed).ephemeral(true)).await?;
+
+    Ok(())
+}
+
+/// What is known about the tasks once the contest is over, keyed by task id.
+struct TaskResults {
+    difficulties: BTreeMap<String, i32>,
+    solved: BTreeMap<String, i64>,
+}
+
+/// One line per task.
+fn task_lines(contest: &Contest, tasks: &[ContestTask], points: &BTreeMap<String, i32>, results: Option<&TaskResults>, lang: &str) -> String {
+    let mut description = String::new();
+    for task in tasks {
+        let mut line = format!("[{}]({}) {}", task.assignment, contest.contest_id.task_url(&task.task_id), task.name);
+        if let Some(point) = points.get(&task.assignment) {
+            line += &format!(" ({})", point);
+        }
+        if let Some(results) = results {
+            if let Some(difficulty) = results.difficulties.get(&task.task_id) {
+                line += &format!(" | diff {}", difficulty);
+            }
+            if let Some(count) = results.solved.get(&task.task_id) {
+                line += &if lang == "ja" {
+                    format!(" | {}人AC", count)
+                } else {
+                    format!(" | {} solved", count)
+                };
+            }
+            line += &format!(
+                " | [{}]({})",
+                if lang == "ja" { "解説" } else { "Editorial" },
+                contest.contest_id.task_editorial_url(&task.task_id)
+            );
+        }
+        if description.chars().count() + line.chars().count() + 1 > MAX_DESCRIPTION_LENGTH {
+            break;
+        }
+        description += &line;
+        description.push('\n');
+    }
+    description
+}
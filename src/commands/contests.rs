@@ -1,23 +1,175 @@
+use crate::commands::autocomplete::autocomplete_atcoder_user;
+use crate::commands::contest_info::info;
+use crate::commands::rating::AtCoderContestType;
+use crate::components::{pagination_row, turn_page, ComponentRequest};
+use crate::scraping::atcoder_identity::user_key;
+use crate::scraping::contest_id::ContestId;
 use crate::scraping::contest_type::{ContestRatingType, ContestType};
+use crate::timezone::{self, DEFAULT_TIMEZONE};
 use crate::utils::svg::create_table::{create_table, Align, Row, TableRowsText, TextConfig, Title};
 use crate::utils::svg_to_png::svg_to_png;
+use crate::Data;
 use crate::{Context, Error};
-use chrono::{DateTime, FixedOffset};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use chrono_tz::Tz;
 use mysql::prelude::*;
 use mysql::*;
-use poise::serenity_prelude::{CreateActionRow, CreateAttachment, CreateButton};
-use poise::CreateReply;
+use poise::serenity_prelude::{
+    self as serenity, ComponentInteraction, CreateActionRow, CreateAttachment, CreateInteractionResponse, CreateInteractionResponseMessage,
+};
+use poise::{BoxFuture, CreateReply};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
 #[derive(Debug)]
 pub struct Contest {
-    pub start_time: DateTime<FixedOffset>,
-    pub end_time: DateTime<FixedOffset>,
+    pub contest_id: ContestId,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
     pub contest_type: ContestType,
     pub rating_type: ContestRatingType,
     pub name: String,
     pub rating_raw: String,
+    /// Lowest and highest rating the contest is rated for. Unrated contests have an empty range.
+    pub rating_range: (i32, i32),
+    /// Length of the contest in minutes.
+    pub duration: i64,
+}
+
+/// All contests known to the bot, in no particular order.
+pub fn load_contests(conn: &mut PooledConn) -> Vec<Contest> {
+    query_contests(conn, "TRUE")
+}
+
+/// Contests that have not started yet, the one ending first at the top.
+pub fn load_upcoming_contests(conn: &mut PooledConn) -> Vec<Contest> {
+    query_contests(conn, "UTC_TIMESTAMP() < start_at ORDER BY end_at")
+}
+
+/// Contests starting within the next 7 days, the one starting first at the top.
+pub fn load_next_week_contests(conn: &mut PooledConn) -> Vec<Contest> {
+    query_contests(
+        conn,
+        "UTC_TIMESTAMP() < start_at AND start_at < UTC_TIMESTAMP() + INTERVAL 7 DAY ORDER BY start_at",
+    )
+}
+
+/// Contests running right now, the one ending first at the top.
+pub fn load_current_contests(conn: &mut PooledConn) -> Vec<Contest> {
+    query_contests(conn, "start_at <= UTC_TIMESTAMP() AND UTC_TIMESTAMP() <= end_at ORDER BY end_at")
+}
+
+/// `condition` is the rest of the `WHERE` clause, including any `ORDER BY`.
+fn query_contests(conn: &mut PooledConn, condition: &str) -> Vec<Contest> {
+    conn.query_map(
+        format!(
+            "select contest_id,start_at,end_at,duration,contest_type,rating_type,name,rating_range_raw,rating_range_start,rating_range_end from contests WHERE {}",
+            condition
+        ),
+        |(contest_id, start_time, end_time, duration, contest_type, rating_type, name, rating_raw, rating_range_start, rating_range_end): (
+            String,
+            NaiveDateTime,
+            NaiveDateTime,
+            i64,
+            i8,
+            i8,
+            String,
+            String,
+            i32,
+            i32,
+        )| {
+            Contest {
+                contest_id: ContestId::new(&contest_id),
+                start_time: start_time.and_utc(),
+                end_time: end_time.and_utc(),
+                contest_type: match contest_type {
+                    0 => ContestType::Algorithm,
+                    _ => ContestType::Heuristic,
+                },
+                rating_type: match rating_type {
+                    0 => ContestRatingType::ABC,
+                    1 => ContestRatingType::ARC,
+                    2 => ContestRatingType::AGC,
+                    _ => ContestRatingType::None,
+                },
+                name,
+                rating_raw,
+                rating_range: (rating_range_start, rating_range_end),
+                duration,
+            }
+        },
+    )
+    .unwrap()
+}
+
+/// Conditions for narrowing down a contest list. Every condition that is set must hold.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ContestFilter {
+    /// 0: Algorithm, 1: Heuristic
+    pub contest_type: Option<i8>,
+    /// 0: ABC, 1: ARC, 2: AGC, 3: unrated
+    pub rating_type: Option<i8>,
+    /// Case-insensitive part of the contest name.
+    pub name: Option<String>,
+    /// First start date to include, `YYYY-MM-DD`.
+    pub from: Option<String>,
+    /// Last start date to include, `YYYY-MM-DD`.
+    pub to: Option<String>,
+    /// AtCoder user whose contests are listed. The contests are the keys of [`load_performances`].
+    pub participant: Option<String>,
+    /// Rating that the contest must be rated for.
+    #[serde(default)]
+    pub rated_for: Option<i32>,
+    /// Time zone of `from` and `to`, [`DEFAULT_TIMEZONE`] when unset.
+    #[serde(default)]
+    pub timezone: Option<Tz>,
+}
+
+pub fn parse_date(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").ok()
+}
+
+impl ContestFilter {
+    /// Returns the offending value when `from` or `to` is not a valid date.
+    pub fn invalid_date(&self) -> Option<&str> {
+        [&self.from, &self.to].into_iter().flatten().find(|date| parse_date(date).is_none()).map(|date| date.as_str())
+    }
+
+    pub fn matches(&self, contest: &Contest, performances: &HashMap<ContestId, i32>) -> bool {
+        let contest_type = match contest.contest_type {
+            ContestType::Algorithm => 0,
+            ContestType::Heuristic => 1,
+        };
+        let rating_type = match contest.rating_type {
+            ContestRatingType::ABC => 0,
+            ContestRatingType::ARC => 1,
+            ContestRatingType::AGC => 2,
+            ContestRatingType::None => 3,
+        };
+        let start_date = contest.start_time.with_timezone(&self.timezone.unwrap_or(DEFAULT_TIMEZONE)).date_naive();
+        self.contest_type.is_none_or(|x| x == contest_type)
+            && self.rating_type.is_none_or(|x| x == rating_type)
+            && self.name.as_ref().is_none_or(|name| contest.name.to_lowercase().contains(&name.trim().to_lowercase()))
+            && self.from.as_deref().and_then(parse_date).is_none_or(|from| from <= start_date)
+            && self.to.as_deref().and_then(parse_date).is_none_or(|to| start_date <= to)
+            && (self.participant.is_none() || performances.contains_key(&contest.contest_id))
+            && self.rated_for.is_none_or(|rating| contest.rating_range.0 <= rating && rating <= contest.rating_range.1)
+    }
+}
+
+/// Performances of `atcoder_user` keyed by contest id.
+pub fn load_performances(conn: &mut PooledConn, atcoder_user: &str) -> HashMap<ContestId, i32> {
+    conn.exec_map(
+        "SELECT contest, performance FROM user_ratings
+        WHERE user_name = COALESCE((SELECT screen_name FROM atcoder_identities WHERE user_key=:user_key), :user_name)",
+        params! {"user_key" => user_key(atcoder_user.trim()), "user_name" => atcoder_user.trim()},
+        |(contest, performance): (String, i32)| (ContestId::new(&contest), performance),
+    )
+    .unwrap_or_default()
+    .into_iter()
+    .collect()
 }
 
 pub async fn create_contest_response(
@@ -26,12 +178,15 @@ pub async fn create_contest_response(
     contests: Vec<&Contest>,
     components: Vec<CreateActionRow>,
     start_no: i32,
+    performances: Option<&HashMap<ContestId, i32>>,
 ) -> (Vec<CreateActionRow>, CreateAttachment) {
     let mut counts: Vec<TextConfig> = vec![];
     let mut contest_type: Vec<TextConfig> = vec![];
     let mut contest_rating_type: Vec<TextConfig> = vec![];
     let mut contest_names: Vec<TextConfig> = vec![];
     let mut rating_range: Vec<TextConfig> = vec![];
+    let mut durations: Vec<TextConfig> = vec![];
+    let mut contest_performances: Vec<TextConfig> = vec![];
     if contests.is_empty() {
         contest_names.push(TextConfig {
             value: "(empty)".to_string(),
@@ -61,6 +216,16 @@ pub async fn create_contest_response(
             value: contest.rating_raw.to_string(),
             color: "white".to_string(),
         });
+        durations.push(TextConfig {
+            value: format!("{}:{:02}", contest.duration / 60, contest.duration % 60),
+            color: "white".to_string(),
+        });
+        if let Some(performances) = performances {
+            contest_performances.push(TextConfig {
+                value: performances.get(&contest.contest_id).map(|x| x.to_string()).unwrap_or("-".to_string()),
+                color: "white".to_string(),
+            });
+        }
         contest_rating_type.push(TextConfig {
             value: "◉".to_string(),
             color: match contest.rating_type {
@@ -72,7 +237,7 @@ pub async fn create_contest_response(
             .to_string(),
         });
     }
-    let rows = vec![
+    let mut rows = vec![
         Row::Text(TableRowsText {
             title: Title::Text("No.".to_string()),
             width: 200,
@@ -103,7 +268,21 @@ pub async fn create_contest_response(
             align: Align::Middle,
             data: rating_range,
         }),
+        Row::Text(TableRowsText {
+            title: Title::Text("Duration".to_string()),
+            width: 400,
+            align: Align::Middle,
+            data: durations,
+        }),
     ];
+    if performances.is_some() {
+        rows.push(Row::Text(TableRowsText {
+            title: Title::Text("Performance".to_string()),
+            width: 500,
+            align: Align::Middle,
+            data: contest_performances,
+        }));
+    }
     let file = create_table(&Arc::new(Mutex::new(pool.clone())), title.to_string(), rows).await;
     let attachment = CreateAttachment::bytes(
         svg_to_png(file.svg.as_str(), file.width as u32 / 4, file.height as u32 / 4, 0.25, 0.25),
@@ -122,7 +301,7 @@ fn ordinal_suffix(n: i32) -> String {
     format!("{}{}", n, suffix)
 }
 
-#[poise::command(prefix_command, slash_command, subcommands("past", "upcoming", "current"))]
+#[poise::command(prefix_command, slash_command, subcommands("past", "upcoming", "current", "info"))]
 pub async fn contest(_ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
@@ -134,35 +313,9 @@ async fn upcoming(ctx: Context<'_>) -> Result<(), Error> {
     let pool = ctx.data().conn.lock().await;
     let mut conn = pool.get_conn().unwrap();
 
-    let contests: Vec<Contest> = conn
-        .query_map(
-            "select start_time,duration,contest_type,rating_type,name,rating_range_raw from contests",
-            |(start_time, duration, contest_type, rating_type, name, rating_raw): (String, i64, i8, i8, String, String)| {
-                let start_time = chrono::DateTime::parse_from_str(&start_time, "%Y-%m-%d %H:%M:%S%z").unwrap();
-                let offset = chrono::Duration::minutes(duration);
-                Contest {
-                    start_time,
-                    end_time: start_time + offset,
-                    contest_type: match contest_type {
-                        0 => ContestType::Algorithm,
-                        _ => ContestType::Heuristic,
-                    },
-                    rating_type: match rating_type {
-                        0 => ContestRatingType::ABC,
-                        1 => ContestRatingType::ARC,
-                        2 => ContestRatingType::AGC,
-                        _ => ContestRatingType::None,
-                    },
-                    name,
-                    rating_raw,
-                }
-            },
-        )
-        .unwrap();
-    let mut contests: Vec<&Contest> = contests.iter().filter(|contest| chrono::Local::now() <= contest.start_time).collect();
-    contests.sort_by(|a, b| a.end_time.partial_cmp(&(b.end_time)).unwrap());
-
-    let (components, attachment) = create_contest_response("upcoming contests", pool.clone(), contests, vec![], 0).await;
+    let contests = load_upcoming_contests(&mut conn);
+
+    let (components, attachment) = create_contest_response("upcoming contests", pool.clone(), contests.iter().collect(), vec![], 0, None).await;
 
     let reply = CreateReply::default().components(components).attachment(attachment).ephemeral(true);
     ctx.send(reply).await?;
@@ -177,36 +330,9 @@ async fn current(ctx: Context<'_>) -> Result<(), Error> {
     let pool = ctx.data().conn.lock().await;
     let mut conn = pool.get_conn().unwrap();
 
-    let contests: Vec<Contest> = conn
-        .query_map(
-            "select start_time,duration,contest_type,rating_type,name,rating_range_raw from contests",
-            |(start_time, duration, contest_type, rating_type, name, rating_raw): (String, i64, i8, i8, String, String)| {
-                let start_time = chrono::DateTime::parse_from_str(&start_time, "%Y-%m-%d %H:%M:%S%z").unwrap();
-                let offset = chrono::Duration::minutes(duration);
-                Contest {
-                    start_time,
-                    end_time: start_time + offset,
-                    contest_type: match contest_type {
-                        0 => ContestType::Algorithm,
-                        _ => ContestType::Heuristic,
-                    },
-                    rating_type: match rating_type {
-                        0 => ContestRatingType::ABC,
-                        1 => ContestRatingType::ARC,
-                        2 => ContestRatingType::AGC,
-                        _ => ContestRatingType::None,
-                    },
-                    name,
-                    rating_raw,
-                }
-            },
-        )
-        .unwrap();
-    let mut contests: Vec<&Contest> =
-        contests.iter().filter(|contest| contest.start_time <= chrono::Local::now() && chrono::Local::now() <= contest.end_time).collect();
-    contests.sort_by(|a, b| a.end_time.partial_cmp(&(b.end_time)).unwrap());
-
-    let (components, attachment) = create_contest_response("current contests", pool.clone(), contests, vec![], 0).await;
+    let contests = load_current_contests(&mut conn);
+
+    let (components, attachment) = create_contest_response("current contests", pool.clone(), contests.iter().collect(), vec![], 0, None).await;
 
     let reply = CreateReply::default().components(components).attachment(attachment).ephemeral(true);
 
@@ -215,47 +341,83 @@ async fn current(ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
 
+#[derive(Debug, poise::ChoiceParameter)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum AtCoderRatingType {
+    #[name = "ABC"]
+    ABC,
+    #[name = "ARC"]
+    ARC,
+    #[name = "AGC"]
+    AGC,
+    #[name = "Unrated"]
+    Unrated,
+}
+
 /// Retrieve information on past AtCoder contests.
 #[poise::command(prefix_command, slash_command)]
-#[allow(clippy::needless_range_loop)]
-async fn past(ctx: Context<'_>) -> Result<(), Error> {
+#[allow(clippy::too_many_arguments)]
+async fn past(
+    ctx: Context<'_>,
+    #[description = "contest_type"] contest_type: Option<AtCoderContestType>,
+    #[description = "rating_type"] rating_type: Option<AtCoderRatingType>,
+    #[description = "Part of the contest name"] name: Option<String>,
+    #[description = "Earliest start date (YYYY-MM-DD)"] from: Option<String>,
+    #[description = "Latest start date (YYYY-MM-DD)"] to: Option<String>,
+    #[description = "Only contests this AtCoder user participated in"]
+    #[autocomplete = "autocomplete_atcoder_user"]
+    atcoder_user: Option<String>,
+) -> Result<(), Error> {
     let pool = ctx.data().conn.lock().await;
     let mut conn = pool.get_conn().unwrap();
 
-    let contests: Vec<Contest> = conn
-        .query_map(
-            "select start_time,duration,contest_type,rating_type,name,rating_range_raw from contests",
-            |(start_time, duration, contest_type, rating_type, name, rating_raw): (String, i64, i8, i8, String, String)| {
-                let start_time = chrono::DateTime::parse_from_str(&start_time, "%Y-%m-%d %H:%M:%S%z").unwrap();
-                let offset = chrono::Duration::minutes(duration);
-                Contest {
-                    start_time,
-                    end_time: start_time + offset,
-                    contest_type: match contest_type {
-                        0 => ContestType::Algorithm,
-                        _ => ContestType::Heuristic,
-                    },
-                    rating_type: match rating_type {
-                        0 => ContestRatingType::ABC,
-                        1 => ContestRatingType::ARC,
-                        2 => ContestRatingType::AGC,
-                        _ => ContestRatingType::None,
-                    },
-                    name,
-                    rating_raw,
-                }
-            },
-        )
-        .unwrap();
-    let mut contests: Vec<&Contest> = contests.iter().filter(|contest| chrono::Local::now() >= contest.start_time).collect();
-    contests.sort_by(|a, b| b.end_time.partial_cmp(&(a.end_time)).unwrap());
-
-    let components = vec![CreateActionRow::Buttons(vec![
-        CreateButton::new("goto_0").label("<").style(poise::serenity_prelude::ButtonStyle::Primary).disabled(true),
-        CreateButton::new("goto_1").label(">").style(poise::serenity_prelude::ButtonStyle::Primary),
-    ])];
+    let filter = ContestFilter {
+        contest_type: contest_type.map(|x| match x {
+            AtCoderContestType::Algorithm => 0,
+            AtCoderContestType::Heuristic => 1,
+        }),
+        rating_type: rating_type.map(|x| match x {
+            AtCoderRatingType::ABC => 0,
+            AtCoderRatingType::ARC => 1,
+            AtCoderRatingType::AGC => 2,
+            AtCoderRatingType::Unrated => 3,
+        }),
+        name,
+        from,
+        to,
+        participant: atcoder_user,
+        rated_for: None,
+        timezone: match ctx.guild_id() {
+            Some(guild_id) => Some(timezone::server_timezone(&mut conn, guild_id.get())?),
+            None => None,
+        },
+    };
+    if let Some(date) = filter.invalid_date() {
+        let mut lang = "ja";
+        let selected_data: Vec<String> = match ctx.guild_id() {
+            Some(guild_id) => conn.exec(
+                r"SELECT language FROM server_settings WHERE server_id=:server_id",
+                params! {"server_id" => guild_id.get()},
+            )?,
+            None => vec![],
+        };
+        if selected_data.len() == 1 {
+            lang = selected_data[0].as_str();
+        }
+        let content = if lang == "ja" {
+            format!("`{}` は日付として読み取れません。`YYYY-MM-DD` の形式で入力してください。", date)
+        } else {
+            format!("`{}` is not a valid date. Please use the `YYYY-MM-DD` format.", date)
+        };
+        ctx.send(CreateReply::default().content(content).ephemeral(true)).await?;
+        return Ok(());
+    }
 
-    let (components, attachment) = create_contest_response("past contests (page 1)", pool.clone(), contests[0..20].to_vec(), components, 0).await;
+    let contests = load_contests(&mut conn);
+    let performances = filter.participant.as_deref().map(|user| load_performances(&mut conn, user)).unwrap_or_default();
+    let state = PastContestsState { page: 0, filter };
+    let state_id = ctx.data().component_states.create(ctx.author().id, &state);
+    let (components, attachment) = past_contests_page(&pool, &contests, &performances, &state, state_id).await;
 
     let reply = CreateReply::default().components(components).attachment(attachment).ephemeral(true);
 
@@ -263,3 +425,71 @@ async fn past(ctx: Context<'_>) -> Result<(), Error> {
 
     Ok(())
 }
+
+const PAST_CONTESTS_PER_PAGE: usize = 20;
+
+#[derive(Serialize, Deserialize)]
+struct PastContestsState {
+    page: usize,
+    #[serde(default)]
+    filter: ContestFilter,
+}
+
+fn past_contests<'a>(contests: &'a [Contest], performances: &HashMap<ContestId, i32>, filter: &ContestFilter) -> Vec<&'a Contest> {
+    let mut contests: Vec<&Contest> = contests.iter().filter(|contest| contest.start_time <= Utc::now() && filter.matches(contest, performances)).collect();
+    contests.sort_by(|a, b| b.end_time.partial_cmp(&(a.end_time)).unwrap());
+    contests
+}
+
+async fn past_contests_page(
+    pool: &Pool,
+    contests: &[Contest],
+    performances: &HashMap<ContestId, i32>,
+    state: &PastContestsState,
+    state_id: u64,
+) -> (Vec<CreateActionRow>, CreateAttachment) {
+    let contests = past_contests(contests, performances, &state.filter);
+    let page_count = contests.len().div_ceil(PAST_CONTESTS_PER_PAGE);
+    let page = state.page.min(page_count.saturating_sub(1));
+    let start = page * PAST_CONTESTS_PER_PAGE;
+    let end = (start + PAST_CONTESTS_PER_PAGE).min(contests.len());
+    let components = vec![pagination_row("contests", state_id, page, page_count)];
+    create_contest_response(
+        format!("past contests (page {})", page + 1).as_str(),
+        pool.clone(),
+        contests[start..end].to_vec(),
+        components,
+        start as i32,
+        state.filter.participant.as_ref().map(|_| performances),
+    )
+    .await
+}
+
+/// Page through `/atcoder contest past`.
+pub fn handle_component<'a>(
+    ctx: &'a serenity::Context,
+    data: &'a Data,
+    interaction: &'a ComponentInteraction,
+    request: ComponentRequest<'a>,
+) -> BoxFuture<'a, Result<(), Error>> {
+    Box::pin(async move {
+        let Some(state) = request.state else {
+            return Ok(());
+        };
+        let Some(mut past) = state.data::<PastContestsState>() else {
+            return Ok(());
+        };
+        let pool = data.conn.lock().await.clone();
+        let mut conn = pool.get_conn()?;
+        let contests = load_contests(&mut conn);
+        let performances = past.filter.participant.as_deref().map(|user| load_performances(&mut conn, user)).unwrap_or_default();
+        let page_count = past_contests(&contests, &performances, &past.filter).len().div_ceil(PAST_CONTESTS_PER_PAGE);
+        past.page = turn_page(past.page, request.action, page_count);
+        data.component_states.update(state.id, &past);
+
+        let (components, attachment) = past_contests_page(&pool, &contests, &performances, &past, state.id).await;
+        let reply = CreateInteractionResponse::UpdateMessage(CreateInteractionResponseMessage::default().add_file(attachment).components(components));
+        interaction.create_response(ctx, reply).await?;
+        Ok(())
+    })
+}
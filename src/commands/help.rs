@@ -42,30 +42,42 @@ pub async fn help(ctx: Context<'_>, #[description = "only_admin"] only_admin: Op
         serde_json::from_str(include_str!("../assets/commands_en.json")).unwrap()
     };
 
-    let mut embed = serenity::CreateEmbed::default()
-        .title(if only_admin { "Owner Only Commands" } else { "Commands" })
-        .author(CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(ctx.data().avatar_url.as_str()).url("https://atcoder-notify.com/"));
-    for i in help_obj {
-        if only_admin {
-            if i.is_owner_only {
-                embed = embed.field(i.usage, i.description.join("\n"), false);
-            }
-        } else {
-            embed = embed.field(
-                if i.is_owner_only {
-                    format!("[**Owner Only**] {}", i.usage)
-                } else {
-                    i.usage.to_string()
-                },
-                i.description.join("\n"),
-                false,
-            );
+    let fields: Vec<(String, String)> = help_obj
+        .into_iter()
+        .filter(|i| !only_admin || i.is_owner_only)
+        .map(|i| {
+            let usage = if i.is_owner_only && !only_admin {
+                format!("[**Owner Only**] {}", i.usage)
+            } else {
+                i.usage
+            };
+            (usage, i.description.join("\n"))
+        })
+        .collect();
+
+    // An embed holds at most 25 fields and 6000 characters, so long lists are sent as several messages.
+    let mut pages: Vec<Vec<(String, String)>> = vec![vec![]];
+    let mut page_length = 0;
+    for (usage, description) in fields {
+        let length = usage.chars().count() + description.chars().count();
+        if pages.last().unwrap().len() == 25 || page_length + length > 5000 {
+            pages.push(vec![]);
+            page_length = 0;
         }
+        page_length += length;
+        pages.last_mut().unwrap().push((usage, description));
     }
 
-    let response = poise::CreateReply::default().embed(embed).ephemeral(true);
-
-    ctx.send(response).await?;
+    for page in pages {
+        let mut embed = serenity::CreateEmbed::default()
+            .title(if only_admin { "Owner Only Commands" } else { "Commands" })
+            .author(CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(ctx.data().avatar_url.as_str()).url("https://atcoder-notify.com/"));
+        for (usage, description) in page {
+            embed = embed.field(usage, description, false);
+        }
+        let response = poise::CreateReply::default().embed(embed).ephemeral(true);
+        ctx.send(response).await?;
+    }
 
     Ok(())
 }
@@ -1,15 +1,55 @@
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::BTreeMap;
 
-use crate::{scraping::get_submission::Submission, Context, Error};
+use crate::commands::register_accounts::init_submission_cursor;
+use crate::{scraping::atcoder_identity::resolve_atcoder_user, Context, Error};
 use mysql::prelude::*;
 use mysql::*;
-use poise::serenity_prelude::{self as serenity, CreateEmbedAuthor, RoleId, UserId};
-use serde::{Deserialize, Serialize};
-use serde_json;
+use poise::serenity_prelude::{self as serenity, CreateEmbedAuthor, GuildId, RoleId, UserId};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct UserSubmissionCount {
-    count: i32,
+/// Replace the member's rating role with the one matching the current algorithm rating of `atcoder_user`.
+/// Take the bot's rating roles off a member, such as when the account they had them for is no longer theirs.
+pub async fn remove_rating_roles(http: &serenity::Http, conn: &mut PooledConn, guild: GuildId, discord_id: UserId) -> Result<(), Error> {
+    let role_ids: Vec<u64> = conn.exec(r"SELECT role_id FROM roles WHERE guild_id=:server_id", params! {"server_id" => guild.get()})?;
+    if role_ids.is_empty() {
+        return Ok(());
+    }
+    let member = guild.member(http, discord_id).await?;
+    for i in &member.roles {
+        if role_ids.contains(&i.get()) {
+            member.remove_role(http, *i).await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn assign_rating_role(http: &serenity::Http, conn: &mut PooledConn, guild: GuildId, discord_id: UserId, atcoder_user: &str) -> Result<(), Error> {
+    let roles: Vec<(i8, u64)> = conn.exec(
+        r"SELECT role_color,role_id FROM roles WHERE guild_id=:server_id",
+        params! {"server_id" => guild.get()},
+    )?;
+    if roles.is_empty() {
+        return Ok(());
+    }
+    let roles_map: BTreeMap<i8, u64> = roles.iter().cloned().collect();
+
+    remove_rating_roles(http, conn, guild, discord_id).await?;
+    let member = guild.member(http, discord_id).await?;
+
+    let ratings: Vec<i64> = conn
+        .exec(
+            "SELECT algo_rating FROM atcoder_user_ratings WHERE user_name=:user_name",
+            params! {
+                "user_name" => atcoder_user
+            },
+        )
+        .unwrap();
+    let rating = if ratings.is_empty() { 0 } else { ratings[0] };
+    if rating == 0 {
+        member.add_role(http, RoleId::new(*roles_map.get(&0).unwrap_or(&0))).await?;
+    } else {
+        member.add_role(http, RoleId::new(*roles_map.get(&(std::cmp::min(8, rating / 400 + 1) as i8)).unwrap_or(&0))).await?;
+    }
+    Ok(())
 }
 
 /// Link a specified AtCoder account with the current Discord account.
@@ -61,6 +101,24 @@ pub async fn link_account(
     }
     let discord_user = discord_user.unwrap_or_else(|| ctx.author().clone());
 
+    ctx.defer_ephemeral().await?;
+
+    let Some(identity) = resolve_atcoder_user(&mut conn, &atcoder_user).await? else {
+        let response = {
+            let mut embed = serenity::CreateEmbed::default()
+                .author(CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(ctx.data().avatar_url.as_str()).url("https://atcoder-notify.com/"));
+            if lang == "ja" {
+                embed = embed.title("エラー").description(format!("`{}` というAtCoderユーザーは見つかりませんでした。", atcoder_user));
+            } else {
+                embed = embed.title("Error").description(format!("The AtCoder user `{}` was not found.", atcoder_user));
+            }
+            poise::CreateReply::default().embed(embed).ephemeral(true)
+        };
+        ctx.send(response).await?;
+        return Ok(());
+    };
+    let atcoder_user = identity.screen_name.clone();
+
     let link_accounts: Vec<i32> = conn
         .exec(
             "SELECT id FROM users WHERE discord_id=:discord_id AND server_id=:server_id",
@@ -68,59 +126,33 @@ pub async fn link_account(
         )
         .unwrap();
 
-    ctx.defer_ephemeral().await?;
     if link_accounts.is_empty() {
         conn.exec_drop(
             r"INSERT INTO users (server_id, discord_id, atcoder_username) VALUES (:server_id, :discord_id, :atcoder_username)",
-            params! {"server_id" => &guild_id, "discord_id" => discord_user.id.to_string().parse::<i64>().unwrap(), "atcoder_username" => &atcoder_user.to_lowercase()},
+            params! {"server_id" => &guild_id, "discord_id" => discord_user.id.to_string().parse::<i64>().unwrap(), "atcoder_username" => &identity.user_key},
         )?;
     } else {
         conn.exec_drop(
-            r"UPDATE users SET atcoder_username=:atcoder_username WHERE id=:id",
-            params! {"id" => &link_accounts[0], "atcoder_username" => &atcoder_user.to_lowercase()},
+            r"UPDATE users SET verified=IF(atcoder_username=:atcoder_username, verified, 0), atcoder_username=:atcoder_username WHERE id=:id",
+            params! {"id" => &link_accounts[0], "atcoder_username" => &identity.user_key},
         )?;
     }
 
-    let roles: Vec<(i8, u64)> = conn.exec(
-        r"SELECT role_color,role_id FROM roles WHERE guild_id=:server_id",
+    let settings: Vec<i8> = conn.exec(
+        r"SELECT require_verification FROM server_settings WHERE server_id=:server_id",
         params! {"server_id" => &guild_id},
     )?;
-    if !roles.is_empty() {
-        let roles_map: BTreeMap<i8, u64> = roles.iter().cloned().collect();
-
-        let guild = ctx.guild_id().unwrap();
-        let role_ids: Vec<u64> = roles.iter().map(|x| x.1).collect();
-        let user = UserId::new(discord_user.id.get());
-        let member = guild.member(ctx.http(), user).await?;
-        for i in &member.roles {
-            if role_ids.contains(&i.get()) {
-                let user = UserId::new(discord_user.id.get());
-                let member = guild.member(ctx.http(), user).await?;
-                member.remove_role(ctx.http(), *i).await?;
-            }
-        }
-
-        let ratings: Vec<i64> = conn
-            .exec(
-                "SELECT algo_rating FROM atcoder_user_ratings WHERE user_name=:user_name",
-                params! {
-                    "user_name" => &atcoder_user.to_lowercase()
-                },
-            )
-            .unwrap();
-        let rating = if ratings.is_empty() { 0 } else { ratings[0] };
-        let user = UserId::new(discord_user.id.get());
-        let member = guild.member(ctx.http(), user).await?;
-        if rating == 0 {
-            member.add_role(ctx.http(), RoleId::new(*roles_map.get(&0).unwrap_or(&0))).await?;
-        } else {
-            member
-                .add_role(
-                    ctx.http(),
-                    RoleId::new(*roles_map.get(&(std::cmp::min(8, rating / 400 + 1) as i8)).unwrap_or(&0)),
-                )
-                .await?;
-        }
+    let require_verification = settings.first().is_some_and(|x| *x == 1);
+    let verified: Vec<i8> = conn.exec(
+        "SELECT verified FROM users WHERE discord_id=:discord_id AND server_id=:server_id",
+        params! {"discord_id" => discord_user.id.get(), "server_id" => &guild_id},
+    )?;
+    let verified = verified.first().is_some_and(|x| *x == 1);
+    // The roles of a previously linked account have to go even when the new one cannot have a role yet.
+    if !require_verification || verified {
+        assign_rating_role(ctx.http(), &mut conn, ctx.guild_id().unwrap(), discord_user.id, &atcoder_user).await?;
+    } else {
+        remove_rating_roles(ctx.http(), &mut conn, ctx.guild_id().unwrap(), discord_user.id).await?;
     }
 
     let response = {
@@ -128,59 +160,32 @@ pub async fn link_account(
             .author(CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(ctx.data().avatar_url.as_str()).url("https://atcoder-notify.com/"));
         if lang == "ja" {
             embed = embed.title("設定変更").description(format!("次のアカウントを連携しました。\n<@{}>と`{}`", discord_user.id, atcoder_user));
+            if require_verification && !verified {
+                embed = embed.field(
+                    "認証",
+                    "このサーバーではロールの付与に認証が必要です。`/atcoder verify-account` で認証してください。",
+                    false,
+                );
+            }
         } else {
             embed = embed.title("Settings Changed").description(format!(
                 "The following accounts have been linked. \n<@{}> and `{}`",
                 discord_user.id, atcoder_user
             ));
+            if require_verification && !verified {
+                embed = embed.field(
+                    "Verification",
+                    "This server requires verification before rating roles are given. Please run `/atcoder verify-account`.",
+                    false,
+                );
+            }
         }
         poise::CreateReply::default().embed(embed).ephemeral(true)
     };
 
     ctx.send(response).await?;
 
-    let user_submission: Vec<u64> = conn.exec(
-        r"SELECT epoch_second FROM submissions WHERE username=:username",
-        params! {"username" => &atcoder_user.to_lowercase()},
-    )?;
-    if user_submission.is_empty() {
-        let submission_count_url = format!(
-            "https://kenkoooo.com/atcoder/atcoder-api/v3/user/submission_count?user={}&from_second=0&to_second={}",
-            atcoder_user,
-            chrono::Utc::now().timestamp()
-        );
-        let submission_count_text = reqwest::get(submission_count_url).await?.text().await?;
-        let submission_count: UserSubmissionCount = serde_json::from_str(&submission_count_text)?;
-        let submissions_per_page = 500;
-        let mut last_epoch = 0;
-        let mut submission_set = BTreeSet::new();
-        for _ in 0..((submission_count.count / submissions_per_page) + 1) {
-            let submission_url = format!(
-                "https://kenkoooo.com/atcoder/atcoder-api/v3/user/submissions?user={}&from_second={}",
-                atcoder_user, last_epoch
-            );
-            let submission_text = reqwest::get(submission_url).await?.text().await?;
-            let submission_json: Vec<Submission> = serde_json::from_str(&submission_text)?;
-            for submission in submission_json {
-                submission_set.insert(submission.problem_id);
-                last_epoch = submission.epoch_second.max(last_epoch);
-            }
-            last_epoch += 1;
-        }
-        conn.exec_batch(
-            r"INSERT INTO submission_data (username, problem_id) VALUES (:username, :problem_id)",
-            submission_set.iter().map(|problem_id| {
-                params! {
-                    "user_id" => &atcoder_user.to_lowercase(),
-                    "problem_id" => problem_id,
-                }
-            }),
-        )?;
-        conn.exec_drop(
-            r"INSERT INTO submissions (username, epoch_second) VALUES (:username, :epoch_second)",
-            params! {"username" => &atcoder_user.to_lowercase(), "epoch_second" => last_epoch},
-        )?;
-    }
+    init_submission_cursor(&mut conn, &identity).await?;
 
     Ok(())
 }
@@ -308,7 +313,15 @@ pub async fn show_linked_account(ctx: Context<'_>, #[description = "discord_user
             let user_id = user.id.to_string().parse::<i64>().unwrap();
             let atcoder_user: Vec<String> = conn
                 .exec(
-                    "SELECT atcoder_username FROM users WHERE discord_id=:discord_id AND server_id=:server_id AND discord_id IS NOT NULL",
+                    "SELECT
+                        COALESCE(atcoder_identities.screen_name, users.atcoder_username)
+                    FROM
+                        users
+                    LEFT JOIN
+                        atcoder_identities
+                    ON
+                        atcoder_identities.user_key = users.atcoder_username
+                    WHERE users.discord_id=:discord_id AND users.server_id=:server_id",
                     params! {
                         "discord_id" => user_id,
                         "server_id" => guild_id
@@ -338,7 +351,16 @@ pub async fn show_linked_account(ctx: Context<'_>, #[description = "discord_user
         None => {
             let atcoder_users: Vec<(i64, String)> = conn
                 .exec(
-                    "SELECT discord_id,atcoder_username FROM users WHERE server_id=:server_id AND discord_id IS NOT NULL",
+                    "SELECT
+                        users.discord_id,
+                        COALESCE(atcoder_identities.screen_name, users.atcoder_username)
+                    FROM
+                        users
+                    LEFT JOIN
+                        atcoder_identities
+                    ON
+                        atcoder_identities.user_key = users.atcoder_username
+                    WHERE users.server_id=:server_id AND users.discord_id IS NOT NULL",
                     params! {
                         "server_id" => guild_id
                     },
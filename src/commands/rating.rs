@@ -5,13 +5,23 @@ use mysql::prelude::*;
 use mysql::*;
 use plotters::backend::RGBPixel;
 use plotters::prelude::*;
-use poise::{serenity_prelude::CreateAttachment, CreateReply};
+use poise::{
+    serenity_prelude::{
+        self as serenity, ComponentInteraction, CreateActionRow, CreateAttachment, CreateEmbed, CreateEmbedAuthor, CreateInteractionResponse,
+        CreateInteractionResponseMessage,
+    },
+    BoxFuture, CreateReply,
+};
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use std::{io::Cursor, vec};
 use tera::Tera;
 use tokio::sync::Mutex;
 
+use crate::commands::autocomplete::{autocomplete_atcoder_user, autocomplete_atcoder_user_list};
+use crate::components::{pagination_row, turn_page, ComponentRequest};
 use crate::utils::svg::create_user_rating::Theme;
+use crate::Data;
 use crate::{
     scraping::contest_type::ContestType,
     utils::{svg::create_user_rating::CreateUserRating, svg_to_png::svg_to_png},
@@ -36,14 +46,16 @@ pub async fn rating(_ctx: Context<'_>) -> Result<(), Error> {
 pub async fn now(
     ctx: Context<'_>,
     #[description = "contest_type"] contest_type: AtCoderContestType,
-    #[description = "atcoder_user"] atcoder_user: Option<String>,
+    #[description = "atcoder_user"]
+    #[autocomplete = "autocomplete_atcoder_user"]
+    atcoder_user: Option<String>,
 ) -> Result<(), Error> {
     let pool = ctx.data().conn.lock().await;
     let mut conn = pool.get_conn().unwrap();
     let guild_id = ctx.guild_id().unwrap().get();
     let users: Vec<String> = conn
         .exec(
-            "SELECT atcoder_username FROM users WHERE discord_id=:discord_id AND server_id=:server_id",
+            "SELECT COALESCE(atcoder_identities.screen_name, users.atcoder_username) FROM users LEFT JOIN atcoder_identities ON atcoder_identities.user_key = users.atcoder_username WHERE users.discord_id=:discord_id AND users.server_id=:server_id",
             params! {"discord_id" => ctx.author().id.to_string().parse::<u64>().unwrap(),
             "server_id" => guild_id},
         )
@@ -81,15 +93,8 @@ pub async fn now(
             AtCoderContestType::Algorithm => ContestType::Algorithm,
             AtCoderContestType::Heuristic => ContestType::Heuristic,
         };
-        let pool: &Pool = &pool.clone();
-        let svg_data = CreateUserRating::from_user(&Arc::new(Mutex::new(pool.clone())), atcoder_user, contest_type, 0, 0, Theme::Dark).await;
-        let mut tmpl = Tera::default();
-        tmpl.add_raw_template("user_rating.svg", include_str!("../../static/img/user_rating.svg")).unwrap();
-        let mut ctx = tera::Context::new();
-        ctx.insert("main", &format!("{}{}", &svg_data.circle_svg, &svg_data.text_svg));
-        ctx.insert("gradient", &svg_data.gradient_svg);
         CreateReply::default().attachment(CreateAttachment::bytes(
-            svg_to_png(&tmpl.render("user_rating.svg", &ctx).unwrap_or_default(), 1336, 100, 1.0, 1.0),
+            render_rating_card(&pool, atcoder_user, contest_type).await,
             "rating.png",
         ))
     };
@@ -99,11 +104,117 @@ pub async fn now(
     Ok(())
 }
 
+/// Render the rating card image shown by `rating now` as PNG.
+async fn render_rating_card(pool: &Pool, atcoder_user: String, contest_type: ContestType) -> Vec<u8> {
+    let svg_data = CreateUserRating::from_user(&Arc::new(Mutex::new(pool.clone())), atcoder_user, contest_type, 0, 0, Theme::Dark).await;
+    let mut tmpl = Tera::default();
+    tmpl.add_raw_template("user_rating.svg", include_str!("../../static/img/user_rating.svg")).unwrap();
+    let mut ctx = tera::Context::new();
+    ctx.insert("main", &format!("{}{}", &svg_data.circle_svg, &svg_data.text_svg));
+    ctx.insert("gradient", &svg_data.gradient_svg);
+    svg_to_png(&tmpl.render("user_rating.svg", &ctx).unwrap_or_default(), 1336, 100, 1.0, 1.0)
+}
+
+/// Display the AtCoder ratings and recent performances of a server member.
+#[poise::command(context_menu_command = "AtCoder profile", guild_only)]
+pub async fn atcoder_profile(ctx: Context<'_>, user: serenity::User) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+    let pool = ctx.data().conn.lock().await;
+    let mut conn = pool.get_conn().unwrap();
+    let guild_id = ctx.guild_id().unwrap().get();
+
+    let selected_data: Vec<String> = conn.exec(
+        r"SELECT language FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => guild_id},
+    )?;
+    let mut lang = "ja";
+    if selected_data.len() == 1 {
+        lang = selected_data[0].as_str();
+    }
+
+    let users: Vec<String> = conn.exec(
+        "SELECT COALESCE(atcoder_identities.screen_name, users.atcoder_username) FROM users LEFT JOIN atcoder_identities ON atcoder_identities.user_key = users.atcoder_username WHERE users.discord_id=:discord_id AND users.server_id=:server_id",
+        params! {"discord_id" => user.id.get(), "server_id" => guild_id},
+    )?;
+    let Some(atcoder_user) = users.into_iter().next() else {
+        let response = if lang == "ja" {
+            CreateReply::default().content(format!("<@{}> はAtCoderアカウントを連携していません。", user.id)).ephemeral(true)
+        } else {
+            CreateReply::default().content(format!("<@{}> has not linked an AtCoder account.", user.id)).ephemeral(true)
+        };
+        ctx.send(response).await?;
+        return Ok(());
+    };
+
+    let performances: Vec<(String, i32, i32, i8)> = conn.exec(
+        "SELECT
+            contests.name,
+            user_ratings.performance,
+            user_ratings.rating,
+            user_ratings.type
+        FROM
+            user_ratings
+        JOIN
+            contests
+        ON
+            contests.contest_id = user_ratings.contest
+        WHERE user_ratings.user_name=:user_name
+        ORDER BY contests.start_at DESC
+        LIMIT 5",
+        params! {"user_name" => &atcoder_user},
+    )?;
+
+    let algorithm_card = render_rating_card(&pool, atcoder_user.clone(), ContestType::Algorithm).await;
+    let heuristic_card = render_rating_card(&pool, atcoder_user.clone(), ContestType::Heuristic).await;
+
+    let mut embed = CreateEmbed::default()
+        .author(CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(ctx.data().avatar_url.as_str()).url("https://atcoder-notify.com/"))
+        .title(&atcoder_user)
+        .url(format!("https://atcoder.jp/users/{}", atcoder_user));
+    let recent = performances
+        .iter()
+        .map(|(name, performance, rating, contest_type)| {
+            format!(
+                "{} {}: perf {} / rating {}",
+                if *contest_type == 0 { "🟦" } else { "🟧" },
+                name,
+                performance,
+                rating
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    if lang == "ja" {
+        embed = embed.description(format!("<@{}> のAtCoderアカウント", user.id)).field(
+            "最近のパフォーマンス",
+            if recent.is_empty() { "記録がありません".to_string() } else { recent },
+            false,
+        );
+    } else {
+        embed = embed.description(format!("AtCoder account of <@{}>", user.id)).field(
+            "Recent performances",
+            if recent.is_empty() { "No contests yet".to_string() } else { recent },
+            false,
+        );
+    }
+
+    let response = CreateReply::default()
+        .embed(embed)
+        .attachment(CreateAttachment::bytes(algorithm_card, "algorithm_rating.png"))
+        .attachment(CreateAttachment::bytes(heuristic_card, "heuristic_rating.png"))
+        .ephemeral(true);
+    ctx.send(response).await?;
+
+    Ok(())
+}
+
 /// Show the rating history for a specified AtCoder user.
 #[poise::command(prefix_command, slash_command, rename = "history")]
 pub async fn rating_history(
     ctx: Context<'_>,
-    #[description = "atcoder_user_list"] atcoder_user_list: Option<String>,
+    #[description = "atcoder_user_list"]
+    #[autocomplete = "autocomplete_atcoder_user_list"]
+    atcoder_user_list: Option<String>,
     #[description = "contest_type"] contest_type: AtCoderContestType,
 ) -> Result<(), Error> {
     ctx.defer_ephemeral().await?;
@@ -112,13 +223,13 @@ pub async fn rating_history(
     let guild_id = ctx.guild_id().unwrap().get();
     let users: Vec<String> = if atcoder_user_list.clone().unwrap_or("".to_string()) == "all" {
         conn.exec(
-            "SELECT atcoder_username FROM users WHERE server_id=:server_id",
+            "SELECT COALESCE(atcoder_identities.screen_name, users.atcoder_username) FROM users LEFT JOIN atcoder_identities ON atcoder_identities.user_key = users.atcoder_username WHERE users.server_id=:server_id",
             params! {"server_id" => guild_id},
         )
         .unwrap()
     } else {
         conn.exec(
-            "SELECT atcoder_username FROM users WHERE discord_id=:discord_id AND server_id=:server_id",
+            "SELECT COALESCE(atcoder_identities.screen_name, users.atcoder_username) FROM users LEFT JOIN atcoder_identities ON atcoder_identities.user_key = users.atcoder_username WHERE users.discord_id=:discord_id AND users.server_id=:server_id",
             params! {"discord_id" => ctx.author().id.to_string().parse::<u64>().unwrap(),
             "server_id" => guild_id},
         )
@@ -157,6 +268,35 @@ pub async fn rating_history(
         }
         None => users[0].clone(),
     };
+    let contest_type: ContestType = match contest_type {
+        AtCoderContestType::Algorithm => ContestType::Algorithm,
+        AtCoderContestType::Heuristic => ContestType::Heuristic,
+    };
+    let user_list: Vec<String> = atcoder_user_list.split(',').map(|x| x.to_string()).collect();
+    let state = RatingHistoryState {
+        users: user_list,
+        contest_type: contest_type as i8,
+        page: 0,
+    };
+    let response = if state.users.len() > HISTORY_USERS_PER_PAGE {
+        let state_id = ctx.data().component_states.create(ctx.author().id, &state);
+        let (components, attachment) = rating_history_page(&mut conn, &state, state_id)?;
+        CreateReply::default().attachment(attachment).components(components)
+    } else {
+        let users: Vec<&str> = state.users.iter().map(|x| x.as_str()).collect();
+        CreateReply::default().attachment(CreateAttachment::bytes(
+            render_rating_history(&mut conn, &users, state.contest_type)?,
+            "history.png",
+        ))
+    };
+
+    ctx.send(response).await?;
+
+    Ok(())
+}
+
+/// Draw the rating history chart of `user_list` as PNG.
+fn render_rating_history(conn: &mut PooledConn, user_list: &[&str], contest_type: i8) -> Result<Vec<u8>, Error> {
     let bg_colors = [
         RGBColor(216, 216, 216),
         RGBColor(216, 197, 178),
@@ -178,184 +318,209 @@ pub async fn rating_history(
         RGBColor(255, 0, 0),
     ];
 
-    let response = {
-        let contest_type: ContestType = match contest_type {
-            AtCoderContestType::Algorithm => ContestType::Algorithm,
-            AtCoderContestType::Heuristic => ContestType::Heuristic,
-        };
-        let contest_type = contest_type as i8;
-        let image_width = 1280;
-        let image_height = 720;
-        let mut buffer: Vec<u8> = vec![0; image_width * image_height * 3];
-        {
-            let root = BitMapBackend::<RGBPixel>::with_buffer(&mut buffer, (image_width as u32, image_height as u32)).into_drawing_area();
-
-            root.fill(&WHITE)?;
-
-            let mut y_min = 99999999;
-            let mut y_max = 0;
-            let mut x_min = chrono::Utc::now().into();
-            let x_max = chrono::DateTime::parse_from_str("2015-04-11 21:00:00+0900", "%Y-%m-%d %H:%M:%S%z");
-            let mut x_max = x_max.unwrap();
-
-            let caption = "Rating History";
-            let font = ("Lato", 40);
-
-            let mut point_series_vec = vec![];
-            let mut line_series_vec = vec![];
-
-            let user_list: Vec<&str> = atcoder_user_list.split(',').collect();
-            for (idx, atcoder_user) in user_list.clone().into_iter().enumerate() {
-                let atcoder_rating: Vec<(i32, String, i32)> = conn
-                    .exec(
-                        "SELECT
-                            user_ratings.rating,
-                            contests.start_time,
-                            contests.duration
-                        FROM
-                            user_ratings
-                        JOIN
-                            contests
-                        ON
-                            contests.contest_id = user_ratings.contest
-                        WHERE user_ratings.user_name=:atcoder_id and user_ratings.type=:contest_type",
-                        params! {"atcoder_id" => atcoder_user, "contest_type" => contest_type},
-                    )
-                    .unwrap();
-                let mut xs = vec![];
-                let mut ys = vec![];
-                for i in atcoder_rating {
-                    let start_time = chrono::DateTime::parse_from_str(&i.1, "%Y-%m-%d %H:%M:%S%z").unwrap();
-                    let offset = chrono::Duration::minutes(i.2 as i64);
-                    xs.push(start_time + offset);
-                    ys.push(i.0);
-                }
-
-                let (y_min_temp, y_max_temp) = ys.iter().fold((ys[0], ys[0]), |(m, n), v| (std::cmp::min(*v, m), std::cmp::max(*v, n)));
-                let y_min_temp = y_min_temp / 400 * 400;
-                let y_min_temp = std::cmp::max(0, y_min_temp - 50);
-                let y_max_temp = y_max_temp / 400 * 400 + 450;
-                let x_min_temp = *xs.first().unwrap() - (*xs.last().unwrap() - *xs.first().unwrap()) / 20;
-                let x_max_temp = *xs.last().unwrap() + (*xs.last().unwrap() - *xs.first().unwrap()) / 20;
-
-                y_min = std::cmp::min(y_min, y_min_temp);
-                y_max = std::cmp::max(y_max, y_max_temp);
-                x_min = std::cmp::min(x_min, x_min_temp);
-                x_max = std::cmp::max(x_max, x_max_temp);
-                if user_list.len() == 1 {
-                    let point_series = xs
-                        .iter()
-                        .zip(ys.iter())
-                        .map(|(x, y)| {
-                            EmptyElement::at((*x, *y))
-                                + Circle::new(
-                                    (0, 0),
-                                    4,
-                                    ShapeStyle::from(&circle_bg_colors[std::cmp::min(y / 400, (circle_bg_colors.len() - 1) as i32) as usize]).filled(),
-                                )
-                                + Circle::new((0, 0), 4, GREY_400.stroke_width(1))
-                        })
-                        .collect::<Vec<_>>();
-                    point_series_vec.push(point_series);
-                } else {
-                    let point_series = xs
-                        .iter()
-                        .zip(ys.iter())
-                        .map(|(x, y)| {
-                            EmptyElement::at((*x, *y))
-                                + Circle::new((0, 0), 4, HSLColor(idx as f64 / user_list.len() as f64, 1.0, 0.5).filled())
-                                + Circle::new((0, 0), 4, TRANSPARENT)
-                        })
-                        .collect::<Vec<_>>();
-                    point_series_vec.push(point_series);
-                };
-                if user_list.len() == 1 {
-                    let line_series = LineSeries::new(xs.iter().zip(ys.iter()).map(|(x, y)| (*x, *y)), GREY_800);
-                    line_series_vec.push(line_series);
-                } else {
-                    let line_series = LineSeries::new(
-                        xs.iter().zip(ys.iter()).map(|(x, y)| (*x, *y)),
-                        HSLColor(idx as f64 / user_list.len() as f64, 1.0, 0.5),
-                    );
-                    line_series_vec.push(line_series);
-                };
-            }
+    let image_width = 1280;
+    let image_height = 720;
+    let mut buffer: Vec<u8> = vec![0; image_width * image_height * 3];
+    {
+        let root = BitMapBackend::<RGBPixel>::with_buffer(&mut buffer, (image_width as u32, image_height as u32)).into_drawing_area();
+
+        root.fill(&WHITE)?;
 
-            let mut chart = ChartBuilder::on(&root)
-                .caption(caption, font.into_font())
-                .margin(40)
-                .x_label_area_size(32)
-                .y_label_area_size(84)
-                .build_cartesian_2d(x_min..x_max, y_min..y_max)?;
-            chart.configure_mesh().x_label_formatter(&|x: &DateTime<FixedOffset>| x.format("%Y/%m/%d").to_string()).draw()?;
-
-            chart.draw_series((0..8).map(|index: i32| {
-                Rectangle::new(
-                    [
-                        (x_min - (x_max - x_min) / 20, 400 * index),
-                        (
-                            x_max + (x_max - x_min) / 20,
-                            match index {
-                                7 => 30000,
-                                _ => 400 * index + 400,
-                            },
-                        ),
-                    ],
-                    ShapeStyle::from(&bg_colors[index as usize]).filled(),
+        let mut y_min = 99999999;
+        let mut y_max = 0;
+        let mut x_min = chrono::Utc::now().into();
+        let x_max = chrono::DateTime::parse_from_str("2015-04-11 21:00:00+0900", "%Y-%m-%d %H:%M:%S%z");
+        let mut x_max = x_max.unwrap();
+
+        let caption = "Rating History";
+        let font = ("Lato", 40);
+
+        let mut point_series_vec = vec![];
+        let mut line_series_vec = vec![];
+
+        for (idx, atcoder_user) in user_list.iter().copied().enumerate() {
+            let atcoder_rating: Vec<(i32, chrono::NaiveDateTime)> = conn
+                .exec(
+                    "SELECT
+                        user_ratings.rating,
+                        contests.end_at
+                    FROM
+                        user_ratings
+                    JOIN
+                        contests
+                    ON
+                        contests.contest_id = user_ratings.contest
+                    WHERE user_ratings.user_name=:atcoder_id and user_ratings.type=:contest_type
+                    ORDER BY contests.end_at",
+                    params! {"atcoder_id" => atcoder_user, "contest_type" => contest_type},
                 )
-            }))?;
-            for i in 0..100 {
-                chart.draw_series(LineSeries::new(
-                    [(x_min - (x_max - x_min) / 20, 400 * i), (x_max + (x_max - x_min) / 20, 400 * i)],
-                    WHITE,
-                ))?;
-            }
-            let user_list: Vec<&str> = atcoder_user_list.split(',').collect();
-            let length = line_series_vec.len();
-            for (idx, data) in line_series_vec.into_iter().enumerate() {
-                let idx_clone = idx;
-                let user_name = user_list[idx];
-                let user_list_clone = user_list.clone();
-                if length == 1 {
-                    chart.draw_series(data)?.label(user_name).legend(move |(x, y)| {
-                        PathElement::new(
-                            vec![(x, y), (x + 20, y)],
-                            GREY_800, // Use the cloned user_list
-                        )
-                    });
-                } else {
-                    chart.draw_series(data)?.label(user_name).legend(move |(x, y)| {
-                        PathElement::new(
-                            vec![(x, y), (x + 20, y)],
-                            HSLColor(idx_clone as f64 / user_list_clone.len() as f64, 1.0, 0.5), // Use the cloned user_list
-                        )
-                    });
-                }
-            }
-            for i in point_series_vec {
-                chart.draw_series(i)?;
-            }
-            chart
-                .configure_series_labels()
-                .position(SeriesLabelPosition::UpperLeft)
-                .border_style(BLACK)
-                .background_style(WHITE.mix(0.8))
-                .label_font(("Lato", 20))
-                .draw()
                 .unwrap();
+            let mut xs = vec![];
+            let mut ys = vec![];
+            for i in atcoder_rating {
+                xs.push(i.1.and_utc().fixed_offset());
+                ys.push(i.0);
+            }
+
+            let (y_min_temp, y_max_temp) = ys.iter().fold((ys[0], ys[0]), |(m, n), v| (std::cmp::min(*v, m), std::cmp::max(*v, n)));
+            let y_min_temp = y_min_temp / 400 * 400;
+            let y_min_temp = std::cmp::max(0, y_min_temp - 50);
+            let y_max_temp = y_max_temp / 400 * 400 + 450;
+            let x_min_temp = *xs.first().unwrap() - (*xs.last().unwrap() - *xs.first().unwrap()) / 20;
+            let x_max_temp = *xs.last().unwrap() + (*xs.last().unwrap() - *xs.first().unwrap()) / 20;
+
+            y_min = std::cmp::min(y_min, y_min_temp);
+            y_max = std::cmp::max(y_max, y_max_temp);
+            x_min = std::cmp::min(x_min, x_min_temp);
+            x_max = std::cmp::max(x_max, x_max_temp);
+            if user_list.len() == 1 {
+                let point_series = xs
+                    .iter()
+                    .zip(ys.iter())
+                    .map(|(x, y)| {
+                        EmptyElement::at((*x, *y))
+                            + Circle::new(
+                                (0, 0),
+                                4,
+                                ShapeStyle::from(&circle_bg_colors[std::cmp::min(y / 400, (circle_bg_colors.len() - 1) as i32) as usize]).filled(),
+                            )
+                            + Circle::new((0, 0), 4, GREY_400.stroke_width(1))
+                    })
+                    .collect::<Vec<_>>();
+                point_series_vec.push(point_series);
+            } else {
+                let point_series = xs
+                    .iter()
+                    .zip(ys.iter())
+                    .map(|(x, y)| {
+                        EmptyElement::at((*x, *y))
+                            + Circle::new((0, 0), 4, HSLColor(idx as f64 / user_list.len() as f64, 1.0, 0.5).filled())
+                            + Circle::new((0, 0), 4, TRANSPARENT)
+                    })
+                    .collect::<Vec<_>>();
+                point_series_vec.push(point_series);
+            };
+            if user_list.len() == 1 {
+                let line_series = LineSeries::new(xs.iter().zip(ys.iter()).map(|(x, y)| (*x, *y)), GREY_800);
+                line_series_vec.push(line_series);
+            } else {
+                let line_series = LineSeries::new(
+                    xs.iter().zip(ys.iter()).map(|(x, y)| (*x, *y)),
+                    HSLColor(idx as f64 / user_list.len() as f64, 1.0, 0.5),
+                );
+                line_series_vec.push(line_series);
+            };
         }
 
-        let img: RgbImage = ImageBuffer::from_raw(image_width as u32, image_height as u32, buffer).expect("Failed to create image buffer");
+        let mut chart = ChartBuilder::on(&root)
+            .caption(caption, font.into_font())
+            .margin(40)
+            .x_label_area_size(32)
+            .y_label_area_size(84)
+            .build_cartesian_2d(x_min..x_max, y_min..y_max)?;
+        chart.configure_mesh().x_label_formatter(&|x: &DateTime<FixedOffset>| x.format("%Y/%m/%d").to_string()).draw()?;
 
-        let mut png_data = Vec::new();
-        {
-            let mut cursor = Cursor::new(&mut png_data);
-            img.write_to(&mut cursor, image::ImageFormat::Png)?;
+        chart.draw_series((0..8).map(|index: i32| {
+            Rectangle::new(
+                [
+                    (x_min - (x_max - x_min) / 20, 400 * index),
+                    (
+                        x_max + (x_max - x_min) / 20,
+                        match index {
+                            7 => 30000,
+                            _ => 400 * index + 400,
+                        },
+                    ),
+                ],
+                ShapeStyle::from(&bg_colors[index as usize]).filled(),
+            )
+        }))?;
+        for i in 0..100 {
+            chart.draw_series(LineSeries::new(
+                [(x_min - (x_max - x_min) / 20, 400 * i), (x_max + (x_max - x_min) / 20, 400 * i)],
+                WHITE,
+            ))?;
         }
-        CreateReply::default().attachment(CreateAttachment::bytes(png_data, "history.png"))
-    };
+        let length = line_series_vec.len();
+        for (idx, data) in line_series_vec.into_iter().enumerate() {
+            let idx_clone = idx;
+            let user_name = user_list[idx];
+            let user_count = user_list.len();
+            if length == 1 {
+                chart.draw_series(data)?.label(user_name).legend(move |(x, y)| {
+                    PathElement::new(
+                        vec![(x, y), (x + 20, y)],
+                        GREY_800, // Use the cloned user_list
+                    )
+                });
+            } else {
+                chart
+                    .draw_series(data)?
+                    .label(user_name)
+                    .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], HSLColor(idx_clone as f64 / user_count as f64, 1.0, 0.5)));
+            }
+        }
+        for i in point_series_vec {
+            chart.draw_series(i)?;
+        }
+        chart
+            .configure_series_labels()
+            .position(SeriesLabelPosition::UpperLeft)
+            .border_style(BLACK)
+            .background_style(WHITE.mix(0.8))
+            .label_font(("Lato", 20))
+            .draw()
+            .unwrap();
+    }
 
-    ctx.send(response).await?;
+    let img: RgbImage = ImageBuffer::from_raw(image_width as u32, image_height as u32, buffer).expect("Failed to create image buffer");
 
-    Ok(())
+    let mut png_data = Vec::new();
+    {
+        let mut cursor = Cursor::new(&mut png_data);
+        img.write_to(&mut cursor, image::ImageFormat::Png)?;
+    }
+    Ok(png_data)
+}
+
+const HISTORY_USERS_PER_PAGE: usize = 8;
+
+#[derive(Serialize, Deserialize)]
+struct RatingHistoryState {
+    users: Vec<String>,
+    contest_type: i8,
+    page: usize,
+}
+
+fn rating_history_page(conn: &mut PooledConn, state: &RatingHistoryState, state_id: u64) -> Result<(Vec<CreateActionRow>, CreateAttachment), Error> {
+    let page_count = state.users.len().div_ceil(HISTORY_USERS_PER_PAGE);
+    let users: Vec<&str> = state.users.iter().skip(state.page * HISTORY_USERS_PER_PAGE).take(HISTORY_USERS_PER_PAGE).map(|x| x.as_str()).collect();
+    let attachment = CreateAttachment::bytes(render_rating_history(conn, &users, state.contest_type)?, "history.png");
+    Ok((vec![pagination_row("history", state_id, state.page, page_count)], attachment))
+}
+
+/// Page through `/atcoder rating history all` when the server has many users.
+pub fn handle_history_component<'a>(
+    ctx: &'a serenity::Context,
+    data: &'a Data,
+    interaction: &'a ComponentInteraction,
+    request: ComponentRequest<'a>,
+) -> BoxFuture<'a, Result<(), Error>> {
+    Box::pin(async move {
+        let Some(state) = request.state else {
+            return Ok(());
+        };
+        let Some(mut history) = state.data::<RatingHistoryState>() else {
+            return Ok(());
+        };
+        history.page = turn_page(history.page, request.action, history.users.len().div_ceil(HISTORY_USERS_PER_PAGE));
+        data.component_states.update(state.id, &history);
+
+        let pool = data.conn.lock().await.clone();
+        let mut conn = pool.get_conn()?;
+        let (components, attachment) = rating_history_page(&mut conn, &history, state.id)?;
+        let reply = CreateInteractionResponse::UpdateMessage(CreateInteractionResponseMessage::default().add_file(attachment).components(components));
+        interaction.create_response(ctx, reply).await?;
+        Ok(())
+    })
 }
@@ -1,11 +1,24 @@
 use std::collections::BTreeSet;
 
-use crate::{scraping::get_submission::Submission, Context, Error};
+use crate::commands::autocomplete::autocomplete_atcoder_user;
+use crate::commands::link_accounts::assign_rating_role;
+use crate::{
+    scraping::{
+        atcoder_identity::{is_valid_username, resolve_atcoder_user, user_key, AtCoderIdentity},
+        get_submission::Submission,
+    },
+    Context, Error,
+};
 use mysql::prelude::*;
 use mysql::*;
-use poise::serenity_prelude::{self as serenity, CreateEmbedAuthor};
+use poise::{
+    serenity_prelude::{self as serenity, CreateEmbed, CreateEmbedAuthor, UserId},
+    CreateReply,
+};
 use serde::{Deserialize, Serialize};
 use serde_json;
+use std::time::Duration;
+use tokio::time::sleep;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[allow(non_snake_case)]
@@ -28,16 +41,32 @@ pub async fn register_account(ctx: Context<'_>, #[description = "atcoder_usernam
         lang = selected_data[0].as_str();
     }
 
+    let Some(identity) = resolve_atcoder_user(&mut conn, &atcoder_user).await? else {
+        let response = {
+            let mut embed = serenity::CreateEmbed::default()
+                .author(CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(ctx.data().avatar_url.as_str()).url("https://atcoder-notify.com/"));
+            if lang == "ja" {
+                embed = embed.title("エラー").description(format!("`{}` というAtCoderユーザーは見つかりませんでした。", atcoder_user));
+            } else {
+                embed = embed.title("Error").description(format!("The AtCoder user `{}` was not found.", atcoder_user));
+            }
+            poise::CreateReply::default().embed(embed).ephemeral(true)
+        };
+        ctx.send(response).await?;
+        return Ok(());
+    };
+    let atcoder_user = identity.screen_name.clone();
+
     let link_accounts: Vec<i32> = conn
         .exec(
             "SELECT id FROM users WHERE atcoder_username=:atcoder_username AND server_id=:server_id AND discord_id IS NULL",
-            params! {"atcoder_username" => &atcoder_user.to_lowercase(),"server_id" => &guild_id},
+            params! {"atcoder_username" => &identity.user_key,"server_id" => &guild_id},
         )
         .unwrap();
     if link_accounts.is_empty() {
         conn.exec_drop(
             r"INSERT INTO users (server_id, atcoder_username) VALUES (:server_id, :atcoder_username)",
-            params! {"server_id" => &guild_id, "atcoder_username" => &atcoder_user.to_lowercase()},
+            params! {"server_id" => &guild_id, "atcoder_username" => &identity.user_key},
         )?;
 
         let response = {
@@ -67,14 +96,281 @@ pub async fn register_account(ctx: Context<'_>, #[description = "atcoder_usernam
         ctx.send(response).await?;
     }
 
+    init_submission_cursor(&mut conn, &identity).await?;
+
+    Ok(())
+}
+
+/// Largest attachment, in bytes, accepted by `register-accounts`.
+const MAX_ACCOUNT_LIST_SIZE: u32 = 256 * 1024;
+/// Largest number of rows accepted by `register-accounts` in one call.
+const MAX_ACCOUNT_LIST_ROWS: usize = 200;
+
+struct AccountRow {
+    line: usize,
+    atcoder_user: String,
+    discord_id: Option<u64>,
+}
+
+/// Read one account per line: an AtCoder username, optionally followed by a Discord user ID or mention,
+/// separated by a comma, tab or spaces. Blank lines, `#` comments and a header row are skipped.
+/// Returns the rows that could be read and the line numbers of those that could not.
+fn parse_account_list(text: &str) -> (Vec<AccountRow>, Vec<usize>) {
+    let mut rows = vec![];
+    let mut invalid = vec![];
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim().trim_start_matches('\u{feff}');
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(|c: char| c == ',' || c == ';' || c.is_whitespace()).filter(|x| !x.is_empty()).collect();
+        if fields.is_empty() {
+            invalid.push(index + 1);
+            continue;
+        }
+        if index == 0 && ["atcoder", "atcoder_username", "atcoder_user", "username"].contains(&fields[0].to_lowercase().as_str()) {
+            continue;
+        }
+        let discord_id = match fields.get(1) {
+            Some(field) => match field.trim_start_matches("<@").trim_start_matches('!').trim_end_matches('>').parse::<u64>() {
+                Ok(discord_id) => Some(discord_id),
+                Err(_) => {
+                    invalid.push(index + 1);
+                    continue;
+                }
+            },
+            None => None,
+        };
+        if fields.len() > 2 || !is_valid_username(fields[0]) {
+            invalid.push(index + 1);
+            continue;
+        }
+        rows.push(AccountRow {
+            line: index + 1,
+            atcoder_user: fields[0].to_string(),
+            discord_id,
+        });
+    }
+    (rows, invalid)
+}
+
+/// Join `items` one per line, cut to fit in an embed field.
+fn summary_field(items: &[String]) -> String {
+    let mut value = String::new();
+    for (index, item) in items.iter().enumerate() {
+        if value.len() + item.len() + 1 > 1000 {
+            value += &format!("… (+{})", items.len() - index);
+            break;
+        }
+        value += item;
+        value += "\n";
+    }
+    value
+}
+
+/// Register AtCoder accounts in bulk from a CSV or text attachment.
+#[poise::command(prefix_command, slash_command, rename = "register-accounts")]
+pub async fn register_accounts(ctx: Context<'_>, #[description = "file"] file: serenity::Attachment) -> Result<(), Error> {
+    // Resolving many accounts takes a while, so the shared lock is only held to clone the pool.
+    let pool = ctx.data().conn.lock().await.clone();
+    let mut conn = pool.get_conn().unwrap();
+    let guild_id = ctx.guild_id().unwrap().get();
+    let selected_data: Vec<String> = conn.exec(
+        r"SELECT language FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => &guild_id},
+    )?;
+    let mut lang = "ja";
+    if selected_data.len() == 1 {
+        lang = selected_data[0].as_str();
+    }
+
+    let owners: Vec<u64> = conn
+        .exec(
+            "SELECT user_id FROM owners WHERE guild_id=:guild_id",
+            params! {
+                "guild_id" => ctx.guild_id().unwrap_or_default().get()
+            },
+        )
+        .unwrap();
+    let has_permission = if owners.is_empty() || owners.contains(&{ ctx.author().id.get() }) {
+        true
+    } else {
+        ctx.author().id.get() == ctx.guild().unwrap().owner_id.get()
+    };
+    if !has_permission {
+        if lang == "ja" {
+            let response = CreateReply::default().embed(CreateEmbed::default().title("エラー").description("権限がありません。")).ephemeral(true);
+            ctx.send(response).await?;
+        } else {
+            let response = CreateReply::default().embed(CreateEmbed::default().title("Error").description("You do not have permission.")).ephemeral(true);
+            ctx.send(response).await?;
+        }
+        return Ok(());
+    }
+
+    let rows = if file.size <= MAX_ACCOUNT_LIST_SIZE {
+        let text = String::from_utf8_lossy(&file.download().await?).to_string();
+        Some(parse_account_list(&text))
+    } else {
+        None
+    };
+    let Some((rows, invalid_lines)) = rows.filter(|(rows, _)| rows.len() <= MAX_ACCOUNT_LIST_ROWS) else {
+        let response = {
+            let mut embed = serenity::CreateEmbed::default()
+                .author(CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(ctx.data().avatar_url.as_str()).url("https://atcoder-notify.com/"));
+            if lang == "ja" {
+                embed = embed.title("エラー").description(format!("一度に登録できるのは{}件までです。", MAX_ACCOUNT_LIST_ROWS));
+            } else {
+                embed = embed.title("Error").description(format!("Up to {} accounts can be registered at once.", MAX_ACCOUNT_LIST_ROWS));
+            }
+            poise::CreateReply::default().embed(embed).ephemeral(true)
+        };
+        ctx.send(response).await?;
+        return Ok(());
+    };
+
+    ctx.defer_ephemeral().await?;
+
+    let settings: Vec<i8> = conn.exec(
+        r"SELECT require_verification FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => &guild_id},
+    )?;
+    let require_verification = settings.first().is_some_and(|x| *x == 1);
+
+    let mut added = vec![];
+    let mut linked = vec![];
+    let mut duplicates = vec![];
+    let mut unknown = vec![];
+    let mut not_members = vec![];
+    let mut role_failures = vec![];
+    let invalid: Vec<String> = invalid_lines.iter().map(|line| format!("L{}", line)).collect();
+    let mut new_identities: Vec<AtCoderIdentity> = vec![];
+    let mut seen_users = BTreeSet::new();
+    let mut seen_discord_ids = BTreeSet::new();
+
+    for (index, row) in rows.into_iter().enumerate() {
+        if !seen_users.insert((user_key(&row.atcoder_user), row.discord_id.is_some())) || row.discord_id.is_some_and(|id| !seen_discord_ids.insert(id)) {
+            duplicates.push(format!("L{} `{}`", row.line, row.atcoder_user));
+            continue;
+        }
+        if let Some(discord_id) = row.discord_id {
+            if ctx.guild_id().unwrap().member(ctx.http(), UserId::new(discord_id)).await.is_err() {
+                not_members.push(format!("L{} <@{}>", row.line, discord_id));
+                continue;
+            }
+        }
+        if index > 0 {
+            sleep(Duration::from_millis(500)).await;
+        }
+        let Some(identity) = resolve_atcoder_user(&mut conn, &row.atcoder_user).await? else {
+            unknown.push(format!("L{} `{}`", row.line, row.atcoder_user));
+            continue;
+        };
+
+        match row.discord_id {
+            Some(discord_id) => {
+                let link_accounts: Vec<i32> = conn.exec(
+                    "SELECT id FROM users WHERE discord_id=:discord_id AND server_id=:server_id",
+                    params! {"discord_id" => discord_id, "server_id" => &guild_id},
+                )?;
+                if !link_accounts.is_empty() {
+                    duplicates.push(format!("L{} <@{}>", row.line, discord_id));
+                    continue;
+                }
+                conn.exec_drop(
+                    r"INSERT INTO users (server_id, discord_id, atcoder_username) VALUES (:server_id, :discord_id, :atcoder_username)",
+                    params! {"server_id" => &guild_id, "discord_id" => discord_id, "atcoder_username" => &identity.user_key},
+                )?;
+                if !require_verification {
+                    let result = assign_rating_role(ctx.http(), &mut conn, ctx.guild_id().unwrap(), UserId::new(discord_id), &identity.screen_name).await;
+                    if let Err(err) = result {
+                        log::warn!("could not assign a rating role to {}: {}", discord_id, err);
+                        role_failures.push(format!("<@{}> `{}`", discord_id, identity.screen_name));
+                    }
+                }
+                linked.push(format!("<@{}> `{}`", discord_id, identity.screen_name));
+            }
+            None => {
+                let registered: Vec<i32> = conn.exec(
+                    "SELECT id FROM users WHERE atcoder_username=:atcoder_username AND server_id=:server_id AND discord_id IS NULL",
+                    params! {"atcoder_username" => &identity.user_key, "server_id" => &guild_id},
+                )?;
+                if !registered.is_empty() {
+                    duplicates.push(format!("L{} `{}`", row.line, identity.screen_name));
+                    continue;
+                }
+                conn.exec_drop(
+                    r"INSERT INTO users (server_id, atcoder_username) VALUES (:server_id, :atcoder_username)",
+                    params! {"server_id" => &guild_id, "atcoder_username" => &identity.user_key},
+                )?;
+                added.push(format!("`{}`", identity.screen_name));
+            }
+        }
+        if new_identities.iter().all(|x| x.user_key != identity.user_key) {
+            new_identities.push(identity);
+        }
+    }
+
+    // Fetching past submissions can take a while for many accounts, so it runs after replying.
+    let background_pool = pool.clone();
+    tokio::spawn(async move {
+        let mut conn = background_pool.get_conn().unwrap();
+        for identity in new_identities {
+            if let Err(err) = init_submission_cursor(&mut conn, &identity).await {
+                log::error!("failed to initialize submissions of {}: {}", identity.screen_name, err);
+            }
+        }
+    });
+
+    let response = {
+        let mut embed = serenity::CreateEmbed::default()
+            .author(CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(ctx.data().avatar_url.as_str()).url("https://atcoder-notify.com/"));
+        let titles = if lang == "ja" {
+            embed = embed.title("一括登録").description(format!("{}件を登録、{}件を連携しました。", added.len(), linked.len()));
+            [
+                "登録",
+                "連携",
+                "重複・登録済み",
+                "存在しないユーザー",
+                "サーバーにいないユーザー",
+                "ロールを付与できなかったユーザー",
+                "読み取れない行",
+            ]
+        } else {
+            embed = embed.title("Bulk Registration").description(format!("Registered {} and linked {} accounts.", added.len(), linked.len()));
+            [
+                "Registered",
+                "Linked",
+                "Duplicates / already added",
+                "Unknown users",
+                "Not in this server",
+                "Rating role not given",
+                "Unreadable lines",
+            ]
+        };
+        for (title, items) in titles.iter().zip([&added, &linked, &duplicates, &unknown, &not_members, &role_failures, &invalid]) {
+            if !items.is_empty() {
+                embed = embed.field(format!("{} ({})", title, items.len()), summary_field(items), false);
+            }
+        }
+        poise::CreateReply::default().embed(embed).ephemeral(true)
+    };
+    ctx.send(response).await?;
+
+    Ok(())
+}
+
+/// Record the problems `identity` has already solved and where the submission watcher should resume.
+/// Accounts that already have a cursor are left untouched.
+pub async fn init_submission_cursor(conn: &mut PooledConn, identity: &AtCoderIdentity) -> Result<(), Error> {
     let user_submission: Vec<u64> = conn.exec(
         r"SELECT epoch_second FROM submissions WHERE username=:username",
-        params! {"username" => &atcoder_user.to_lowercase()},
+        params! {"username" => &identity.user_key},
     )?;
     if user_submission.is_empty() {
         let submission_count_url = format!(
             "https://kenkoooo.com/atcoder/atcoder-api/v3/user/submission_count?user={}&from_second=0&to_second={}",
-            atcoder_user,
+            identity.screen_name,
             chrono::Utc::now().timestamp()
         );
         let submission_count_text = reqwest::get(submission_count_url).await?.text().await?;
@@ -85,12 +381,14 @@ pub async fn register_account(ctx: Context<'_>, #[description = "atcoder_usernam
         for _ in 0..((submission_count.count / submissions_per_page) + 1) {
             let submission_url = format!(
                 "https://kenkoooo.com/atcoder/atcoder-api/v3/user/submissions?user={}&from_second={}",
-                atcoder_user, last_epoch
+                identity.screen_name, last_epoch
             );
             let submission_text = reqwest::get(submission_url).await?.text().await?;
             let submission_json: Vec<Submission> = serde_json::from_str(&submission_text)?;
             for submission in submission_json {
-                submission_set.insert(submission.problem_id);
+                if submission.result == "AC" {
+                    submission_set.insert(submission.problem_id);
+                }
                 last_epoch = submission.epoch_second.max(last_epoch);
             }
             last_epoch += 1;
@@ -99,14 +397,14 @@ pub async fn register_account(ctx: Context<'_>, #[description = "atcoder_usernam
             r"INSERT INTO submission_data (user_id, problem_id) VALUES (:user_id, :problem_id)",
             submission_set.iter().map(|problem_id| {
                 params! {
-                    "user_id" => &atcoder_user.to_lowercase(),
+                    "user_id" => &identity.user_key,
                     "problem_id" => problem_id,
                 }
             }),
         )?;
         conn.exec_drop(
             r"INSERT INTO submissions (username, epoch_second) VALUES (:username, :epoch_second)",
-            params! {"username" => &atcoder_user.to_lowercase(), "epoch_second" => last_epoch},
+            params! {"username" => &identity.user_key, "epoch_second" => last_epoch},
         )?;
     }
 
@@ -115,14 +413,19 @@ pub async fn register_account(ctx: Context<'_>, #[description = "atcoder_usernam
 
 /// Delete the registration of a specified AtCoder account.
 #[poise::command(prefix_command, slash_command, rename = "delete-account")]
-pub async fn delete_account(ctx: Context<'_>, #[description = "atcoder_user"] atcoder_user: String) -> Result<(), Error> {
+pub async fn delete_account(
+    ctx: Context<'_>,
+    #[description = "atcoder_user"]
+    #[autocomplete = "autocomplete_atcoder_user"]
+    atcoder_user: String,
+) -> Result<(), Error> {
     let pool = ctx.data().conn.lock().await;
     let mut conn = pool.get_conn().unwrap();
     let guild_id = ctx.guild_id().unwrap().get();
     let registered_accounts: Vec<i32> = conn
         .exec(
             "SELECT id FROM users WHERE atcoder_username=:atcoder_username AND server_id=:server_id AND discord_id IS NULL",
-            params! {"atcoder_username" => &atcoder_user,"server_id" => &guild_id},
+            params! {"atcoder_username" => user_key(&atcoder_user),"server_id" => &guild_id},
         )
         .unwrap();
     let selected_data: Vec<String> = conn.exec(
@@ -185,7 +488,15 @@ pub async fn show_accounts(ctx: Context<'_>) -> Result<(), Error> {
     }
 
     let selected_data: Vec<String> = conn.exec(
-        r"SELECT atcoder_username FROM users WHERE server_id=:server_id AND discord_id IS NULL",
+        r"SELECT
+            COALESCE(atcoder_identities.screen_name, users.atcoder_username)
+        FROM
+            users
+        LEFT JOIN
+            atcoder_identities
+        ON
+            atcoder_identities.user_key = users.atcoder_username
+        WHERE users.server_id=:server_id AND users.discord_id IS NULL",
         params! {"server_id" => &guild_id},
     )?;
 
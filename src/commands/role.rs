@@ -1,5 +1,5 @@
 use poise::{
-    serenity_prelude::{Colour, CreateEmbed, EditRole, RoleId, UserId},
+    serenity_prelude::{Colour, CreateEmbed, EditRole, GuildId, Http, RoleId, UserId},
     CreateReply,
 };
 
@@ -24,6 +24,63 @@ pub async fn role(_ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
 
+/// Create the nine rating color roles in `guild` and give them to the linked members.
+pub async fn create_rating_roles(http: &Http, conn: &mut PooledConn, guild: GuildId) -> Result<(), Error> {
+    let atcoder_users_vec: Vec<(u64, i64)> = conn.exec(
+        r"SELECT
+            users.discord_id,
+            COALESCE(atcoder_user_ratings.algo_rating, 0) AS algo_rating
+        FROM
+            users
+        LEFT JOIN
+            atcoder_identities
+        ON
+            atcoder_identities.user_key = users.atcoder_username
+        LEFT JOIN
+            atcoder_user_ratings
+        ON
+            COALESCE(atcoder_identities.screen_name, users.atcoder_username) = atcoder_user_ratings.user_name
+        LEFT JOIN
+            server_settings
+        ON
+            users.server_id = server_settings.server_id
+        WHERE users.server_id=:server_id AND users.discord_id IS NOT NULL AND (users.verified = 1 OR COALESCE(server_settings.require_verification, 0) = 0)",
+        params! {"server_id" => guild.get()},
+    )?;
+
+    let mut transaction = conn.start_transaction(TxOpts::default()).unwrap();
+
+    for i in ROLE_COLORS_AND_NAMES.iter().enumerate().rev() {
+        let (name, color) = i.1;
+        let output = guild.create_role(http, EditRole::new().name(*name).colour(Colour::from_rgb(color.0, color.1, color.2))).await?;
+        transaction.exec_drop(
+            "INSERT INTO roles (guild_id, role_id, role_color) VALUES (:guild_id, :role_id, :role_color)",
+            params! {
+                "guild_id" => guild.get(),
+                "role_id" => output.id.get(),
+                "role_color" => i.0
+            },
+        )?;
+        for j in &atcoder_users_vec {
+            if i.0 == 0 {
+                if j.1 == 0 {
+                    let user = UserId::new(j.0);
+                    let member = guild.member(http, user).await?;
+                    member.add_role(http, output.id).await?;
+                }
+            } else if i.0 as i64 == j.1 / 400 + 1 && j.1 != 0 {
+                let user = UserId::new(j.0);
+                let member = guild.member(http, user).await?;
+                member.add_role(http, output.id).await?;
+            }
+        }
+    }
+
+    transaction.commit().unwrap();
+
+    Ok(())
+}
+
 #[poise::command(prefix_command, slash_command)]
 pub async fn create_roles(ctx: Context<'_>) -> Result<(), Error> {
     let pool = ctx.data().conn.lock().await;
@@ -77,50 +134,7 @@ pub async fn create_roles(ctx: Context<'_>) -> Result<(), Error> {
         return Ok(());
     }
 
-    let atcoder_users_vec: Vec<(u64, i64)> = conn.exec(
-        r"SELECT
-            users.discord_id,
-            COALESCE(atcoder_user_ratings.algo_rating, 0) AS algo_rating
-        FROM
-            users
-        LEFT JOIN
-            atcoder_user_ratings
-        ON
-            users.atcoder_username = atcoder_user_ratings.user_name
-        WHERE users.server_id=:server_id AND users.discord_id IS NOT NULL",
-        params! {"server_id" => guild_id},
-    )?;
-
-    let mut transaction = conn.start_transaction(TxOpts::default()).unwrap();
-
-    for i in ROLE_COLORS_AND_NAMES.iter().enumerate().rev() {
-        let (name, color) = i.1;
-        let guild = ctx.guild_id().unwrap();
-        let output = guild.create_role(ctx.http(), EditRole::new().name(*name).colour(Colour::from_rgb(color.0, color.1, color.2))).await?;
-        transaction.exec_drop(
-            "INSERT INTO roles (guild_id, role_id, role_color) VALUES (:guild_id, :role_id, :role_color)",
-            params! {
-                "guild_id" => guild_id,
-                "role_id" => output.id.get(),
-                "role_color" => i.0
-            },
-        )?;
-        for j in &atcoder_users_vec {
-            if i.0 == 0 {
-                if j.1 == 0 {
-                    let user = UserId::new(j.0);
-                    let member = guild.member(ctx.http(), user).await?;
-                    member.add_role(ctx.http(), output.id).await?;
-                }
-            } else if i.0 as i64 == j.1 / 400 + 1 && j.1 != 0 {
-                let user = UserId::new(j.0);
-                let member = guild.member(ctx.http(), user).await?;
-                member.add_role(ctx.http(), output.id).await?;
-            }
-        }
-    }
-
-    transaction.commit().unwrap();
+    create_rating_roles(ctx.http(), &mut conn, ctx.guild_id().unwrap()).await?;
 
     if lang == "ja" {
         let response = CreateReply::default().embed(CreateEmbed::new().title("成功").description("ロールの作成に成功しました。")).ephemeral(true);
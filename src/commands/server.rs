@@ -2,11 +2,38 @@ use crate::{Context, Error};
 
 use super::owner::owner;
 use crate::commands::role::role;
+use crate::commands::server_config::{server_export, server_import};
 use crate::commands::set_ac_notify::set_ac_notify;
-use crate::commands::set_everyone::set_everyone;
+use crate::commands::set_discussion_threads::set_discussion_threads;
 use crate::commands::set_language::set_language;
+use crate::commands::set_mention::set_mention;
+use crate::commands::set_quiet_hours::set_quiet_hours;
+use crate::commands::set_scheduled_events::set_scheduled_events;
+use crate::commands::set_timezone::set_timezone;
+use crate::commands::set_weekly_schedule::set_weekly_schedule;
+use crate::commands::setup::server_setup;
+use crate::commands::verify_account::set_require_verification;
 
-#[poise::command(prefix_command, slash_command, subcommands("set_language", "role", "owner", "set_ac_notify", "set_everyone"))]
+#[poise::command(
+    prefix_command,
+    slash_command,
+    subcommands(
+        "server_setup",
+        "set_language",
+        "role",
+        "owner",
+        "set_ac_notify",
+        "set_mention",
+        "server_export",
+        "server_import",
+        "set_require_verification",
+        "set_discussion_threads",
+        "set_scheduled_events",
+        "set_weekly_schedule",
+        "set_timezone",
+        "set_quiet_hours"
+    )
+)]
 pub async fn server(_ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
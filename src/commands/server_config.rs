@@ -0,0 +1,841 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+use crate::mentions::{self, MentionTarget, NotificationKind};
+use crate::routing;
+use crate::scraping::atcoder_identity::user_key;
+use crate::timezone::parse_timezone;
+use crate::{Context, Error};
+
+use mysql::prelude::*;
+use mysql::*;
+
+use chrono::NaiveTime;
+
+use poise::{
+    serenity_prelude::{
+        self as serenity, ChannelId, ChannelType, ComponentInteractionCollector, ComponentInteractionDataKind, CreateActionRow, CreateAttachment, CreateButton,
+        CreateEmbed, CreateEmbedAuthor, CreateInteractionResponse, CreateInteractionResponseMessage, CreateSelectMenu, CreateSelectMenuKind, RoleId, UserId,
+    },
+    CreateReply,
+};
+use serde::{Deserialize, Serialize};
+
+pub const SERVER_CONFIG_VERSION: u32 = 3;
+
+type ServerSettingsTuple = (
+    Option<String>,
+    Option<i32>,
+    i32,
+    Option<u64>,
+    i32,
+    i32,
+    Option<u32>,
+    NaiveTime,
+    Option<String>,
+    NaiveTime,
+);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ServerSettingsConfig {
+    pub language: String,
+    pub ac_notify: i32,
+    /// Whether reminders mention @everyone. Superseded by [`ServerConfig::mentions`] in version 3 and only
+    /// written so that older versions can still read the file.
+    pub do_everyone: i32,
+    #[serde(default)]
+    pub require_verification: i32,
+    /// The opt-in role managed by the bot for [`MentionTarget::Participants`].
+    #[serde(default)]
+    pub participant_role_id: Option<u64>,
+    #[serde(default)]
+    pub discussion_threads: i32,
+    #[serde(default)]
+    pub scheduled_events: i32,
+    /// Counting from Monday = 0. The weekly schedule is off when it is `None`.
+    #[serde(default)]
+    pub weekly_schedule_weekday: Option<u32>,
+    /// `HH:MM`, 09:00 when it is `None`.
+    #[serde(default)]
+    pub weekly_schedule_time: Option<String>,
+    /// IANA time zone, [`DEFAULT_TIMEZONE`](crate::timezone::DEFAULT_TIMEZONE) when it is `None`.
+    #[serde(default)]
+    pub timezone: Option<String>,
+    /// `HH:MM` of the daily digest, 00:00 when it is `None`.
+    #[serde(default)]
+    pub digest_time: Option<String>,
+    /// `HH:MM`. Quiet hours are off when either end is `None`.
+    #[serde(default)]
+    pub quiet_hours_start: Option<String>,
+    #[serde(default)]
+    pub quiet_hours_end: Option<String>,
+    #[serde(default)]
+    pub quiet_hours_hold_reminders: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct NotificationConfig {
+    pub contest_channel_id: Option<u64>,
+    pub submission_channel_id: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LinkedAccountConfig {
+    pub discord_id: u64,
+    pub atcoder_username: String,
+    #[serde(default)]
+    pub verified: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RoleConfig {
+    pub role_color: i8,
+    pub role_id: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MentionConfig {
+    pub notification: String,
+    pub target: String,
+    pub role_id: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RouteConfig {
+    pub event: String,
+    pub channel_id: u64,
+    pub contest_type: Option<i8>,
+    pub series: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ServerConfig {
+    pub version: u32,
+    pub exported_at: String,
+    pub guild_id: u64,
+    pub settings: ServerSettingsConfig,
+    pub notifications: NotificationConfig,
+    pub owners: Vec<u64>,
+    pub registered_accounts: Vec<String>,
+    pub linked_accounts: Vec<LinkedAccountConfig>,
+    pub roles: Vec<RoleConfig>,
+    #[serde(default)]
+    pub mentions: Vec<MentionConfig>,
+    #[serde(default)]
+    pub routes: Vec<RouteConfig>,
+}
+
+impl ServerConfig {
+    pub fn mention(&self, kind: NotificationKind) -> MentionTarget {
+        match self.mentions.iter().find(|mention| mention.notification == kind.as_str()) {
+            Some(mention) => MentionTarget::from_row(&mention.target, mention.role_id),
+            None => MentionTarget::default_for(kind),
+        }
+    }
+
+    pub fn set_mention(&mut self, kind: NotificationKind, target: MentionTarget) {
+        let (target, role_id) = target.to_row();
+        self.mentions.retain(|mention| mention.notification != kind.as_str());
+        self.mentions.push(MentionConfig {
+            notification: kind.as_str().to_string(),
+            target: target.to_string(),
+            role_id,
+        });
+    }
+
+    fn mentions_text(&self, lang: &str) -> String {
+        NotificationKind::ALL.into_iter().map(|kind| format!("{}: {}", kind.label(lang), self.mention(kind).label(lang))).collect::<Vec<String>>().join("\n")
+    }
+}
+
+/// Something in the imported file that does not exist in this server and has to be chosen again.
+#[derive(Debug, Clone)]
+enum Remap {
+    ContestChannel(u64),
+    SubmissionChannel(u64),
+    Role(i8, u64),
+    MentionRole(NotificationKind, u64),
+    RouteChannel(u64),
+}
+
+fn parse_channel_id(channel_id: Option<String>) -> Option<u64> {
+    channel_id.and_then(|channel_id| channel_id.parse::<u64>().ok())
+}
+
+pub fn load_server_config(conn: &mut PooledConn, guild_id: u64) -> Result<ServerConfig> {
+    let settings: Vec<ServerSettingsTuple> = conn.exec(
+        r"SELECT language,ac_notify,require_verification,participant_role_id,discussion_threads,scheduled_events,weekly_schedule_weekday,weekly_schedule_time,timezone,digest_time FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => guild_id},
+    )?;
+    let quiet_hours: Option<(Option<NaiveTime>, Option<NaiveTime>, i32)> = conn.exec_first(
+        r"SELECT quiet_hours_start,quiet_hours_end,quiet_hours_hold_reminders FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => guild_id},
+    )?;
+    let (quiet_hours_start, quiet_hours_end, quiet_hours_hold_reminders) = quiet_hours.unwrap_or((None, None, 0));
+    let mentions = mentions::load_mentions(conn, guild_id)?;
+    let do_everyone = mentions.iter().any(|(kind, target)| *kind == NotificationKind::Reminder && *target == MentionTarget::Everyone) as i32;
+    let settings = match settings.first() {
+        Some((
+            language,
+            ac_notify,
+            require_verification,
+            participant_role_id,
+            discussion_threads,
+            scheduled_events,
+            weekly_schedule_weekday,
+            weekly_schedule_time,
+            timezone,
+            digest_time,
+        )) => ServerSettingsConfig {
+            language: language.clone().unwrap_or("ja".to_string()),
+            ac_notify: ac_notify.unwrap_or(0),
+            do_everyone,
+            require_verification: *require_verification,
+            participant_role_id: *participant_role_id,
+            discussion_threads: *discussion_threads,
+            scheduled_events: *scheduled_events,
+            weekly_schedule_weekday: *weekly_schedule_weekday,
+            weekly_schedule_time: Some(weekly_schedule_time.format("%H:%M").to_string()),
+            timezone: timezone.clone(),
+            digest_time: Some(digest_time.format("%H:%M").to_string()),
+            quiet_hours_start: quiet_hours_start.map(|time| time.format("%H:%M").to_string()),
+            quiet_hours_end: quiet_hours_end.map(|time| time.format("%H:%M").to_string()),
+            quiet_hours_hold_reminders,
+        },
+        None => ServerSettingsConfig {
+            language: "ja".to_string(),
+            ac_notify: 0,
+            do_everyone,
+            require_verification: 0,
+            participant_role_id: None,
+            discussion_threads: 0,
+            scheduled_events: 0,
+            weekly_schedule_weekday: None,
+            weekly_schedule_time: None,
+            timezone: None,
+            digest_time: None,
+            quiet_hours_start: None,
+            quiet_hours_end: None,
+            quiet_hours_hold_reminders: 0,
+        },
+    };
+
+    let notifications: Vec<(Option<String>, Option<String>)> = conn.exec(
+        r"SELECT contest_channel_id,submission_channel_id FROM notifications WHERE server_id=:server_id",
+        params! {"server_id" => guild_id},
+    )?;
+    let notifications = match notifications.into_iter().next() {
+        Some((contest_channel_id, submission_channel_id)) => NotificationConfig {
+            contest_channel_id: parse_channel_id(contest_channel_id),
+            submission_channel_id: parse_channel_id(submission_channel_id),
+        },
+        None => NotificationConfig::default(),
+    };
+
+    let owners: Vec<u64> = conn.exec("SELECT user_id FROM owners WHERE guild_id=:guild_id", params! {"guild_id" => guild_id})?;
+
+    let users: Vec<(Option<u64>, String, i32)> = conn.exec(
+        "SELECT discord_id,atcoder_username,verified FROM users WHERE server_id=:server_id",
+        params! {"server_id" => guild_id},
+    )?;
+    let mut registered_accounts = vec![];
+    let mut linked_accounts = vec![];
+    for (discord_id, atcoder_username, verified) in users {
+        match discord_id {
+            Some(discord_id) => linked_accounts.push(LinkedAccountConfig {
+                discord_id,
+                atcoder_username,
+                verified,
+            }),
+            None => registered_accounts.push(atcoder_username),
+        }
+    }
+
+    let roles: Vec<(i8, u64)> = conn.exec(
+        "SELECT role_color,role_id FROM roles WHERE guild_id=:guild_id ORDER BY role_color",
+        params! {"guild_id" => guild_id},
+    )?;
+
+    let routes = routing::server_routes(conn, guild_id)?;
+
+    Ok(ServerConfig {
+        version: SERVER_CONFIG_VERSION,
+        exported_at: chrono::Utc::now().to_rfc3339(),
+        guild_id,
+        settings,
+        notifications,
+        owners,
+        registered_accounts,
+        linked_accounts,
+        roles: roles.into_iter().map(|(role_color, role_id)| RoleConfig { role_color, role_id }).collect(),
+        mentions: mentions
+            .into_iter()
+            .map(|(kind, target)| {
+                let (target, role_id) = target.to_row();
+                MentionConfig {
+                    notification: kind.as_str().to_string(),
+                    target: target.to_string(),
+                    role_id,
+                }
+            })
+            .collect(),
+        routes: routes
+            .into_iter()
+            .map(|route| RouteConfig {
+                event: route.event.as_str().to_string(),
+                channel_id: route.channel_id,
+                contest_type: route.contest_type.map(routing::RouteContestType::value),
+                series: route.series.map(|series| series.as_str().to_string()),
+            })
+            .collect(),
+    })
+}
+
+fn apply_server_config(conn: &mut PooledConn, guild_id: u64, config: &ServerConfig) -> Result<()> {
+    let mut transaction = conn.start_transaction(TxOpts::default())?;
+
+    let count: Vec<i32> = transaction.exec(
+        r"SELECT count(*) FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => guild_id},
+    )?;
+    let weekly_schedule_time = config
+        .settings
+        .weekly_schedule_time
+        .as_deref()
+        .and_then(|time| NaiveTime::parse_from_str(time, "%H:%M").ok())
+        .unwrap_or(NaiveTime::from_hms_opt(9, 0, 0).unwrap());
+    let digest_time = config.settings.digest_time.as_deref().and_then(|time| NaiveTime::parse_from_str(time, "%H:%M").ok()).unwrap_or(NaiveTime::MIN);
+    let quiet_hours_time = |time: &Option<String>| time.as_deref().and_then(|time| NaiveTime::parse_from_str(time, "%H:%M").ok());
+    let (quiet_hours_start, quiet_hours_end) = match (
+        quiet_hours_time(&config.settings.quiet_hours_start),
+        quiet_hours_time(&config.settings.quiet_hours_end),
+    ) {
+        (Some(start), Some(end)) => (Some(start), Some(end)),
+        _ => (None, None),
+    };
+    let settings_params = params! {
+        "server_id" => guild_id,
+        "language" => &config.settings.language,
+        "ac_notify" => config.settings.ac_notify,
+        "require_verification" => config.settings.require_verification,
+        "participant_role_id" => config.settings.participant_role_id,
+        "discussion_threads" => config.settings.discussion_threads,
+        "scheduled_events" => config.settings.scheduled_events,
+        "weekly_schedule_weekday" => config.settings.weekly_schedule_weekday,
+        "weekly_schedule_time" => weekly_schedule_time,
+        "timezone" => config.settings.timezone.as_deref().and_then(parse_timezone).map(|timezone| timezone.name()),
+        "digest_time" => digest_time,
+        "quiet_hours_start" => quiet_hours_start,
+        "quiet_hours_end" => quiet_hours_end,
+        "quiet_hours_hold_reminders" => config.settings.quiet_hours_hold_reminders,
+    };
+    if count[0] == 0 {
+        transaction.exec_drop(
+            r"INSERT INTO server_settings (server_id, language, ac_notify, require_verification, participant_role_id, discussion_threads, scheduled_events, weekly_schedule_weekday, weekly_schedule_time, weekly_schedule_sent_at, timezone, digest_time, quiet_hours_start, quiet_hours_end, quiet_hours_hold_reminders) VALUES (:server_id, :language, :ac_notify, :require_verification, :participant_role_id, :discussion_threads, :scheduled_events, :weekly_schedule_weekday, :weekly_schedule_time, UTC_TIMESTAMP(), :timezone, :digest_time, :quiet_hours_start, :quiet_hours_end, :quiet_hours_hold_reminders)",
+            settings_params,
+        )?;
+    } else {
+        transaction.exec_drop(
+            r"UPDATE server_settings SET language=:language, ac_notify=:ac_notify, require_verification=:require_verification, participant_role_id=:participant_role_id, discussion_threads=:discussion_threads, scheduled_events=:scheduled_events, weekly_schedule_weekday=:weekly_schedule_weekday, weekly_schedule_time=:weekly_schedule_time, weekly_schedule_sent_at=UTC_TIMESTAMP(), timezone=:timezone, digest_time=:digest_time, quiet_hours_start=:quiet_hours_start, quiet_hours_end=:quiet_hours_end, quiet_hours_hold_reminders=:quiet_hours_hold_reminders WHERE server_id=:server_id",
+            settings_params,
+        )?;
+    }
+
+    transaction.exec_drop(r"DELETE FROM mention_settings WHERE server_id=:server_id", params! {"server_id" => guild_id})?;
+    for kind in NotificationKind::ALL {
+        mentions::save_mention(&mut transaction, guild_id, kind, config.mention(kind))?;
+    }
+
+    transaction.exec_drop(r"DELETE FROM notifications WHERE server_id=:server_id", params! {"server_id" => guild_id})?;
+    transaction.exec_drop(
+        r"INSERT INTO notifications (server_id, contest_channel_id, submission_channel_id) VALUES (:server_id, :contest_channel_id, :submission_channel_id)",
+        params! {
+            "server_id" => guild_id,
+            "contest_channel_id" => config.notifications.contest_channel_id,
+            "submission_channel_id" => config.notifications.submission_channel_id,
+        },
+    )?;
+
+    transaction.exec_drop(r"DELETE FROM notification_routes WHERE server_id=:server_id", params! {"server_id" => guild_id})?;
+    transaction.exec_batch(
+        r"INSERT INTO notification_routes (server_id, event, channel_id, contest_type, series) VALUES (:server_id, :event, :channel_id, :contest_type, :series)",
+        config.routes.iter().filter(|route| routing::RouteEvent::parse(&route.event).is_some()).map(|route| {
+            params! {
+                "server_id" => guild_id,
+                "event" => &route.event,
+                "channel_id" => route.channel_id,
+                "contest_type" => route.contest_type,
+                "series" => route.series.as_deref().and_then(routing::Series::parse).map(routing::Series::as_str),
+            }
+        }),
+    )?;
+
+    transaction.exec_drop(r"DELETE FROM owners WHERE guild_id=:guild_id", params! {"guild_id" => guild_id})?;
+    transaction.exec_batch(
+        r"INSERT INTO owners (guild_id, user_id) VALUES (:guild_id, :user_id)",
+        config.owners.iter().map(|user_id| params! {"guild_id" => guild_id, "user_id" => user_id}),
+    )?;
+
+    transaction.exec_drop(r"DELETE FROM users WHERE server_id=:server_id", params! {"server_id" => guild_id})?;
+    transaction.exec_batch(
+        r"INSERT INTO users (server_id, atcoder_username) VALUES (:server_id, :atcoder_username)",
+        config.registered_accounts.iter().map(|atcoder_username| params! {"server_id" => guild_id, "atcoder_username" => user_key(atcoder_username)}),
+    )?;
+    transaction.exec_batch(
+        r"INSERT INTO users (server_id, discord_id, atcoder_username, verified) VALUES (:server_id, :discord_id, :atcoder_username, :verified)",
+        config.linked_accounts.iter().map(|account| {
+            params! {
+                "server_id" => guild_id,
+                "discord_id" => account.discord_id,
+                "atcoder_username" => user_key(&account.atcoder_username),
+                "verified" => account.verified,
+            }
+        }),
+    )?;
+
+    transaction.exec_drop(r"DELETE FROM roles WHERE guild_id=:guild_id", params! {"guild_id" => guild_id})?;
+    transaction.exec_batch(
+        r"INSERT INTO roles (guild_id, role_id, role_color) VALUES (:guild_id, :role_id, :role_color)",
+        config.roles.iter().map(|role| params! {"guild_id" => guild_id, "role_id" => role.role_id, "role_color" => role.role_color}),
+    )?;
+
+    transaction.commit()
+}
+
+/// Export every setting stored for this server as a JSON file.
+#[poise::command(prefix_command, slash_command, rename = "export")]
+pub async fn server_export(ctx: Context<'_>) -> Result<(), Error> {
+    let pool = ctx.data().conn.lock().await;
+    let mut conn = pool.get_conn().unwrap();
+    let guild_id = ctx.guild_id().unwrap().get();
+
+    let selected_data: Vec<String> = conn.exec(
+        r"SELECT language FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => guild_id},
+    )?;
+    let mut lang = "ja";
+    if selected_data.len() == 1 {
+        lang = selected_data[0].as_str();
+    }
+
+    let owners: Vec<u64> = conn
+        .exec(
+            "SELECT user_id FROM owners WHERE guild_id=:guild_id",
+            params! {
+                "guild_id" => ctx.guild_id().unwrap_or_default().get()
+            },
+        )
+        .unwrap();
+    let has_permission = if owners.is_empty() || owners.contains(&{ ctx.author().id.get() }) {
+        true
+    } else {
+        ctx.author().id.get() == ctx.guild().unwrap().owner_id.get()
+    };
+    if !has_permission {
+        if lang == "ja" {
+            let response = CreateReply::default().embed(CreateEmbed::default().title("エラー").description("権限がありません。")).ephemeral(true);
+            ctx.send(response).await?;
+        } else {
+            let response = CreateReply::default().embed(CreateEmbed::default().title("Error").description("You do not have permission.")).ephemeral(true);
+            ctx.send(response).await?;
+        }
+        return Ok(());
+    }
+
+    let config = load_server_config(&mut conn, guild_id)?;
+    let json = serde_json::to_string_pretty(&config)?;
+
+    let response = {
+        let mut embed = serenity::CreateEmbed::default()
+            .author(CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(ctx.data().avatar_url.as_str()).url("https://atcoder-notify.com/"));
+        if lang == "ja" {
+            embed = embed.title("設定のエクスポート").description(format!(
+                "このサーバーの設定を書き出しました。\n登録アカウント: {}件\n連携アカウント: {}件\nオーナー: {}人\nロール: {}個",
+                config.registered_accounts.len(),
+                config.linked_accounts.len(),
+                config.owners.len(),
+                config.roles.len()
+            ));
+        } else {
+            embed = embed.title("Settings Exported").description(format!(
+                "Exported the settings of this server.\nRegistered accounts: {}\nLinked accounts: {}\nOwners: {}\nRoles: {}",
+                config.registered_accounts.len(),
+                config.linked_accounts.len(),
+                config.owners.len(),
+                config.roles.len()
+            ));
+        }
+        poise::CreateReply::default()
+            .embed(embed)
+            .attachment(CreateAttachment::bytes(json.into_bytes(), format!("atcoder_notify_bot_{}.json", guild_id)))
+            .ephemeral(true)
+    };
+
+    ctx.send(response).await?;
+
+    Ok(())
+}
+
+/// Import settings exported with `/server export`, remapping channels and roles to this server.
+#[poise::command(prefix_command, slash_command, rename = "import")]
+pub async fn server_import(ctx: Context<'_>, #[description = "exported settings file"] file: serenity::Attachment) -> Result<(), Error> {
+    // The wizard below waits for user input, so the pool is cloned instead of holding the lock.
+    let pool = ctx.data().conn.lock().await.clone();
+    let mut conn = pool.get_conn().unwrap();
+    let guild_id = ctx.guild_id().unwrap();
+
+    let selected_data: Vec<String> = conn.exec(
+        r"SELECT language FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => guild_id.get()},
+    )?;
+    let mut lang = "ja".to_string();
+    if selected_data.len() == 1 {
+        lang.clone_from(&selected_data[0]);
+    }
+    let lang = lang.as_str();
+
+    let owners: Vec<u64> = conn
+        .exec(
+            "SELECT user_id FROM owners WHERE guild_id=:guild_id",
+            params! {
+                "guild_id" => ctx.guild_id().unwrap_or_default().get()
+            },
+        )
+        .unwrap();
+    let has_permission = if owners.is_empty() || owners.contains(&{ ctx.author().id.get() }) {
+        true
+    } else {
+        ctx.author().id.get() == ctx.guild().unwrap().owner_id.get()
+    };
+    if !has_permission {
+        if lang == "ja" {
+            let response = CreateReply::default().embed(CreateEmbed::default().title("エラー").description("権限がありません。")).ephemeral(true);
+            ctx.send(response).await?;
+        } else {
+            let response = CreateReply::default().embed(CreateEmbed::default().title("Error").description("You do not have permission.")).ephemeral(true);
+            ctx.send(response).await?;
+        }
+        return Ok(());
+    }
+
+    ctx.defer_ephemeral().await?;
+
+    let content = file.download().await?;
+    let config: Option<ServerConfig> = serde_json::from_slice(&content).ok();
+    let mut config = match config {
+        Some(config) if config.version <= SERVER_CONFIG_VERSION => config,
+        _ => {
+            if lang == "ja" {
+                let response = CreateReply::default()
+                    .embed(
+                        CreateEmbed::default()
+                            .title("エラー")
+                            .description("設定ファイルを読み込めませんでした。`/server export` で出力したファイルを指定してください。"),
+                    )
+                    .ephemeral(true);
+                ctx.send(response).await?;
+            } else {
+                let response = CreateReply::default()
+                    .embed(
+                        CreateEmbed::default()
+                            .title("Error")
+                            .description("Could not read the settings file. Please attach a file created by `/server export`."),
+                    )
+                    .ephemeral(true);
+                ctx.send(response).await?;
+            }
+            return Ok(());
+        }
+    };
+
+    if config.version < 3 {
+        let reminder = if config.settings.do_everyone == 0 {
+            MentionTarget::None
+        } else {
+            MentionTarget::Everyone
+        };
+        config.set_mention(NotificationKind::Reminder, reminder);
+    }
+
+    let channels = guild_id.channels(ctx.http()).await?;
+    let roles = guild_id.roles(ctx.http()).await?;
+
+    let mut warnings = vec![];
+    let mut seen_discord_ids = BTreeSet::new();
+    let mut linked_accounts = vec![];
+    for account in config.linked_accounts.drain(..) {
+        if !seen_discord_ids.insert(account.discord_id) {
+            continue;
+        }
+        if guild_id.member(ctx.http(), UserId::new(account.discord_id)).await.is_ok() {
+            linked_accounts.push(account);
+        } else if lang == "ja" {
+            warnings.push(format!(
+                "<@{}> (`{}`) はこのサーバーにいないため連携をスキップしました。",
+                account.discord_id, account.atcoder_username
+            ));
+        } else {
+            warnings.push(format!(
+                "Skipped <@{}> (`{}`) because they are not a member of this server.",
+                account.discord_id, account.atcoder_username
+            ));
+        }
+    }
+    config.linked_accounts = linked_accounts;
+
+    let registered_accounts: BTreeSet<String> = config.registered_accounts.drain(..).collect();
+    config.registered_accounts = registered_accounts.into_iter().collect();
+
+    let mut importable_owners = vec![];
+    for owner in config.owners.drain(..).collect::<BTreeSet<u64>>() {
+        if guild_id.member(ctx.http(), UserId::new(owner)).await.is_ok() {
+            importable_owners.push(owner);
+        } else if lang == "ja" {
+            warnings.push(format!("<@{}> はこのサーバーにいないためオーナーに追加しませんでした。", owner));
+        } else {
+            warnings.push(format!("<@{}> was not added as an owner because they are not a member of this server.", owner));
+        }
+    }
+    config.owners = importable_owners;
+
+    let mut remaps = vec![];
+    if let Some(channel_id) = config.notifications.contest_channel_id {
+        if !channels.contains_key(&ChannelId::new(channel_id)) {
+            remaps.push(Remap::ContestChannel(channel_id));
+        }
+    }
+    if let Some(channel_id) = config.notifications.submission_channel_id {
+        if !channels.contains_key(&ChannelId::new(channel_id)) {
+            remaps.push(Remap::SubmissionChannel(channel_id));
+        }
+    }
+    let route_channels: BTreeSet<u64> = config.routes.iter().map(|route| route.channel_id).collect();
+    for channel_id in route_channels {
+        if !channels.contains_key(&ChannelId::new(channel_id)) {
+            remaps.push(Remap::RouteChannel(channel_id));
+        }
+    }
+    let mut role_map: BTreeMap<i8, u64> = BTreeMap::new();
+    for role in &config.roles {
+        if roles.contains_key(&RoleId::new(role.role_id)) {
+            role_map.insert(role.role_color, role.role_id);
+        } else {
+            remaps.push(Remap::Role(role.role_color, role.role_id));
+        }
+    }
+    for kind in NotificationKind::ALL {
+        if let MentionTarget::Role(role_id) = config.mention(kind) {
+            if !roles.contains_key(&RoleId::new(role_id)) {
+                remaps.push(Remap::MentionRole(kind, role_id));
+            }
+        }
+    }
+    // The participants role is created again by `/server set-mention` when it is missing.
+    if let Some(role_id) = config.settings.participant_role_id {
+        if !roles.contains_key(&RoleId::new(role_id)) {
+            config.settings.participant_role_id = None;
+            if NotificationKind::ALL.into_iter().any(|kind| config.mention(kind) == MentionTarget::Participants) {
+                if lang == "ja" {
+                    warnings.push("コンテスト参加者ロールがこのサーバーに存在しません。`/server set-mention` で作り直してください。".to_string());
+                } else {
+                    warnings.push("The contest participants role does not exist in this server. Use `/server set-mention` to create it again.".to_string());
+                }
+            }
+        }
+    }
+
+    let handle = ctx
+        .send(
+            CreateReply::default()
+                .content(if lang == "ja" {
+                    "設定ファイルを確認しています..."
+                } else {
+                    "Checking the settings file..."
+                })
+                .ephemeral(true),
+        )
+        .await?;
+    let message_id = handle.message().await?.id;
+
+    for remap in remaps {
+        let (description, menu_kind) = match &remap {
+            Remap::ContestChannel(old) | Remap::SubmissionChannel(old) | Remap::RouteChannel(old) => {
+                let label = match (&remap, lang) {
+                    (Remap::ContestChannel(_), "ja") => "コンテスト情報の通知チャンネル",
+                    (Remap::ContestChannel(_), _) => "contest notification channel",
+                    (Remap::RouteChannel(_), "ja") => "通知ルートのチャンネル",
+                    (Remap::RouteChannel(_), _) => "channel of notification routes",
+                    (_, "ja") => "ユーザー提出情報の通知チャンネル",
+                    _ => "submission notification channel",
+                };
+                (
+                    if lang == "ja" {
+                        format!("{} (元: `{}`) はこのサーバーに存在しません。代わりのチャンネルを選んでください。", label, old)
+                    } else {
+                        format!("The {} (was `{}`) does not exist in this server. Please choose a replacement.", label, old)
+                    },
+                    CreateSelectMenuKind::Channel {
+                        channel_types: Some(vec![ChannelType::Text, ChannelType::News]),
+                        default_channels: None,
+                    },
+                )
+            }
+            Remap::Role(color, old) => (
+                if lang == "ja" {
+                    format!(
+                        "レーティングロール {} (元: `{}`) はこのサーバーに存在しません。代わりのロールを選んでください。",
+                        color, old
+                    )
+                } else {
+                    format!(
+                        "The rating role for color {} (was `{}`) does not exist in this server. Please choose a replacement.",
+                        color, old
+                    )
+                },
+                CreateSelectMenuKind::Role { default_roles: None },
+            ),
+            Remap::MentionRole(kind, old) => (
+                if lang == "ja" {
+                    format!(
+                        "{}でメンションするロール (元: `{}`) はこのサーバーに存在しません。代わりのロールを選んでください。",
+                        kind.label(lang),
+                        old
+                    )
+                } else {
+                    format!(
+                        "The role mentioned by the {} (was `{}`) does not exist in this server. Please choose a replacement.",
+                        kind.label(lang),
+                        old
+                    )
+                },
+                CreateSelectMenuKind::Role { default_roles: None },
+            ),
+        };
+        let components = vec![
+            CreateActionRow::SelectMenu(CreateSelectMenu::new("server_import_select", menu_kind)),
+            CreateActionRow::Buttons(vec![CreateButton::new("server_import_skip")
+                .label(if lang == "ja" { "設定しない" } else { "Skip" })
+                .style(serenity::ButtonStyle::Secondary)]),
+        ];
+        handle.edit(ctx, CreateReply::default().content(description).components(components)).await?;
+
+        let interaction = ComponentInteractionCollector::new(ctx).author_id(ctx.author().id).message_id(message_id).timeout(Duration::from_secs(180)).await;
+        let Some(interaction) = interaction else {
+            handle
+                .edit(
+                    ctx,
+                    CreateReply::default().content(if lang == "ja" { "タイムアウトしました。" } else { "Timed out." }).components(vec![]),
+                )
+                .await?;
+            return Ok(());
+        };
+        interaction.create_response(ctx, CreateInteractionResponse::Acknowledge).await?;
+
+        let selected = match &interaction.data.kind {
+            ComponentInteractionDataKind::ChannelSelect { values } => values.first().map(|channel| channel.get()),
+            ComponentInteractionDataKind::RoleSelect { values } => values.first().map(|role| role.get()),
+            _ => None,
+        };
+        match remap {
+            Remap::ContestChannel(_) => config.notifications.contest_channel_id = selected,
+            Remap::SubmissionChannel(_) => config.notifications.submission_channel_id = selected,
+            Remap::Role(color, _) => {
+                if let Some(role_id) = selected {
+                    role_map.insert(color, role_id);
+                }
+            }
+            Remap::MentionRole(kind, _) => config.set_mention(kind, selected.map(MentionTarget::Role).unwrap_or(MentionTarget::None)),
+            // Skipping drops the routes to the missing channel.
+            Remap::RouteChannel(old) => match selected {
+                Some(channel_id) => config.routes.iter_mut().filter(|route| route.channel_id == old).for_each(|route| route.channel_id = channel_id),
+                None => config.routes.retain(|route| route.channel_id != old),
+            },
+        }
+    }
+    config.roles = role_map.into_iter().map(|(role_color, role_id)| RoleConfig { role_color, role_id }).collect();
+
+    let channel_text = |channel_id: Option<u64>| match channel_id {
+        Some(channel_id) => format!("<#{}>", channel_id),
+        None => if lang == "ja" { "未設定" } else { "Not Set" }.to_string(),
+    };
+    let mut embed = CreateEmbed::default()
+        .author(CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(ctx.data().avatar_url.as_str()).url("https://atcoder-notify.com/"));
+    if lang == "ja" {
+        embed = embed
+            .title("インポート内容の確認")
+            .field("言語", &config.settings.language, true)
+            .field("AC通知", if config.settings.ac_notify == 0 { "すべて" } else { "Unique AC のみ" }, true)
+            .field("メンション", config.mentions_text(lang), false)
+            .field("コンテスト情報", channel_text(config.notifications.contest_channel_id), true)
+            .field("ユーザー提出情報", channel_text(config.notifications.submission_channel_id), true)
+            .field("通知ルート", config.routes.len().to_string(), true)
+            .field("オーナー", config.owners.len().to_string(), true)
+            .field("登録アカウント", config.registered_accounts.len().to_string(), true)
+            .field("連携アカウント", config.linked_accounts.len().to_string(), true)
+            .field("ロール", config.roles.len().to_string(), true);
+    } else {
+        embed = embed
+            .title("Confirm Import")
+            .field("Language", &config.settings.language, true)
+            .field("AC notification", if config.settings.ac_notify == 0 { "All" } else { "Unique AC only" }, true)
+            .field("Mentions", config.mentions_text(lang), false)
+            .field("Contest Information", channel_text(config.notifications.contest_channel_id), true)
+            .field("User Submission Information", channel_text(config.notifications.submission_channel_id), true)
+            .field("Notification routes", config.routes.len().to_string(), true)
+            .field("Owners", config.owners.len().to_string(), true)
+            .field("Registered accounts", config.registered_accounts.len().to_string(), true)
+            .field("Linked accounts", config.linked_accounts.len().to_string(), true)
+            .field("Roles", config.roles.len().to_string(), true);
+    }
+    if !warnings.is_empty() {
+        let mut warning_text = warnings.join("\n");
+        if warning_text.len() > 1000 {
+            warning_text = format!("{}...", warning_text.chars().take(1000).collect::<String>());
+        }
+        embed = embed.field(if lang == "ja" { "警告" } else { "Warnings" }, warning_text, false);
+    }
+    let components = vec![CreateActionRow::Buttons(vec![
+        CreateButton::new("server_import_apply").label(if lang == "ja" { "適用する" } else { "Apply" }).style(serenity::ButtonStyle::Success),
+        CreateButton::new("server_import_cancel").label(if lang == "ja" { "キャンセル" } else { "Cancel" }).style(serenity::ButtonStyle::Danger),
+    ])];
+    handle.edit(ctx, CreateReply::default().content("").embed(embed).components(components)).await?;
+
+    let interaction = ComponentInteractionCollector::new(ctx).author_id(ctx.author().id).message_id(message_id).timeout(Duration::from_secs(180)).await;
+    let apply = match &interaction {
+        Some(interaction) => interaction.data.custom_id == "server_import_apply",
+        None => false,
+    };
+
+    let result_text = if !apply {
+        if lang == "ja" {
+            "インポートをキャンセルしました。"
+        } else {
+            "Import cancelled."
+        }
+    } else {
+        match apply_server_config(&mut conn, guild_id.get(), &config) {
+            Ok(()) => {
+                if lang == "ja" {
+                    "設定をインポートしました。"
+                } else {
+                    "Settings imported."
+                }
+            }
+            Err(err) => {
+                log::error!("server import failed: {}", err);
+                if lang == "ja" {
+                    "インポート中にエラーが発生しました。設定は変更されていません。"
+                } else {
+                    "An error occurred during the import. No settings were changed."
+                }
+            }
+        }
+    };
+    let response = CreateInteractionResponseMessage::default().content(result_text).embeds(vec![]).components(vec![]);
+    match interaction {
+        Some(interaction) => interaction.create_response(ctx, CreateInteractionResponse::UpdateMessage(response)).await?,
+        None => handle.edit(ctx, CreateReply::default().content(result_text).components(vec![])).await?,
+    }
+
+    Ok(())
+}
@@ -1,13 +1,5 @@
 use crate::{Context, Error};
 
-#[derive(Debug, poise::ChoiceParameter)]
-pub enum ACSettings {
-    #[name = "all"]
-    All,
-    #[name = "unique"]
-    Unique,
-}
-
 use mysql::prelude::*;
 use mysql::*;
 
@@ -16,20 +8,22 @@ use poise::{
     CreateReply,
 };
 
-/// Set the default language for the server.
-#[poise::command(prefix_command, slash_command, rename = "set-notify-everyone")]
-pub async fn set_notify_everyone(ctx: Context<'_>, #[description = "do everyone"] do_everyone: bool) -> Result<(), Error> {
+/// Set whether a discussion thread is opened for each contest.
+#[poise::command(prefix_command, slash_command, rename = "set-discussion-threads")]
+pub async fn set_discussion_threads(ctx: Context<'_>, #[description = "discussion_threads"] discussion_threads: bool) -> Result<(), Error> {
     let pool = ctx.data().conn.lock().await;
     let mut conn = pool.get_conn().unwrap();
     let guild_id = ctx.guild_id().unwrap().to_string();
 
-    let selected_data: Vec<(String, i32)> = conn.exec(
-        r"SELECT language,plan FROM server_settings WHERE server_id=:server_id",
+    let discussion_threads = if discussion_threads { 1 } else { 0 };
+
+    let selected_data: Vec<String> = conn.exec(
+        r"SELECT language FROM server_settings WHERE server_id=:server_id",
         params! {"server_id" => &guild_id},
     )?;
     let mut lang = "ja";
     if selected_data.len() == 1 {
-        lang = selected_data[0].0.as_str();
+        lang = selected_data[0].as_str();
     }
 
     let owners: Vec<u64> = conn
@@ -63,13 +57,13 @@ pub async fn set_notify_everyone(ctx: Context<'_>, #[description = "do everyone"
 
     if count[0] == 0 {
         conn.exec_drop(
-            r"INSERT INTO server_settings (server_id, do_everyone) VALUES (:server_id, :do_everyone)",
-            params! {"server_id" => &guild_id, "do_everyone" => do_everyone as i32},
+            r"INSERT INTO server_settings (server_id, discussion_threads) VALUES (:server_id, :discussion_threads)",
+            params! {"server_id" => &guild_id, "discussion_threads" => discussion_threads},
         )?;
     } else {
         conn.exec_drop(
-            r"UPDATE server_settings SET do_everyone=:do_everyone WHERE server_id=:server_id",
-            params! {"server_id" => &guild_id, "do_everyone" => do_everyone as i32},
+            r"UPDATE server_settings SET discussion_threads=:discussion_threads WHERE server_id=:server_id",
+            params! {"server_id" => &guild_id, "discussion_threads" => discussion_threads},
         )?;
     }
 
@@ -78,13 +72,13 @@ pub async fn set_notify_everyone(ctx: Context<'_>, #[description = "do everyone"
             .author(CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(ctx.data().avatar_url.as_str()).url("https://atcoder-notify.com/"));
         if lang == "ja" {
             embed = embed.title("設定変更").description(format!(
-                "1時間前通知でeveryoneを {} に変更しました。",
-                if do_everyone { "する" } else { "しない" }
+                "コンテストごとの感想スレッドを {} にしました。",
+                if discussion_threads == 0 { "無効" } else { "有効" }
             ));
         } else {
             embed = embed.title("Settings Changed").description(format!(
-                "Changed 1 hour notification to {} everyone.",
-                if do_everyone { "do" } else { "not do" }
+                "Discussion threads for each contest are now {}.",
+                if discussion_threads == 0 { "disabled" } else { "enabled" }
             ));
         }
         poise::CreateReply::default().embed(embed).ephemeral(true)
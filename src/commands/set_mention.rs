@@ -0,0 +1,223 @@
+use crate::components::ComponentRequest;
+use crate::mentions::{self, MentionTarget, NotificationKind, PARTICIPANT_ROLE_NAME};
+use crate::{Context, Data, Error};
+
+use mysql::prelude::*;
+use mysql::*;
+
+use poise::{
+    serenity_prelude::{
+        self as serenity, ComponentInteraction, CreateEmbed, CreateEmbedAuthor, CreateInteractionResponse, CreateInteractionResponseMessage, EditRole, GuildId,
+        RoleId,
+    },
+    BoxFuture, CreateReply,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum MentionChoice {
+    #[name = "none"]
+    None,
+    #[name = "@everyone"]
+    Everyone,
+    #[name = "@here"]
+    Here,
+    #[name = "role"]
+    Role,
+    #[name = "contest participants"]
+    Participants,
+}
+
+/// The participants role of the server, created when the server has none or it was deleted.
+async fn ensure_participant_role(http: &serenity::Http, conn: &mut PooledConn, guild_id: GuildId) -> Result<u64, Error> {
+    if let Some(role_id) = mentions::participant_role(conn, guild_id.get())? {
+        if guild_id.roles(http).await?.contains_key(&RoleId::new(role_id)) {
+            return Ok(role_id);
+        }
+    }
+    let role = guild_id.create_role(http, EditRole::new().name(PARTICIPANT_ROLE_NAME).mentionable(true)).await?;
+    let count: Vec<i32> = conn.exec(
+        r"SELECT count(*) FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => guild_id.get()},
+    )?;
+    if count[0] == 0 {
+        conn.exec_drop(
+            r"INSERT INTO server_settings (server_id, participant_role_id) VALUES (:server_id, :participant_role_id)",
+            params! {"server_id" => guild_id.get(), "participant_role_id" => role.id.get()},
+        )?;
+    } else {
+        conn.exec_drop(
+            r"UPDATE server_settings SET participant_role_id=:participant_role_id WHERE server_id=:server_id",
+            params! {"server_id" => guild_id.get(), "participant_role_id" => role.id.get()},
+        )?;
+    }
+    Ok(role.id.get())
+}
+
+/// Choose who is mentioned by each kind of notification.
+#[poise::command(prefix_command, slash_command, rename = "set-mention")]
+pub async fn set_mention(
+    ctx: Context<'_>,
+    #[description = "notification"] notification: NotificationKind,
+    #[description = "target"] target: MentionChoice,
+    #[description = "role (when target is role)"] role: Option<serenity::Role>,
+) -> Result<(), Error> {
+    let pool = ctx.data().conn.lock().await.clone();
+    let mut conn = pool.get_conn().unwrap();
+    let guild_id = ctx.guild_id().unwrap();
+
+    let selected_data: Vec<String> = conn.exec(
+        r"SELECT language FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => guild_id.get()},
+    )?;
+    let mut lang = "ja";
+    if selected_data.len() == 1 {
+        lang = selected_data[0].as_str();
+    }
+
+    let owners: Vec<u64> = conn
+        .exec(
+            "SELECT user_id FROM owners WHERE guild_id=:guild_id",
+            params! {
+                "guild_id" => ctx.guild_id().unwrap_or_default().get()
+            },
+        )
+        .unwrap();
+    let has_permission = if owners.is_empty() || owners.contains(&{ ctx.author().id.get() }) {
+        true
+    } else {
+        ctx.author().id.get() == ctx.guild().unwrap().owner_id.get()
+    };
+    if !has_permission {
+        if lang == "ja" {
+            let response = CreateReply::default().embed(CreateEmbed::default().title("エラー").description("権限がありません。")).ephemeral(true);
+            ctx.send(response).await?;
+        } else {
+            let response = CreateReply::default().embed(CreateEmbed::default().title("Error").description("You do not have permission.")).ephemeral(true);
+            ctx.send(response).await?;
+        }
+        return Ok(());
+    }
+
+    let target = match (target, role) {
+        (MentionChoice::None, _) => MentionTarget::None,
+        (MentionChoice::Everyone, _) => MentionTarget::Everyone,
+        (MentionChoice::Here, _) => MentionTarget::Here,
+        (MentionChoice::Role, Some(role)) => MentionTarget::Role(role.id.get()),
+        (MentionChoice::Role, None) => {
+            let description = if lang == "ja" {
+                "`role` を指定してください。"
+            } else {
+                "Please specify `role`."
+            };
+            let title = if lang == "ja" { "エラー" } else { "Error" };
+            ctx.send(CreateReply::default().embed(CreateEmbed::default().title(title).description(description)).ephemeral(true)).await?;
+            return Ok(());
+        }
+        (MentionChoice::Participants, _) => {
+            if let Err(err) = ensure_participant_role(ctx.http(), &mut conn, guild_id).await {
+                log::info!("could not create the participants role in {}: {}", guild_id, err);
+                let description = if lang == "ja" {
+                    "コンテスト参加者ロールを作成できませんでした。Botにロールの管理権限があるか確認してください。"
+                } else {
+                    "Could not create the contest participants role. Please check that the bot can manage roles."
+                };
+                let title = if lang == "ja" { "エラー" } else { "Error" };
+                ctx.send(CreateReply::default().embed(CreateEmbed::default().title(title).description(description)).ephemeral(true)).await?;
+                return Ok(());
+            }
+            MentionTarget::Participants
+        }
+    };
+    mentions::save_mention(&mut conn, guild_id.get(), notification, target)?;
+
+    let response = {
+        let mut embed = serenity::CreateEmbed::default()
+            .author(CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(ctx.data().avatar_url.as_str()).url("https://atcoder-notify.com/"));
+        if lang == "ja" {
+            let mut description = format!("{}のメンションを {} に変更しました。", notification.label(lang), target.label(lang));
+            if target == MentionTarget::Participants {
+                description += "\n1時間前通知のボタンからメンバーが自分でロールを付け外しできます。";
+            }
+            embed = embed.title("設定変更").description(description);
+        } else {
+            let mut description = format!("Changed the mention of the {} to {}.", notification.label(lang), target.label(lang));
+            if target == MentionTarget::Participants {
+                description += "\nMembers can add or remove the role themselves with the button on reminders.";
+            }
+            embed = embed.title("Settings Changed").description(description);
+        }
+        poise::CreateReply::default().embed(embed).ephemeral(true)
+    };
+
+    ctx.send(response).await?;
+
+    Ok(())
+}
+
+/// Toggle the contest participants role from the button on reminders.
+pub fn handle_component<'a>(
+    ctx: &'a serenity::Context,
+    data: &'a Data,
+    interaction: &'a ComponentInteraction,
+    request: ComponentRequest<'a>,
+) -> BoxFuture<'a, Result<(), Error>> {
+    Box::pin(async move {
+        match request.action {
+            "join" => toggle_participant_role(ctx, data, interaction).await,
+            _ => Ok(()),
+        }
+    })
+}
+
+async fn toggle_participant_role(ctx: &serenity::Context, data: &Data, interaction: &ComponentInteraction) -> Result<(), Error> {
+    let (Some(guild_id), Some(member)) = (interaction.guild_id, interaction.member.as_ref()) else {
+        return Ok(());
+    };
+    let pool = data.conn.lock().await.clone();
+    let mut conn = pool.get_conn()?;
+
+    let selected_data: Vec<String> = conn.exec(
+        r"SELECT language FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => guild_id.get()},
+    )?;
+    let mut lang = "ja";
+    if selected_data.len() == 1 {
+        lang = selected_data[0].as_str();
+    }
+
+    let content = match mentions::participant_role(&mut conn, guild_id.get())? {
+        None => {
+            if lang == "ja" {
+                "このサーバーにはコンテスト参加者ロールがありません。".to_string()
+            } else {
+                "This server has no contest participants role.".to_string()
+            }
+        }
+        Some(role_id) => {
+            let role_id = RoleId::new(role_id);
+            let has_role = member.roles.contains(&role_id);
+            let result = if has_role {
+                member.remove_role(&ctx.http, role_id).await
+            } else {
+                member.add_role(&ctx.http, role_id).await
+            };
+            match (result, has_role, lang) {
+                (Err(err), _, "ja") => {
+                    log::info!("could not toggle the participants role in {}: {}", guild_id, err);
+                    "ロールを変更できませんでした。".to_string()
+                }
+                (Err(err), _, _) => {
+                    log::info!("could not toggle the participants role in {}: {}", guild_id, err);
+                    "Could not change your roles.".to_string()
+                }
+                (Ok(()), true, "ja") => format!("<@&{}> を外しました。", role_id),
+                (Ok(()), true, _) => format!("Removed <@&{}>.", role_id),
+                (Ok(()), false, "ja") => format!("<@&{}> を付けました。コンテストの通知でメンションされます。", role_id),
+                (Ok(()), false, _) => format!("Added <@&{}>. You will be mentioned by contest notifications.", role_id),
+            }
+        }
+    };
+    let response = CreateInteractionResponseMessage::default().content(content).ephemeral(true);
+    interaction.create_response(ctx, CreateInteractionResponse::Message(response)).await?;
+    Ok(())
+}
@@ -0,0 +1,177 @@
+use crate::routing::{self, RouteContestType, RouteEvent, Series};
+use crate::{Context, Error};
+
+use mysql::prelude::*;
+use mysql::*;
+
+use poise::{
+    serenity_prelude::{self as serenity, CreateEmbed, CreateEmbedAuthor},
+    CreateReply,
+};
+
+/// Send one kind of notification to a channel, optionally only for some contests.
+#[poise::command(prefix_command, slash_command, rename = "route")]
+pub async fn set_notification_route(
+    ctx: Context<'_>,
+    #[description = "kind of notification"] event: RouteEvent,
+    #[description = "notify channel"] channel: serenity::Channel,
+    #[description = "only for this contest type"] contest_type: Option<RouteContestType>,
+    #[description = "only for this series"] series: Option<Series>,
+) -> Result<(), Error> {
+    let pool = ctx.data().conn.lock().await;
+    let mut conn = pool.get_conn().unwrap();
+    let guild_id = ctx.guild_id().unwrap().to_string();
+    let channel_id = channel.id().get();
+
+    let selected_data: Vec<String> = conn.exec(
+        r"SELECT language FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => &guild_id},
+    )?;
+    let mut lang = "ja";
+    if selected_data.len() == 1 {
+        lang = selected_data[0].as_str();
+    }
+    let owners: Vec<u64> = conn
+        .exec(
+            "SELECT user_id FROM owners WHERE guild_id=:guild_id",
+            params! {
+                "guild_id" => ctx.guild_id().unwrap_or_default().get()
+            },
+        )
+        .unwrap();
+    let has_permission = if owners.is_empty() || owners.contains(&{ ctx.author().id.get() }) {
+        true
+    } else {
+        ctx.author().id.get() == ctx.guild().unwrap().owner_id.get()
+    };
+    if !has_permission {
+        if lang == "ja" {
+            let response = CreateReply::default().embed(CreateEmbed::default().title("エラー").description("権限がありません。")).ephemeral(true);
+            ctx.send(response).await?;
+        } else {
+            let response = CreateReply::default().embed(CreateEmbed::default().title("Error").description("You do not have permission.")).ephemeral(true);
+            ctx.send(response).await?;
+        }
+        return Ok(());
+    }
+
+    // The senders look for servers in `notifications`, so a server that only has routes still needs a row.
+    let count: Vec<i32> = conn.exec(
+        r"SELECT count(*) FROM notifications WHERE server_id=:server_id",
+        params! {"server_id" => &guild_id},
+    )?;
+    if count[0] == 0 {
+        conn.exec_drop(r"INSERT INTO notifications (server_id) VALUES (:server_id)", params! {"server_id" => &guild_id})?;
+    }
+
+    let route_params = params! {
+        "server_id" => &guild_id,
+        "event" => event.as_str(),
+        "channel_id" => channel_id,
+        "contest_type" => contest_type.map(RouteContestType::value),
+        "series" => series.map(Series::as_str),
+    };
+    let existing: Vec<u64> = conn.exec(
+        r"SELECT id FROM notification_routes
+        WHERE server_id=:server_id AND event=:event AND channel_id=:channel_id AND contest_type<=>:contest_type AND series<=>:series",
+        route_params.clone(),
+    )?;
+    if existing.is_empty() {
+        conn.exec_drop(
+            r"INSERT INTO notification_routes (server_id, event, channel_id, contest_type, series) VALUES (:server_id, :event, :channel_id, :contest_type, :series)",
+            route_params,
+        )?;
+    }
+
+    let response = {
+        let mut embed = serenity::CreateEmbed::default()
+            .author(CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(ctx.data().avatar_url.as_str()).url("https://atcoder-notify.com/"));
+        let filters = routing::filter_text(contest_type, series, lang);
+        if lang == "ja" {
+            embed = embed.title("設定変更").description(format!(
+                "{} ({}) を <#{}> に送信します。\n条件に合うルートがないコンテストは、これまでどおりコンテスト情報・ユーザー提出情報のチャンネルに送信します。",
+                event.label(lang),
+                filters,
+                channel_id
+            ));
+        } else {
+            embed = embed.title("Settings Changed").description(format!(
+                "{} ({}) will be sent to <#{}>.\nContests that match no route are still sent to the contest or submission channel.",
+                event.label(lang),
+                filters,
+                channel_id
+            ));
+        }
+        poise::CreateReply::default().embed(embed).ephemeral(true)
+    };
+
+    ctx.send(response).await?;
+
+    Ok(())
+}
+
+/// Remove the routes of one kind of notification, or only those to a channel.
+#[poise::command(prefix_command, slash_command, rename = "route")]
+pub async fn unset_notification_route(
+    ctx: Context<'_>,
+    #[description = "kind of notification"] event: RouteEvent,
+    #[description = "only the routes to this channel"] channel: Option<serenity::Channel>,
+) -> Result<(), Error> {
+    let pool = ctx.data().conn.lock().await;
+    let mut conn = pool.get_conn().unwrap();
+    let guild_id = ctx.guild_id().unwrap().to_string();
+
+    let selected_data: Vec<String> = conn.exec(
+        r"SELECT language FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => &guild_id},
+    )?;
+    let mut lang = "ja";
+    if selected_data.len() == 1 {
+        lang = selected_data[0].as_str();
+    }
+    let owners: Vec<u64> = conn
+        .exec(
+            "SELECT user_id FROM owners WHERE guild_id=:guild_id",
+            params! {
+                "guild_id" => ctx.guild_id().unwrap_or_default().get()
+            },
+        )
+        .unwrap();
+    let has_permission = if owners.is_empty() || owners.contains(&{ ctx.author().id.get() }) {
+        true
+    } else {
+        ctx.author().id.get() == ctx.guild().unwrap().owner_id.get()
+    };
+    if !has_permission {
+        if lang == "ja" {
+            let response = CreateReply::default().embed(CreateEmbed::default().title("エラー").description("権限がありません。")).ephemeral(true);
+            ctx.send(response).await?;
+        } else {
+            let response = CreateReply::default().embed(CreateEmbed::default().title("Error").description("You do not have permission.")).ephemeral(true);
+            ctx.send(response).await?;
+        }
+        return Ok(());
+    }
+
+    let channel_id = channel.map(|channel| channel.id().get());
+    conn.exec_drop(
+        r"DELETE FROM notification_routes WHERE server_id=:server_id AND event=:event AND (:channel_id IS NULL OR channel_id=:channel_id)",
+        params! {"server_id" => &guild_id, "event" => event.as_str(), "channel_id" => channel_id},
+    )?;
+    let removed = conn.affected_rows();
+
+    let response = {
+        let mut embed = serenity::CreateEmbed::default()
+            .author(CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(ctx.data().avatar_url.as_str()).url("https://atcoder-notify.com/"));
+        if lang == "ja" {
+            embed = embed.title("設定変更").description(format!("{}のルートを{}件削除しました。", event.label(lang), removed));
+        } else {
+            embed = embed.title("Settings Changed").description(format!("Removed {} routes of the {}.", removed, event.label(lang)));
+        }
+        poise::CreateReply::default().embed(embed).ephemeral(true)
+    };
+
+    ctx.send(response).await?;
+
+    Ok(())
+}
@@ -0,0 +1,161 @@
+use crate::timezone;
+use crate::{Context, Error};
+
+use mysql::prelude::*;
+use mysql::*;
+
+use chrono::NaiveTime;
+
+use poise::{
+    serenity_prelude::{self as serenity, CreateEmbed, CreateEmbedAuthor},
+    CreateReply,
+};
+
+/// Set the quiet hours during which notifications are held and sent later as one summary.
+#[poise::command(prefix_command, slash_command, rename = "set-quiet-hours")]
+pub async fn set_quiet_hours(
+    ctx: Context<'_>,
+    #[description = "enabled"] enabled: bool,
+    #[description = "start (HH:MM, default: 23:00)"] start: Option<String>,
+    #[description = "end (HH:MM, default: 07:00)"] end: Option<String>,
+    #[description = "also hold contest reminders and live standings (default: False)"] hold_reminders: Option<bool>,
+) -> Result<(), Error> {
+    let pool = ctx.data().conn.lock().await.clone();
+    let mut conn = pool.get_conn().unwrap();
+    let guild_id = ctx.guild_id().unwrap().to_string();
+
+    let selected_data: Vec<String> = conn.exec(
+        r"SELECT language FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => &guild_id},
+    )?;
+    let mut lang = "ja";
+    if selected_data.len() == 1 {
+        lang = selected_data[0].as_str();
+    }
+
+    let owners: Vec<u64> = conn
+        .exec(
+            "SELECT user_id FROM owners WHERE guild_id=:guild_id",
+            params! {
+                "guild_id" => ctx.guild_id().unwrap_or_default().get()
+            },
+        )
+        .unwrap();
+    let has_permission = if owners.is_empty() || owners.contains(&{ ctx.author().id.get() }) {
+        true
+    } else {
+        ctx.author().id.get() == ctx.guild().unwrap().owner_id.get()
+    };
+    if !has_permission {
+        if lang == "ja" {
+            let response = CreateReply::default().embed(CreateEmbed::default().title("エラー").description("権限がありません。")).ephemeral(true);
+            ctx.send(response).await?;
+        } else {
+            let response = CreateReply::default().embed(CreateEmbed::default().title("Error").description("You do not have permission.")).ephemeral(true);
+            ctx.send(response).await?;
+        }
+        return Ok(());
+    }
+
+    let parse_time = |time: Option<String>, default: NaiveTime| match time.as_deref().map(|time| NaiveTime::parse_from_str(time.trim(), "%H:%M")) {
+        None => Some(default),
+        Some(Ok(time)) => Some(time),
+        Some(Err(_)) => None,
+    };
+    let start = parse_time(start, NaiveTime::from_hms_opt(23, 0, 0).unwrap());
+    let end = parse_time(end, NaiveTime::from_hms_opt(7, 0, 0).unwrap());
+    let (start, end) = match (start, end) {
+        (Some(start), Some(end)) if start != end => (start, end),
+        (Some(_), Some(_)) => {
+            let description = if lang == "ja" {
+                "開始時刻と終了時刻には異なる時刻を入力してください。"
+            } else {
+                "Please enter different start and end times."
+            };
+            let title = if lang == "ja" { "エラー" } else { "Error" };
+            ctx.send(CreateReply::default().embed(CreateEmbed::default().title(title).description(description)).ephemeral(true)).await?;
+            return Ok(());
+        }
+        _ => {
+            let description = if lang == "ja" {
+                "時刻は `HH:MM` の形式で入力してください。"
+            } else {
+                "Please enter the time in the `HH:MM` format."
+            };
+            let title = if lang == "ja" { "エラー" } else { "Error" };
+            ctx.send(CreateReply::default().embed(CreateEmbed::default().title(title).description(description)).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    let hold_reminders = if hold_reminders.unwrap_or(false) { 1 } else { 0 };
+    let timezone = timezone::server_timezone(&mut conn, ctx.guild_id().unwrap().get())?;
+
+    let count: Vec<i32> = conn.exec(
+        r"SELECT count(*) FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => &guild_id},
+    )?;
+
+    // Turning quiet hours off leaves the held messages to the next release, which sends them right away.
+    let quiet_params = params! {
+        "server_id" => &guild_id,
+        "start" => if enabled { Some(start) } else { None },
+        "end" => if enabled { Some(end) } else { None },
+        "hold_reminders" => hold_reminders,
+    };
+    if count[0] == 0 {
+        conn.exec_drop(
+            r"INSERT INTO server_settings (server_id, quiet_hours_start, quiet_hours_end, quiet_hours_hold_reminders) VALUES (:server_id, :start, :end, :hold_reminders)",
+            quiet_params,
+        )?;
+    } else {
+        conn.exec_drop(
+            r"UPDATE server_settings SET quiet_hours_start=:start, quiet_hours_end=:end, quiet_hours_hold_reminders=:hold_reminders WHERE server_id=:server_id",
+            quiet_params,
+        )?;
+    }
+
+    let response = {
+        let mut embed = serenity::CreateEmbed::default()
+            .author(CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(ctx.data().avatar_url.as_str()).url("https://atcoder-notify.com/"));
+        if lang == "ja" {
+            let description = if enabled {
+                format!(
+                    "毎日 {}〜{} ({}) は通知を保留し、終了時にまとめて送信します。\n{}",
+                    start.format("%H:%M"),
+                    end.format("%H:%M"),
+                    timezone.name(),
+                    if hold_reminders == 1 {
+                        "コンテストの1時間前通知とサーバー内ランキングも保留します。"
+                    } else {
+                        "コンテストの1時間前通知とサーバー内ランキングはすぐに送信します。"
+                    }
+                )
+            } else {
+                "静かな時間帯を解除しました。保留中の通知はまもなく送信されます。".to_string()
+            };
+            embed = embed.title("設定変更").description(description);
+        } else {
+            let description = if enabled {
+                format!(
+                    "Notifications will be held every day from {} to {} ({}) and sent as one summary when the quiet hours end.\n{}",
+                    start.format("%H:%M"),
+                    end.format("%H:%M"),
+                    timezone.name(),
+                    if hold_reminders == 1 {
+                        "Contest reminders and live standings are held too."
+                    } else {
+                        "Contest reminders and live standings are still sent right away."
+                    }
+                )
+            } else {
+                "Turned off quiet hours. Held notifications will be sent shortly.".to_string()
+            };
+            embed = embed.title("Settings Changed").description(description);
+        }
+        poise::CreateReply::default().embed(embed).ephemeral(true)
+    };
+
+    ctx.send(response).await?;
+
+    Ok(())
+}
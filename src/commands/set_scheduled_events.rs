@@ -0,0 +1,110 @@
+use crate::scheduled_events;
+use crate::{Context, Error};
+
+use mysql::prelude::*;
+use mysql::*;
+
+use poise::{
+    serenity_prelude::{self as serenity, CreateEmbed, CreateEmbedAuthor},
+    CreateReply,
+};
+
+/// Set whether a Discord event is created for each upcoming contest.
+#[poise::command(prefix_command, slash_command, rename = "set-scheduled-events")]
+pub async fn set_scheduled_events(ctx: Context<'_>, #[description = "scheduled_events"] scheduled_events: bool) -> Result<(), Error> {
+    let pool = ctx.data().conn.lock().await.clone();
+    let mut conn = pool.get_conn().unwrap();
+    let guild_id = ctx.guild_id().unwrap().to_string();
+
+    let scheduled_events = if scheduled_events { 1 } else { 0 };
+
+    let selected_data: Vec<String> = conn.exec(
+        r"SELECT language FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => &guild_id},
+    )?;
+    let mut lang = "ja";
+    if selected_data.len() == 1 {
+        lang = selected_data[0].as_str();
+    }
+
+    let owners: Vec<u64> = conn
+        .exec(
+            "SELECT user_id FROM owners WHERE guild_id=:guild_id",
+            params! {
+                "guild_id" => ctx.guild_id().unwrap_or_default().get()
+            },
+        )
+        .unwrap();
+    let has_permission = if owners.is_empty() || owners.contains(&{ ctx.author().id.get() }) {
+        true
+    } else {
+        ctx.author().id.get() == ctx.guild().unwrap().owner_id.get()
+    };
+    if !has_permission {
+        if lang == "ja" {
+            let response = CreateReply::default().embed(CreateEmbed::default().title("エラー").description("権限がありません。")).ephemeral(true);
+            ctx.send(response).await?;
+        } else {
+            let response = CreateReply::default().embed(CreateEmbed::default().title("Error").description("You do not have permission.")).ephemeral(true);
+            ctx.send(response).await?;
+        }
+        return Ok(());
+    }
+
+    let count: Vec<i32> = conn.exec(
+        r"SELECT count(*) FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => &guild_id},
+    )?;
+
+    if count[0] == 0 {
+        conn.exec_drop(
+            r"INSERT INTO server_settings (server_id, scheduled_events) VALUES (:server_id, :scheduled_events)",
+            params! {"server_id" => &guild_id, "scheduled_events" => scheduled_events},
+        )?;
+    } else {
+        conn.exec_drop(
+            r"UPDATE server_settings SET scheduled_events=:scheduled_events WHERE server_id=:server_id",
+            params! {"server_id" => &guild_id, "scheduled_events" => scheduled_events},
+        )?;
+    }
+
+    // Creating the events of every upcoming contest takes a while.
+    ctx.defer_ephemeral().await?;
+    let created = if scheduled_events == 1 {
+        scheduled_events::create_events(&mut conn, ctx.http(), Some(ctx.guild_id().unwrap().get())).await?
+    } else {
+        scheduled_events::delete_events(&mut conn, ctx.http(), ctx.guild_id().unwrap().get()).await?;
+        0
+    };
+
+    let response = {
+        let mut embed = serenity::CreateEmbed::default()
+            .author(CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(ctx.data().avatar_url.as_str()).url("https://atcoder-notify.com/"));
+        if lang == "ja" {
+            let description = if scheduled_events == 0 {
+                "コンテストのイベント作成を無効にし、作成済みのイベントを削除しました。".to_string()
+            } else {
+                format!(
+                    "コンテストのイベント作成を有効にしました。{}件のイベントを作成しました。\nイベントが作成されない場合は、Botにイベントの作成権限があるか確認してください。",
+                    created
+                )
+            };
+            embed = embed.title("設定変更").description(description);
+        } else {
+            let description = if scheduled_events == 0 {
+                "Disabled events for contests and deleted the events created so far.".to_string()
+            } else {
+                format!(
+                    "Enabled events for contests. Created {} events.\nIf no event is created, please check that the bot can create events.",
+                    created
+                )
+            };
+            embed = embed.title("Settings Changed").description(description);
+        }
+        poise::CreateReply::default().embed(embed).ephemeral(true)
+    };
+
+    ctx.send(response).await?;
+
+    Ok(())
+}
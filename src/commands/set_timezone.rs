@@ -0,0 +1,135 @@
+use crate::commands::autocomplete::autocomplete_timezone;
+use crate::timezone::parse_timezone;
+use crate::{Context, Error};
+
+use mysql::prelude::*;
+use mysql::*;
+
+use chrono::NaiveTime;
+
+use poise::{
+    serenity_prelude::{self as serenity, CreateEmbed, CreateEmbedAuthor},
+    CreateReply,
+};
+
+/// Set the time zone of the server and the time of the daily contest digest.
+#[poise::command(prefix_command, slash_command, rename = "set-timezone")]
+pub async fn set_timezone(
+    ctx: Context<'_>,
+    #[description = "IANA time zone, such as Asia/Tokyo"]
+    #[autocomplete = "autocomplete_timezone"]
+    timezone: String,
+    #[description = "time of the daily digest (HH:MM, default: unchanged)"] digest_time: Option<String>,
+) -> Result<(), Error> {
+    let pool = ctx.data().conn.lock().await.clone();
+    let mut conn = pool.get_conn().unwrap();
+    let guild_id = ctx.guild_id().unwrap().to_string();
+
+    let selected_data: Vec<String> = conn.exec(
+        r"SELECT language FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => &guild_id},
+    )?;
+    let mut lang = "ja";
+    if selected_data.len() == 1 {
+        lang = selected_data[0].as_str();
+    }
+
+    let owners: Vec<u64> = conn
+        .exec(
+            "SELECT user_id FROM owners WHERE guild_id=:guild_id",
+            params! {
+                "guild_id" => ctx.guild_id().unwrap_or_default().get()
+            },
+        )
+        .unwrap();
+    let has_permission = if owners.is_empty() || owners.contains(&{ ctx.author().id.get() }) {
+        true
+    } else {
+        ctx.author().id.get() == ctx.guild().unwrap().owner_id.get()
+    };
+    if !has_permission {
+        if lang == "ja" {
+            let response = CreateReply::default().embed(CreateEmbed::default().title("エラー").description("権限がありません。")).ephemeral(true);
+            ctx.send(response).await?;
+        } else {
+            let response = CreateReply::default().embed(CreateEmbed::default().title("Error").description("You do not have permission.")).ephemeral(true);
+            ctx.send(response).await?;
+        }
+        return Ok(());
+    }
+
+    let Some(timezone) = parse_timezone(&timezone) else {
+        let description = if lang == "ja" {
+            format!(
+                "`{}` はタイムゾーンとして読み取れません。`Asia/Tokyo` のようなIANAタイムゾーン名を入力してください。",
+                timezone
+            )
+        } else {
+            format!("`{}` is not a time zone. Please enter an IANA time zone name such as `Asia/Tokyo`.", timezone)
+        };
+        let title = if lang == "ja" { "エラー" } else { "Error" };
+        ctx.send(CreateReply::default().embed(CreateEmbed::default().title(title).description(description)).ephemeral(true)).await?;
+        return Ok(());
+    };
+    let digest_time = match digest_time.as_deref().map(|time| NaiveTime::parse_from_str(time.trim(), "%H:%M")) {
+        // Changing only the time zone keeps the digest time the server already has.
+        None => {
+            let stored: Option<NaiveTime> = conn.exec_first(
+                r"SELECT digest_time FROM server_settings WHERE server_id=:server_id",
+                params! {"server_id" => &guild_id},
+            )?;
+            stored.unwrap_or(NaiveTime::MIN)
+        }
+        Some(Ok(time)) => time,
+        Some(Err(_)) => {
+            let description = if lang == "ja" {
+                "時刻は `HH:MM` の形式で入力してください。"
+            } else {
+                "Please enter the time in the `HH:MM` format."
+            };
+            let title = if lang == "ja" { "エラー" } else { "Error" };
+            ctx.send(CreateReply::default().embed(CreateEmbed::default().title(title).description(description)).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+
+    let count: Vec<i32> = conn.exec(
+        r"SELECT count(*) FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => &guild_id},
+    )?;
+
+    if count[0] == 0 {
+        conn.exec_drop(
+            r"INSERT INTO server_settings (server_id, timezone, digest_time) VALUES (:server_id, :timezone, :digest_time)",
+            params! {"server_id" => &guild_id, "timezone" => timezone.name(), "digest_time" => digest_time},
+        )?;
+    } else {
+        conn.exec_drop(
+            r"UPDATE server_settings SET timezone=:timezone, digest_time=:digest_time WHERE server_id=:server_id",
+            params! {"server_id" => &guild_id, "timezone" => timezone.name(), "digest_time" => digest_time},
+        )?;
+    }
+
+    let response = {
+        let mut embed = serenity::CreateEmbed::default()
+            .author(CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(ctx.data().avatar_url.as_str()).url("https://atcoder-notify.com/"));
+        if lang == "ja" {
+            embed = embed.title("設定変更").description(format!(
+                "タイムゾーンを `{}` に変更しました。今日のコンテストは毎日 {} に送信されます。",
+                timezone.name(),
+                digest_time.format("%H:%M")
+            ));
+        } else {
+            embed = embed.title("Settings Changed").description(format!(
+                "Changed the time zone to `{}`. Today's contests will be sent every day at {}.",
+                timezone.name(),
+                digest_time.format("%H:%M")
+            ));
+        }
+        poise::CreateReply::default().embed(embed).ephemeral(true)
+    };
+
+    ctx.send(response).await?;
+
+    Ok(())
+}
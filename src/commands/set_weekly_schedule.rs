@@ -0,0 +1,157 @@
+use crate::timezone;
+use crate::{Context, Error};
+
+#[derive(Debug, Clone, Copy, poise::ChoiceParameter)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    /// Counting from Monday = 0, as stored in `server_settings.weekly_schedule_weekday`.
+    fn index(self) -> u32 {
+        self as u32
+    }
+
+    fn label(self, lang: &str) -> &'static str {
+        let ja = ["月曜日", "火曜日", "水曜日", "木曜日", "金曜日", "土曜日", "日曜日"];
+        let en = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+        if lang == "ja" {
+            ja[self.index() as usize]
+        } else {
+            en[self.index() as usize]
+        }
+    }
+}
+
+use mysql::prelude::*;
+use mysql::*;
+
+use chrono::NaiveTime;
+
+use poise::{
+    serenity_prelude::{self as serenity, CreateEmbed, CreateEmbedAuthor},
+    CreateReply,
+};
+
+/// Set the weekday and time of the weekly contest schedule, or turn it off.
+#[poise::command(prefix_command, slash_command, rename = "set-weekly-schedule")]
+pub async fn set_weekly_schedule(
+    ctx: Context<'_>,
+    #[description = "enabled"] enabled: bool,
+    #[description = "weekday (default: Monday)"] weekday: Option<Weekday>,
+    #[description = "time (HH:MM, default: 09:00)"] time: Option<String>,
+) -> Result<(), Error> {
+    let pool = ctx.data().conn.lock().await.clone();
+    let mut conn = pool.get_conn().unwrap();
+    let guild_id = ctx.guild_id().unwrap().to_string();
+
+    let selected_data: Vec<String> = conn.exec(
+        r"SELECT language FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => &guild_id},
+    )?;
+    let mut lang = "ja";
+    if selected_data.len() == 1 {
+        lang = selected_data[0].as_str();
+    }
+
+    let owners: Vec<u64> = conn
+        .exec(
+            "SELECT user_id FROM owners WHERE guild_id=:guild_id",
+            params! {
+                "guild_id" => ctx.guild_id().unwrap_or_default().get()
+            },
+        )
+        .unwrap();
+    let has_permission = if owners.is_empty() || owners.contains(&{ ctx.author().id.get() }) {
+        true
+    } else {
+        ctx.author().id.get() == ctx.guild().unwrap().owner_id.get()
+    };
+    if !has_permission {
+        if lang == "ja" {
+            let response = CreateReply::default().embed(CreateEmbed::default().title("エラー").description("権限がありません。")).ephemeral(true);
+            ctx.send(response).await?;
+        } else {
+            let response = CreateReply::default().embed(CreateEmbed::default().title("Error").description("You do not have permission.")).ephemeral(true);
+            ctx.send(response).await?;
+        }
+        return Ok(());
+    }
+
+    let weekday = weekday.unwrap_or(Weekday::Monday);
+    let time = match time.as_deref().map(|time| NaiveTime::parse_from_str(time.trim(), "%H:%M")) {
+        None => NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+        Some(Ok(time)) => time,
+        Some(Err(_)) => {
+            let description = if lang == "ja" {
+                "時刻は `HH:MM` の形式で入力してください。"
+            } else {
+                "Please enter the time in the `HH:MM` format."
+            };
+            let title = if lang == "ja" { "エラー" } else { "Error" };
+            ctx.send(CreateReply::default().embed(CreateEmbed::default().title(title).description(description)).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    let weekday_index = if enabled { Some(weekday.index()) } else { None };
+    let timezone = timezone::server_timezone(&mut conn, ctx.guild_id().unwrap().get())?;
+
+    let count: Vec<i32> = conn.exec(
+        r"SELECT count(*) FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => &guild_id},
+    )?;
+
+    // The schedule that is due right now, if any, was not asked for, so the first one is posted next week.
+    if count[0] == 0 {
+        conn.exec_drop(
+            r"INSERT INTO server_settings (server_id, weekly_schedule_weekday, weekly_schedule_time, weekly_schedule_sent_at) VALUES (:server_id, :weekday, :time, UTC_TIMESTAMP())",
+            params! {"server_id" => &guild_id, "weekday" => weekday_index, "time" => time},
+        )?;
+    } else {
+        conn.exec_drop(
+            r"UPDATE server_settings SET weekly_schedule_weekday=:weekday, weekly_schedule_time=:time, weekly_schedule_sent_at=UTC_TIMESTAMP() WHERE server_id=:server_id",
+            params! {"server_id" => &guild_id, "weekday" => weekday_index, "time" => time},
+        )?;
+    }
+
+    let response = {
+        let mut embed = serenity::CreateEmbed::default()
+            .author(CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(ctx.data().avatar_url.as_str()).url("https://atcoder-notify.com/"));
+        if lang == "ja" {
+            let description = if enabled {
+                format!(
+                    "毎週{} {} ({})に、今後7日間のコンテストをコンテスト情報チャンネルに送信します。",
+                    weekday.label(lang),
+                    time.format("%H:%M"),
+                    timezone.name()
+                )
+            } else {
+                "週間コンテスト予定の送信を停止しました。".to_string()
+            };
+            embed = embed.title("設定変更").description(description);
+        } else {
+            let description = if enabled {
+                format!(
+                    "The contests of the next 7 days will be sent to the contest channel every {} at {} ({}).",
+                    weekday.label(lang),
+                    time.format("%H:%M"),
+                    timezone.name()
+                )
+            } else {
+                "Stopped sending the weekly contest schedule.".to_string()
+            };
+            embed = embed.title("Settings Changed").description(description);
+        }
+        poise::CreateReply::default().embed(embed).ephemeral(true)
+    };
+
+    ctx.send(response).await?;
+
+    Ok(())
+}
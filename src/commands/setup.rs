@@ -0,0 +1,455 @@
+use std::time::Duration;
+
+use crate::commands::role::create_rating_roles;
+use crate::commands::server_config::{load_server_config, NotificationConfig, ServerSettingsConfig};
+use crate::components::ComponentRequest;
+use crate::mentions::{self, MentionTarget, NotificationKind};
+use crate::{Context, Data, Error};
+
+use mysql::prelude::*;
+use mysql::*;
+
+use poise::{
+    serenity_prelude::{
+        self as serenity, ChannelType, ComponentInteraction, ComponentInteractionCollector, ComponentInteractionDataKind, CreateActionRow, CreateButton,
+        CreateEmbed, CreateEmbedAuthor, CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage, CreateSelectMenu, CreateSelectMenuKind,
+        CreateSelectMenuOption, EditInteractionResponse, GuildId, MessageId, UserId,
+    },
+    BoxFuture, CreateReply,
+};
+
+/// Custom id of the button on the welcome message that starts the wizard.
+const SETUP_BUTTON_ID: &str = "setup:start";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SetupStep {
+    Language,
+    ContestChannel,
+    SubmissionChannel,
+    AcNotify,
+    Everyone,
+    Roles,
+}
+
+struct SetupChoices {
+    settings: ServerSettingsConfig,
+    notifications: NotificationConfig,
+    reminder_mention: MentionTarget,
+    /// `None` when the server already has rating roles, in which case the step is not shown.
+    create_roles: Option<bool>,
+}
+
+fn load_choices(conn: &mut PooledConn, guild_id: GuildId) -> Result<SetupChoices> {
+    let config = load_server_config(conn, guild_id.get())?;
+    Ok(SetupChoices {
+        reminder_mention: config.mention(NotificationKind::Reminder),
+        settings: config.settings,
+        notifications: config.notifications,
+        create_roles: if config.roles.is_empty() { Some(false) } else { None },
+    })
+}
+
+fn has_permission(conn: &mut PooledConn, guild_id: GuildId, user_id: UserId, owner_id: UserId) -> Result<bool> {
+    let owners: Vec<u64> = conn.exec("SELECT user_id FROM owners WHERE guild_id=:guild_id", params! {"guild_id" => guild_id.get()})?;
+    Ok(owners.is_empty() || owners.contains(&user_id.get()) || user_id == owner_id)
+}
+
+fn skip_button(lang: &str) -> CreateButton {
+    CreateButton::new("server_setup_skip").label(if lang == "ja" { "変更しない" } else { "Keep current" }).style(serenity::ButtonStyle::Secondary)
+}
+
+fn cancel_button(lang: &str) -> CreateButton {
+    CreateButton::new("server_setup_cancel").label(if lang == "ja" { "中止" } else { "Cancel" }).style(serenity::ButtonStyle::Danger)
+}
+
+fn step_page(step: SetupStep, index: usize, total: usize, lang: &str, avatar_url: &str) -> (CreateEmbed, Vec<CreateActionRow>) {
+    let channel_menu = || {
+        CreateActionRow::SelectMenu(CreateSelectMenu::new(
+            "server_setup_channel",
+            CreateSelectMenuKind::Channel {
+                channel_types: Some(vec![ChannelType::Text, ChannelType::News]),
+                default_channels: None,
+            },
+        ))
+    };
+    let (description, components) = match step {
+        SetupStep::Language => (
+            "Botの言語を選んでください。\nChoose the language of the bot.",
+            vec![
+                CreateActionRow::SelectMenu(CreateSelectMenu::new(
+                    "server_setup_language",
+                    CreateSelectMenuKind::String {
+                        options: vec![CreateSelectMenuOption::new("日本語", "ja"), CreateSelectMenuOption::new("English", "en")],
+                    },
+                )),
+                CreateActionRow::Buttons(vec![skip_button(lang), cancel_button(lang)]),
+            ],
+        ),
+        SetupStep::ContestChannel => (
+            if lang == "ja" {
+                "コンテスト情報を通知するチャンネルを選んでください。"
+            } else {
+                "Choose the channel for contest information."
+            },
+            vec![channel_menu(), CreateActionRow::Buttons(vec![skip_button(lang), cancel_button(lang)])],
+        ),
+        SetupStep::SubmissionChannel => (
+            if lang == "ja" {
+                "ユーザーの提出を通知するチャンネルを選んでください。"
+            } else {
+                "Choose the channel for user submissions."
+            },
+            vec![channel_menu(), CreateActionRow::Buttons(vec![skip_button(lang), cancel_button(lang)])],
+        ),
+        SetupStep::AcNotify => (
+            if lang == "ja" {
+                "どのACを通知しますか？"
+            } else {
+                "Which ACs should be notified?"
+            },
+            vec![CreateActionRow::Buttons(vec![
+                CreateButton::new("server_setup_ac_all").label(if lang == "ja" { "すべて" } else { "All" }),
+                CreateButton::new("server_setup_ac_unique").label(if lang == "ja" { "Unique AC のみ" } else { "Unique AC only" }),
+                cancel_button(lang),
+            ])],
+        ),
+        SetupStep::Everyone => (
+            if lang == "ja" {
+                "コンテストの通知で @everyone をメンションしますか？"
+            } else {
+                "Should contest notifications mention @everyone?"
+            },
+            vec![CreateActionRow::Buttons(vec![
+                CreateButton::new("server_setup_everyone_on").label(if lang == "ja" { "オン" } else { "On" }),
+                CreateButton::new("server_setup_everyone_off").label(if lang == "ja" { "オフ" } else { "Off" }),
+                skip_button(lang),
+                cancel_button(lang),
+            ])],
+        ),
+        SetupStep::Roles => (
+            if lang == "ja" {
+                "レーティングの色ロールを作成しますか？"
+            } else {
+                "Create the rating color roles?"
+            },
+            vec![CreateActionRow::Buttons(vec![
+                CreateButton::new("server_setup_roles_create").label(if lang == "ja" { "作成する" } else { "Create" }).style(serenity::ButtonStyle::Success),
+                CreateButton::new("server_setup_roles_skip").label(if lang == "ja" { "作成しない" } else { "Skip" }).style(serenity::ButtonStyle::Secondary),
+                cancel_button(lang),
+            ])],
+        ),
+    };
+    let embed = CreateEmbed::default()
+        .author(CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(avatar_url).url("https://atcoder-notify.com/"))
+        .title(format!(
+            "{} ({}/{})",
+            if lang == "ja" { "サーバー設定" } else { "Server Setup" },
+            index + 1,
+            total
+        ))
+        .description(description);
+    (embed, components)
+}
+
+fn record_choice(step: SetupStep, interaction: &ComponentInteraction, choices: &mut SetupChoices) {
+    let custom_id = interaction.data.custom_id.as_str();
+    match (step, &interaction.data.kind) {
+        (SetupStep::Language, ComponentInteractionDataKind::StringSelect { values }) => {
+            if let Some(language) = values.first() {
+                choices.settings.language.clone_from(language);
+            }
+        }
+        (SetupStep::ContestChannel, ComponentInteractionDataKind::ChannelSelect { values }) => {
+            choices.notifications.contest_channel_id = values.first().map(|channel| channel.get()).or(choices.notifications.contest_channel_id);
+        }
+        (SetupStep::SubmissionChannel, ComponentInteractionDataKind::ChannelSelect { values }) => {
+            choices.notifications.submission_channel_id = values.first().map(|channel| channel.get()).or(choices.notifications.submission_channel_id);
+        }
+        (SetupStep::AcNotify, _) => match custom_id {
+            "server_setup_ac_all" => choices.settings.ac_notify = 0,
+            "server_setup_ac_unique" => choices.settings.ac_notify = 1,
+            _ => {}
+        },
+        (SetupStep::Everyone, _) => match custom_id {
+            "server_setup_everyone_on" => choices.reminder_mention = MentionTarget::Everyone,
+            "server_setup_everyone_off" => choices.reminder_mention = MentionTarget::None,
+            _ => {}
+        },
+        (SetupStep::Roles, _) => choices.create_roles = Some(custom_id == "server_setup_roles_create"),
+        _ => {}
+    }
+}
+
+fn save_choices(conn: &mut PooledConn, guild_id: GuildId, choices: &SetupChoices) -> Result<()> {
+    let guild_id = guild_id.get();
+    let count: Vec<i32> = conn.exec(
+        r"SELECT count(*) FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => guild_id},
+    )?;
+    if count[0] == 0 {
+        conn.exec_drop(
+            r"INSERT INTO server_settings (server_id, language, ac_notify) VALUES (:server_id, :language, :ac_notify)",
+            params! {"server_id" => guild_id, "language" => &choices.settings.language, "ac_notify" => choices.settings.ac_notify},
+        )?;
+    } else {
+        conn.exec_drop(
+            r"UPDATE server_settings SET language=:language, ac_notify=:ac_notify WHERE server_id=:server_id",
+            params! {"server_id" => guild_id, "language" => &choices.settings.language, "ac_notify" => choices.settings.ac_notify},
+        )?;
+    }
+    mentions::save_mention(conn, guild_id, NotificationKind::Reminder, choices.reminder_mention)?;
+
+    let count: Vec<i32> = conn.exec(
+        r"SELECT count(*) FROM notifications WHERE server_id=:server_id",
+        params! {"server_id" => guild_id},
+    )?;
+    let params = params! {
+        "server_id" => guild_id,
+        "contest_channel_id" => choices.notifications.contest_channel_id,
+        "submission_channel_id" => choices.notifications.submission_channel_id,
+    };
+    if count[0] == 0 {
+        conn.exec_drop(
+            r"INSERT INTO notifications (server_id, contest_channel_id, submission_channel_id) VALUES (:server_id, :contest_channel_id, :submission_channel_id)",
+            params,
+        )?;
+    } else {
+        conn.exec_drop(
+            r"UPDATE notifications SET contest_channel_id=:contest_channel_id, submission_channel_id=:submission_channel_id WHERE server_id=:server_id",
+            params,
+        )?;
+    }
+    Ok(())
+}
+
+fn summary_embed(choices: &SetupChoices, roles_result: Option<&Result<(), Error>>, avatar_url: &str) -> CreateEmbed {
+    let lang = choices.settings.language.as_str();
+    let channel_text = |channel_id: Option<u64>| match channel_id {
+        Some(channel_id) => format!("<#{}>", channel_id),
+        None => if lang == "ja" { "未設定" } else { "Not Set" }.to_string(),
+    };
+    let roles_text = match (roles_result, lang) {
+        (None, "ja") => "変更なし".to_string(),
+        (None, _) => "Unchanged".to_string(),
+        (Some(Ok(())), "ja") => "作成しました".to_string(),
+        (Some(Ok(())), _) => "Created".to_string(),
+        (Some(Err(err)), "ja") => format!("作成に失敗しました: {}", err),
+        (Some(Err(err)), _) => format!("Could not be created: {}", err),
+    };
+    let embed = CreateEmbed::default().author(CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(avatar_url).url("https://atcoder-notify.com/"));
+    if lang == "ja" {
+        embed
+            .title("設定が完了しました")
+            .field("言語", &choices.settings.language, true)
+            .field("AC通知", if choices.settings.ac_notify == 0 { "すべて" } else { "Unique AC のみ" }, true)
+            .field(NotificationKind::Reminder.label(lang), choices.reminder_mention.label(lang), true)
+            .field("コンテスト情報", channel_text(choices.notifications.contest_channel_id), true)
+            .field("ユーザー提出情報", channel_text(choices.notifications.submission_channel_id), true)
+            .field("ロール", roles_text, true)
+            .footer(serenity::CreateEmbedFooter::new(
+                "各項目は /server や /atcoder のコマンドで後から変更できます。",
+            ))
+    } else {
+        embed
+            .title("Setup Complete")
+            .field("Language", &choices.settings.language, true)
+            .field("AC notification", if choices.settings.ac_notify == 0 { "All" } else { "Unique AC only" }, true)
+            .field(NotificationKind::Reminder.label(lang), choices.reminder_mention.label(lang), true)
+            .field("Contest Information", channel_text(choices.notifications.contest_channel_id), true)
+            .field("User Submission Information", channel_text(choices.notifications.submission_channel_id), true)
+            .field("Roles", roles_text, true)
+            .footer(serenity::CreateEmbedFooter::new(
+                "Each setting can be changed later with the /server and /atcoder commands.",
+            ))
+    }
+}
+
+fn setup_steps(choices: &SetupChoices) -> Vec<SetupStep> {
+    let mut steps = vec![
+        SetupStep::Language,
+        SetupStep::ContestChannel,
+        SetupStep::SubmissionChannel,
+        SetupStep::AcNotify,
+        SetupStep::Everyone,
+    ];
+    if choices.create_roles.is_some() {
+        steps.push(SetupStep::Roles);
+    }
+    steps
+}
+
+/// Drive the wizard once its first page has been posted as `message_id`. Every later page is shown by
+/// answering the component interaction of the previous one, which also works for ephemeral messages.
+async fn run_setup_wizard(
+    ctx: &serenity::Context,
+    pool: &Pool,
+    avatar_url: &str,
+    guild_id: GuildId,
+    user_id: UserId,
+    message_id: MessageId,
+    mut choices: SetupChoices,
+) -> Result<(), Error> {
+    let steps = setup_steps(&choices);
+    let mut last_interaction: Option<ComponentInteraction> = None;
+    for (index, step) in steps.iter().enumerate() {
+        let interaction = ComponentInteractionCollector::new(ctx).author_id(user_id).message_id(message_id).timeout(Duration::from_secs(300)).await;
+        let lang = choices.settings.language.clone();
+        let Some(interaction) = interaction else {
+            if let Some(last_interaction) = last_interaction {
+                let response = EditInteractionResponse::new()
+                    .content(if lang == "ja" { "タイムアウトしました。" } else { "Timed out." })
+                    .embeds(vec![])
+                    .components(vec![]);
+                last_interaction.edit_response(ctx, response).await?;
+            }
+            return Ok(());
+        };
+        if interaction.data.custom_id == "server_setup_cancel" {
+            let response = CreateInteractionResponseMessage::default()
+                .content(if lang == "ja" {
+                    "設定を中止しました。"
+                } else {
+                    "Setup was cancelled."
+                })
+                .embeds(vec![])
+                .components(vec![]);
+            interaction.create_response(ctx, CreateInteractionResponse::UpdateMessage(response)).await?;
+            return Ok(());
+        }
+        record_choice(*step, &interaction, &mut choices);
+        let lang = choices.settings.language.as_str();
+
+        if let Some(next) = steps.get(index + 1) {
+            let (embed, components) = step_page(*next, index + 1, steps.len(), lang, avatar_url);
+            interaction
+                .create_response(
+                    ctx,
+                    CreateInteractionResponse::UpdateMessage(CreateInteractionResponseMessage::default().embed(embed).components(components)),
+                )
+                .await?;
+            last_interaction = Some(interaction);
+            continue;
+        }
+
+        // Creating roles can take longer than the 3 seconds Discord waits for a response.
+        interaction.create_response(ctx, CreateInteractionResponse::Acknowledge).await?;
+        let mut conn = pool.get_conn()?;
+        save_choices(&mut conn, guild_id, &choices)?;
+        let roles_result = match choices.create_roles {
+            Some(true) => Some(create_rating_roles(&ctx.http, &mut conn, guild_id).await),
+            _ => None,
+        };
+        let response = EditInteractionResponse::new().embed(summary_embed(&choices, roles_result.as_ref(), avatar_url)).components(vec![]);
+        interaction.edit_response(ctx, response).await?;
+    }
+    Ok(())
+}
+
+/// Set up notifications, language and rating roles step by step.
+#[poise::command(prefix_command, slash_command, rename = "setup")]
+pub async fn server_setup(ctx: Context<'_>) -> Result<(), Error> {
+    // The wizard waits for user input, so the pool is cloned instead of holding the lock.
+    let pool = ctx.data().conn.lock().await.clone();
+    let mut conn = pool.get_conn().unwrap();
+    let guild_id = ctx.guild_id().unwrap();
+
+    let choices = load_choices(&mut conn, guild_id)?;
+    let lang = choices.settings.language.clone();
+
+    let owner_id = ctx.guild().unwrap().owner_id;
+    if !has_permission(&mut conn, guild_id, ctx.author().id, owner_id)? {
+        if lang == "ja" {
+            let response = CreateReply::default().embed(CreateEmbed::default().title("エラー").description("権限がありません。")).ephemeral(true);
+            ctx.send(response).await?;
+        } else {
+            let response = CreateReply::default().embed(CreateEmbed::default().title("Error").description("You do not have permission.")).ephemeral(true);
+            ctx.send(response).await?;
+        }
+        return Ok(());
+    }
+
+    let steps = setup_steps(&choices);
+    let (embed, components) = step_page(steps[0], 0, steps.len(), &lang, &ctx.data().avatar_url);
+    let handle = ctx.send(CreateReply::default().embed(embed).components(components).ephemeral(true)).await?;
+    let message_id = handle.message().await?.id;
+
+    run_setup_wizard(
+        ctx.serenity_context(),
+        &pool,
+        &ctx.data().avatar_url,
+        guild_id,
+        ctx.author().id,
+        message_id,
+        choices,
+    )
+    .await
+}
+
+/// Start the wizard from the button on the welcome message.
+pub fn handle_component<'a>(
+    ctx: &'a serenity::Context,
+    data: &'a Data,
+    interaction: &'a ComponentInteraction,
+    request: ComponentRequest<'a>,
+) -> BoxFuture<'a, Result<(), Error>> {
+    Box::pin(async move {
+        match request.action {
+            "start" => start_setup_from_button(ctx, data, interaction).await,
+            _ => Ok(()),
+        }
+    })
+}
+
+async fn start_setup_from_button(ctx: &serenity::Context, data: &Data, interaction: &ComponentInteraction) -> Result<(), Error> {
+    let Some(guild_id) = interaction.guild_id else {
+        return Ok(());
+    };
+    let pool = data.conn.lock().await.clone();
+    let mut conn = pool.get_conn()?;
+
+    let choices = load_choices(&mut conn, guild_id)?;
+    let lang = choices.settings.language.clone();
+
+    let owner_id = guild_id.to_partial_guild(&ctx.http).await?.owner_id;
+    if !has_permission(&mut conn, guild_id, interaction.user.id, owner_id)? {
+        let response = if lang == "ja" {
+            CreateInteractionResponseMessage::default().embed(CreateEmbed::default().title("エラー").description("権限がありません。"))
+        } else {
+            CreateInteractionResponseMessage::default().embed(CreateEmbed::default().title("Error").description("You do not have permission."))
+        };
+        interaction.create_response(ctx, CreateInteractionResponse::Message(response.ephemeral(true))).await?;
+        return Ok(());
+    }
+
+    let steps = setup_steps(&choices);
+    let (embed, components) = step_page(steps[0], 0, steps.len(), &lang, &data.avatar_url);
+    let response = CreateInteractionResponseMessage::default().embed(embed).components(components).ephemeral(true);
+    interaction.create_response(ctx, CreateInteractionResponse::Message(response)).await?;
+    let message_id = interaction.get_response(&ctx.http).await?.id;
+
+    run_setup_wizard(ctx, &pool, &data.avatar_url, guild_id, interaction.user.id, message_id, choices).await
+}
+
+/// Greet a newly joined server and offer the setup wizard.
+pub async fn send_welcome_message(ctx: &serenity::Context, data: &Data, guild: &serenity::Guild) {
+    let embed = CreateEmbed::default()
+        .author(CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(data.avatar_url.as_str()).url("https://atcoder-notify.com/"))
+        .title("AtCoder Notify Bot")
+        .description(
+            "追加ありがとうございます！下のボタンか `/server setup` で通知チャンネルや言語を設定できます。\n\
+             Thanks for adding the bot! Use the button below or `/server setup` to configure notifications and the language.",
+        );
+    let message = CreateMessage::new().embed(embed).components(vec![CreateActionRow::Buttons(vec![CreateButton::new(SETUP_BUTTON_ID)
+        .label("Setup / 設定")
+        .style(serenity::ButtonStyle::Primary)])]);
+
+    let mut channels: Vec<&serenity::GuildChannel> = guild.channels.values().filter(|channel| channel.kind == ChannelType::Text).collect();
+    channels.sort_by_key(|channel| channel.position);
+    let candidates = guild.system_channel_id.into_iter().chain(channels.into_iter().map(|channel| channel.id));
+    for channel_id in candidates.take(5) {
+        match channel_id.send_message(&ctx.http, message.clone()).await {
+            Ok(_) => return,
+            Err(err) => log::info!("could not send the welcome message to {}: {}", channel_id, err),
+        }
+    }
+}
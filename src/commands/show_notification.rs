@@ -1,3 +1,4 @@
+use crate::routing;
 use crate::{Context, Error};
 
 use mysql::prelude::*;
@@ -5,14 +6,14 @@ use mysql::*;
 
 use poise::serenity_prelude::{self as serenity, CreateEmbedAuthor};
 
-/// Display the currently set notification channels for contests and submissions.
+/// Display the notification channels and routes for contests and submissions.
 #[poise::command(prefix_command, slash_command, rename = "show-notification")]
 pub async fn show_notification(ctx: Context<'_>) -> Result<(), Error> {
     let pool = ctx.data().conn.lock().await;
     let mut conn = pool.get_conn().unwrap();
     let guild_id = ctx.guild_id().unwrap().to_string();
 
-    let settings: Vec<(String, String)> = conn.exec(
+    let settings: Vec<(Option<String>, Option<String>)> = conn.exec(
         r"SELECT contest_channel_id,submission_channel_id FROM notifications WHERE server_id=:server_id",
         params! {"server_id" => &guild_id},
     )?;
@@ -26,11 +27,27 @@ pub async fn show_notification(ctx: Context<'_>) -> Result<(), Error> {
         lang = selected_data[0].as_str();
     }
 
+    let routes = routing::server_routes(&mut conn, ctx.guild_id().unwrap().get())?;
+    // Discord rejects field values longer than 1024 characters.
+    let mut routes_text = String::new();
+    for route in &routes {
+        let line = format!("{}\n", route.text(lang));
+        if routes_text.chars().count() + line.chars().count() > 1024 {
+            break;
+        }
+        routes_text += &line;
+    }
+
     let mut contest_channel_id = "null".to_string();
     let mut submission_channel_id = "null".to_string();
     if !settings.is_empty() {
-        contest_channel_id.clone_from(&settings[0].0);
-        submission_channel_id.clone_from(&settings[0].1);
+        // Servers that only have routes have a row without channels.
+        if let Some(channel_id) = &settings[0].0 {
+            contest_channel_id.clone_from(channel_id);
+        }
+        if let Some(channel_id) = &settings[0].1 {
+            submission_channel_id.clone_from(channel_id);
+        }
     }
 
     let response = {
@@ -48,6 +65,9 @@ pub async fn show_notification(ctx: Context<'_>) -> Result<(), Error> {
             } else {
                 embed = embed.field("ユーザー提出情報", format!("<#{}>", submission_channel_id), false);
             }
+            if !routes.is_empty() {
+                embed = embed.field("通知ルート", &routes_text, false);
+            }
         } else {
             embed = embed.title("Current Server Settings");
             if contest_channel_id == "null" {
@@ -60,6 +80,9 @@ pub async fn show_notification(ctx: Context<'_>) -> Result<(), Error> {
             } else {
                 embed = embed.field("User Submission Information", format!("<#{}>", submission_channel_id), false);
             }
+            if !routes.is_empty() {
+                embed = embed.field("Notification Routes", &routes_text, false);
+            }
         }
         poise::CreateReply::default().embed(embed).ephemeral(true)
     };
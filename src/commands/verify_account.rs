@@ -0,0 +1,232 @@
+use crate::commands::link_accounts::assign_rating_role;
+use crate::scraping::user_profile::get_user_profile;
+use crate::{Context, Error};
+
+use mysql::prelude::*;
+use mysql::*;
+use rand::rngs::OsRng;
+use rand::RngCore;
+
+use poise::{
+    serenity_prelude::{self as serenity, CreateEmbed, CreateEmbedAuthor},
+    CreateReply,
+};
+
+/// A token that nobody can guess, drawn from the operating system's random number generator.
+fn create_token() -> String {
+    let mut bytes = [0u8; 8];
+    OsRng.fill_bytes(&mut bytes);
+    format!("acnb-{}", bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<String>())
+}
+
+/// Verify your linked AtCoder account with a token placed in your AtCoder affiliation.
+#[poise::command(prefix_command, slash_command, rename = "verify-account")]
+pub async fn verify_account(ctx: Context<'_>) -> Result<(), Error> {
+    let pool = ctx.data().conn.lock().await;
+    let mut conn = pool.get_conn().unwrap();
+    let guild_id = ctx.guild_id().unwrap().get();
+    let discord_id = ctx.author().id.get();
+
+    let selected_data: Vec<String> = conn.exec(
+        r"SELECT language FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => &guild_id},
+    )?;
+    let mut lang = "ja";
+    if selected_data.len() == 1 {
+        lang = selected_data[0].as_str();
+    }
+
+    let link_accounts: Vec<(i32, String, i8)> = conn.exec(
+        "SELECT id,atcoder_username,verified FROM users WHERE discord_id=:discord_id AND server_id=:server_id",
+        params! {"discord_id" => discord_id, "server_id" => &guild_id},
+    )?;
+    let Some((user_id, atcoder_user, verified)) = link_accounts.into_iter().next() else {
+        if lang == "ja" {
+            let response = CreateReply::default()
+                .embed(
+                    CreateEmbed::default()
+                        .title("エラー")
+                        .description("AtCoderアカウントが連携されていません。先に `/atcoder link-account` を実行してください。"),
+                )
+                .ephemeral(true);
+            ctx.send(response).await?;
+        } else {
+            let response = CreateReply::default()
+                .embed(CreateEmbed::default().title("Error").description("No AtCoder account is linked. Please run `/atcoder link-account` first."))
+                .ephemeral(true);
+            ctx.send(response).await?;
+        }
+        return Ok(());
+    };
+
+    if verified == 1 {
+        if lang == "ja" {
+            let response = CreateReply::default()
+                .embed(CreateEmbed::default().title("表示").description(format!("`{}` はすでに認証済みです。", atcoder_user)))
+                .ephemeral(true);
+            ctx.send(response).await?;
+        } else {
+            let response = CreateReply::default()
+                .embed(CreateEmbed::default().title("display").description(format!("`{}` is already verified.", atcoder_user)))
+                .ephemeral(true);
+            ctx.send(response).await?;
+        }
+        return Ok(());
+    }
+
+    let tokens: Vec<(String, String)> = conn.exec(
+        "SELECT atcoder_username,token FROM account_verifications WHERE server_id=:server_id AND discord_id=:discord_id",
+        params! {"server_id" => &guild_id, "discord_id" => discord_id},
+    )?;
+    let token = match tokens.into_iter().next() {
+        Some((token_user, token)) if token_user == atcoder_user => token,
+        _ => {
+            let token = create_token();
+            conn.exec_drop(
+                "REPLACE INTO account_verifications (server_id, discord_id, atcoder_username, token) VALUES (:server_id, :discord_id, :atcoder_username, :token)",
+                params! {"server_id" => &guild_id, "discord_id" => discord_id, "atcoder_username" => &atcoder_user, "token" => &token},
+            )?;
+            let response = {
+                let mut embed = serenity::CreateEmbed::default().author(
+                    CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(ctx.data().avatar_url.as_str()).url("https://atcoder-notify.com/"),
+                );
+                if lang == "ja" {
+                    embed = embed.title("アカウント認証").description(format!(
+                        "[AtCoderの設定ページ](https://atcoder.jp/settings)で所属に次の文字列を含めてから、もう一度 `/atcoder verify-account` を実行してください。\n`{}`\n認証が終わったら所属は元に戻して構いません。",
+                        token
+                    ));
+                } else {
+                    embed = embed.title("Account Verification").description(format!(
+                        "Add the following text to your affiliation on the [AtCoder settings page](https://atcoder.jp/settings), then run `/atcoder verify-account` again.\n`{}`\nYou can change your affiliation back once verification is done.",
+                        token
+                    ));
+                }
+                poise::CreateReply::default().embed(embed).ephemeral(true)
+            };
+            ctx.send(response).await?;
+            return Ok(());
+        }
+    };
+
+    ctx.defer_ephemeral().await?;
+
+    let profile = get_user_profile(&atcoder_user).await?;
+    let is_verified = profile.is_some_and(|profile| profile.affiliation.contains(&token));
+
+    if !is_verified {
+        let response = {
+            let mut embed = serenity::CreateEmbed::default()
+                .author(CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(ctx.data().avatar_url.as_str()).url("https://atcoder-notify.com/"));
+            if lang == "ja" {
+                embed = embed.title("エラー").description(format!("`{}` の所属に `{}` が見つかりませんでした。", atcoder_user, token));
+            } else {
+                embed = embed.title("Error").description(format!("Could not find `{}` in the affiliation of `{}`.", token, atcoder_user));
+            }
+            poise::CreateReply::default().embed(embed).ephemeral(true)
+        };
+        ctx.send(response).await?;
+        return Ok(());
+    }
+
+    conn.exec_drop("UPDATE users SET verified=1 WHERE id=:id", params! {"id" => user_id})?;
+    conn.exec_drop(
+        "DELETE FROM account_verifications WHERE server_id=:server_id AND discord_id=:discord_id",
+        params! {"server_id" => &guild_id, "discord_id" => discord_id},
+    )?;
+    assign_rating_role(ctx.http(), &mut conn, ctx.guild_id().unwrap(), ctx.author().id, &atcoder_user).await?;
+
+    let response = {
+        let mut embed = serenity::CreateEmbed::default()
+            .author(CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(ctx.data().avatar_url.as_str()).url("https://atcoder-notify.com/"));
+        if lang == "ja" {
+            embed = embed.title("認証完了").description(format!("<@{}>と`{}`の連携を認証しました。", discord_id, atcoder_user));
+        } else {
+            embed = embed.title("Verified").description(format!("Verified the link between <@{}> and `{}`.", discord_id, atcoder_user));
+        }
+        poise::CreateReply::default().embed(embed).ephemeral(true)
+    };
+    ctx.send(response).await?;
+
+    Ok(())
+}
+
+/// Set whether rating roles are only given to verified accounts.
+#[poise::command(prefix_command, slash_command, rename = "set-require-verification")]
+pub async fn set_require_verification(ctx: Context<'_>, #[description = "require_verification"] require_verification: bool) -> Result<(), Error> {
+    let pool = ctx.data().conn.lock().await;
+    let mut conn = pool.get_conn().unwrap();
+    let guild_id = ctx.guild_id().unwrap().to_string();
+
+    let require_verification = if require_verification { 1 } else { 0 };
+
+    let selected_data: Vec<String> = conn.exec(
+        r"SELECT language FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => &guild_id},
+    )?;
+    let mut lang = "ja";
+    if selected_data.len() == 1 {
+        lang = selected_data[0].as_str();
+    }
+
+    let owners: Vec<u64> = conn
+        .exec(
+            "SELECT user_id FROM owners WHERE guild_id=:guild_id",
+            params! {
+                "guild_id" => ctx.guild_id().unwrap_or_default().get()
+            },
+        )
+        .unwrap();
+    let has_permission = if owners.is_empty() || owners.contains(&{ ctx.author().id.get() }) {
+        true
+    } else {
+        ctx.author().id.get() == ctx.guild().unwrap().owner_id.get()
+    };
+    if !has_permission {
+        if lang == "ja" {
+            let response = CreateReply::default().embed(CreateEmbed::default().title("エラー").description("権限がありません。")).ephemeral(true);
+            ctx.send(response).await?;
+        } else {
+            let response = CreateReply::default().embed(CreateEmbed::default().title("Error").description("You do not have permission.")).ephemeral(true);
+            ctx.send(response).await?;
+        }
+        return Ok(());
+    }
+
+    let count: Vec<i32> = conn.exec(
+        r"SELECT count(*) FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => &guild_id},
+    )?;
+
+    if count[0] == 0 {
+        conn.exec_drop(
+            r"INSERT INTO server_settings (server_id, require_verification) VALUES (:server_id, :require_verification)",
+            params! {"server_id" => &guild_id, "require_verification" => require_verification},
+        )?;
+    } else {
+        conn.exec_drop(
+            r"UPDATE server_settings SET require_verification=:require_verification WHERE server_id=:server_id",
+            params! {"server_id" => &guild_id, "require_verification" => require_verification},
+        )?;
+    }
+
+    let response = {
+        let mut embed = serenity::CreateEmbed::default()
+            .author(CreateEmbedAuthor::new("").name("AtCoder Notify Bot v3").icon_url(ctx.data().avatar_url.as_str()).url("https://atcoder-notify.com/"));
+        if lang == "ja" {
+            embed = embed.title("設定変更").description(format!(
+                "ロール付与時のアカウント認証を {} に変更しました。",
+                if require_verification == 0 { "不要" } else { "必須" }
+            ));
+        } else {
+            embed = embed.title("Settings Changed").description(format!(
+                "Account verification before role assignment is now {}.",
+                if require_verification == 0 { "optional" } else { "required" }
+            ));
+        }
+        poise::CreateReply::default().embed(embed).ephemeral(true)
+    };
+
+    ctx.send(response).await?;
+
+    Ok(())
+}
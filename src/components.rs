@@ -0,0 +1,151 @@
+//! Routing of button and select menu interactions to the command that created them.
+//!
+//! Custom ids have the form `<namespace>:<action>`. Components that belong to a single command
+//! invocation use `<namespace>:<state id>:<action>`, where the state id refers to a [`ComponentState`]
+//! kept in memory for [`STATE_LIFETIME`]. Only the user who created the state can use those components.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use poise::serenity_prelude::{
+    self as serenity, ButtonStyle, ComponentInteraction, CreateActionRow, CreateButton, CreateInteractionResponse, CreateInteractionResponseMessage, UserId,
+};
+use poise::BoxFuture;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{Data, Error};
+
+/// How long the components of a command invocation keep working.
+pub const STATE_LIFETIME: Duration = Duration::from_secs(15 * 60);
+
+pub type ComponentHandler = for<'a> fn(&'a serenity::Context, &'a Data, &'a ComponentInteraction, ComponentRequest<'a>) -> BoxFuture<'a, Result<(), Error>>;
+
+pub struct ComponentRequest<'a> {
+    pub action: &'a str,
+    /// Present when the custom id carries a state id. The router has already checked the invoker.
+    pub state: Option<ComponentState>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ComponentState {
+    pub id: u64,
+    pub invoker: UserId,
+    data: String,
+    expires_at: Instant,
+}
+
+impl ComponentState {
+    pub fn data<T: DeserializeOwned>(&self) -> Option<T> {
+        serde_json::from_str(&self.data).ok()
+    }
+}
+
+#[derive(Default)]
+pub struct ComponentStates {
+    states: Mutex<HashMap<u64, ComponentState>>,
+    next_id: AtomicU64,
+}
+
+impl ComponentStates {
+    /// Store `data` for a new command invocation by `invoker` and return its state id.
+    pub fn create<T: Serialize>(&self, invoker: UserId, data: &T) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let now = Instant::now();
+        let mut states = self.states.lock().unwrap();
+        states.retain(|_, state| state.expires_at > now);
+        states.insert(
+            id,
+            ComponentState {
+                id,
+                invoker,
+                data: serde_json::to_string(data).unwrap_or_default(),
+                expires_at: now + STATE_LIFETIME,
+            },
+        );
+        id
+    }
+
+    /// Replace the data of a state and extend its lifetime.
+    pub fn update<T: Serialize>(&self, id: u64, data: &T) {
+        if let Some(state) = self.states.lock().unwrap().get_mut(&id) {
+            state.data = serde_json::to_string(data).unwrap_or_default();
+            state.expires_at = Instant::now() + STATE_LIFETIME;
+        }
+    }
+
+    fn get(&self, id: u64) -> Option<ComponentState> {
+        let states = self.states.lock().unwrap();
+        states.get(&id).filter(|state| state.expires_at > Instant::now()).cloned()
+    }
+}
+
+pub fn custom_id(namespace: &str, state_id: u64, action: &str) -> String {
+    format!("{}:{}:{}", namespace, state_id, action)
+}
+
+/// `<` and `>` buttons for a paginated response. `page` starts at 0.
+pub fn pagination_row(namespace: &str, state_id: u64, page: usize, page_count: usize) -> CreateActionRow {
+    CreateActionRow::Buttons(vec![
+        CreateButton::new(custom_id(namespace, state_id, "prev")).label("<").style(ButtonStyle::Primary).disabled(page == 0),
+        CreateButton::new(custom_id(namespace, state_id, "page"))
+            .label(format!("{}/{}", page + 1, page_count.max(1)))
+            .style(ButtonStyle::Secondary)
+            .disabled(true),
+        CreateButton::new(custom_id(namespace, state_id, "next")).label(">").style(ButtonStyle::Primary).disabled(page + 1 >= page_count),
+    ])
+}
+
+/// Move `page` according to a `prev` or `next` action, staying within `page_count` pages.
+pub fn turn_page(page: usize, action: &str, page_count: usize) -> usize {
+    match action {
+        "prev" => page.saturating_sub(1),
+        "next" => (page + 1).min(page_count.saturating_sub(1)),
+        _ => page,
+    }
+}
+
+#[derive(Default)]
+pub struct ComponentRouter {
+    routes: Vec<(&'static str, ComponentHandler)>,
+}
+
+impl ComponentRouter {
+    pub fn route(mut self, namespace: &'static str, handler: ComponentHandler) -> Self {
+        self.routes.push((namespace, handler));
+        self
+    }
+
+    /// Pass `interaction` to the handler registered for its namespace. Returns `false` when no handler matches,
+    /// e.g. for components that a running command is waiting on with a collector.
+    pub async fn dispatch(&self, ctx: &serenity::Context, data: &Data, interaction: &ComponentInteraction) -> Result<bool, Error> {
+        let Some((namespace, payload)) = interaction.data.custom_id.split_once(':') else {
+            return Ok(false);
+        };
+        let Some((_, handler)) = self.routes.iter().find(|(route, _)| *route == namespace) else {
+            return Ok(false);
+        };
+
+        let state_id = payload.split_once(':').and_then(|(state_id, action)| state_id.parse::<u64>().ok().map(|state_id| (state_id, action)));
+        let request = match state_id {
+            Some((state_id, action)) => {
+                let notice = match data.component_states.get(state_id) {
+                    None => "この操作は期限切れです。もう一度コマンドを実行してください。\nThis has expired. Please run the command again.",
+                    Some(state) if state.invoker != interaction.user.id => {
+                        "コマンドを実行した人だけが操作できます。\nOnly the user who ran the command can use this."
+                    }
+                    Some(state) => {
+                        let request = ComponentRequest { action, state: Some(state) };
+                        return handler(ctx, data, interaction, request).await.map(|_| true);
+                    }
+                };
+                let response = CreateInteractionResponseMessage::default().content(notice).ephemeral(true);
+                interaction.create_response(ctx, CreateInteractionResponse::Message(response)).await?;
+                return Ok(true);
+            }
+            None => ComponentRequest { action: payload, state: None },
+        };
+        handler(ctx, data, interaction, request).await.map(|_| true)
+    }
+}
@@ -0,0 +1,184 @@
+//! Discussion threads for servers that turned on `server_settings.discussion_threads`.
+//!
+//! A thread is created under the contest channel when a contest starts and kept archived and locked, so that
+//! nobody can post in it. When the contest ends the thread is opened and seeded with the tasks, their estimated
+//! difficulties and the editorial. Progress is kept in `contest_threads`.
+//!
+//! A step that fails, usually because of a transient Discord error, is tried again every minute. A thread is only
+//! given up on, with the status `failed`, after [`MAX_ATTEMPTS`] failures in a row.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use mysql::prelude::*;
+use mysql::*;
+use poise::serenity_prelude::{self as serenity, AutoArchiveDuration, ChannelId, ChannelType, CreateEmbed, CreateMessage, CreateThread, EditThread};
+use reqwest::cookie::Jar;
+use reqwest::Client;
+use tokio::sync::Mutex;
+
+use crate::scraping::contest_id::ContestId;
+use crate::scraping::contest_info::{get_standings_difficulties, get_task_list, ContestTask};
+use crate::utils::MAX_DESCRIPTION_LENGTH;
+
+/// Discord rejects thread names longer than 100 characters.
+const MAX_THREAD_NAME_LENGTH: usize = 100;
+/// How many times creating or opening a thread is tried before giving up.
+const MAX_ATTEMPTS: i32 = 5;
+
+/// Create threads for contests that have started and open those whose contest has ended.
+pub async fn update_threads(pool: &Arc<Mutex<Pool>>, ctx: &serenity::Context, cookie_store: &Arc<Jar>) -> Result<()> {
+    let pool = pool.lock().await.clone();
+    let mut conn = pool.get_conn()?;
+    create_threads(&mut conn, ctx).await?;
+    open_threads(&mut conn, ctx, cookie_store).await
+}
+
+async fn create_threads(conn: &mut PooledConn, ctx: &serenity::Context) -> Result<()> {
+    let due: Vec<(String, String, chrono::NaiveDateTime, u64, String, Option<String>)> = conn.query(
+        "SELECT contests.contest_id, contests.name, contests.end_at, server_settings.server_id, notifications.contest_channel_id, server_settings.language
+        FROM contests
+        JOIN server_settings ON server_settings.discussion_threads = 1
+        JOIN notifications ON notifications.server_id = server_settings.server_id
+        LEFT JOIN contest_threads ON contest_threads.server_id = server_settings.server_id AND contest_threads.contest_id = contests.contest_id
+        WHERE contests.start_at <= UTC_TIMESTAMP() AND UTC_TIMESTAMP() < contests.end_at
+            AND notifications.contest_channel_id IS NOT NULL
+            AND (contest_threads.contest_id IS NULL OR contest_threads.status = 'pending')",
+    )?;
+    for (contest_id, name, end_at, server_id, channel_id, lang) in due {
+        let Ok(channel_id) = channel_id.parse::<u64>() else {
+            continue;
+        };
+        let lang = lang.unwrap_or("ja".to_string());
+        match create_locked_thread(ctx, ChannelId::new(channel_id), &name, end_at.and_utc().timestamp(), &lang).await {
+            Ok(thread_id) => conn.exec_drop(
+                "INSERT INTO contest_threads (server_id, contest_id, thread_id, status, attempts, created_at)
+                VALUES (:server_id, :contest_id, :thread_id, 'locked', 0, UTC_TIMESTAMP())
+                ON DUPLICATE KEY UPDATE thread_id=:thread_id, status='locked', attempts=0",
+                params! {"server_id" => server_id, "contest_id" => &contest_id, "thread_id" => thread_id.get()},
+            )?,
+            Err(err) => {
+                log::warn!("could not create the thread for {} in {}: {}", contest_id, server_id, err);
+                conn.exec_drop(
+                    "INSERT INTO contest_threads (server_id, contest_id, thread_id, status, attempts, created_at)
+                    VALUES (:server_id, :contest_id, NULL, IF(1 >= :max_attempts, 'failed', 'pending'), 1, UTC_TIMESTAMP())
+                    ON DUPLICATE KEY UPDATE status=IF(attempts + 1 >= :max_attempts, 'failed', 'pending'), attempts=attempts + 1",
+                    params! {"server_id" => server_id, "contest_id" => &contest_id, "max_attempts" => MAX_ATTEMPTS},
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn create_locked_thread(ctx: &serenity::Context, channel_id: ChannelId, name: &str, end_at: i64, lang: &str) -> serenity::Result<ChannelId> {
+    let name: String = name.chars().take(MAX_THREAD_NAME_LENGTH).collect();
+    let thread = channel_id
+        .create_thread(
+            &ctx.http,
+            CreateThread::new(name).kind(ChannelType::PublicThread).auto_archive_duration(AutoArchiveDuration::OneWeek),
+        )
+        .await?;
+    let content = if lang == "ja" {
+        format!(
+            "コンテストの感想・解説用スレッドです。コンテスト終了 (<t:{}:R>) 後に書き込めるようになります。",
+            end_at
+        )
+    } else {
+        format!("This thread is for discussing the contest. It opens when the contest ends (<t:{}:R>).", end_at)
+    };
+    let result = async {
+        thread.id.send_message(&ctx.http, CreateMessage::new().content(content)).await?;
+        thread.id.edit_thread(&ctx.http, EditThread::new().archived(true).locked(true)).await
+    }
+    .await;
+    // A thread left open would take posts during the contest, and the next attempt creates another one.
+    if let Err(err) = result {
+        if let Err(err) = thread.id.delete(&ctx.http).await {
+            log::warn!("could not delete the thread {}: {}", thread.id, err);
+        }
+        return Err(err);
+    }
+    Ok(thread.id)
+}
+
+async fn open_threads(conn: &mut PooledConn, ctx: &serenity::Context, cookie_store: &Arc<Jar>) -> Result<()> {
+    let due: Vec<(u64, String, u64, String, String, Option<String>)> = conn.query(
+        "SELECT contest_threads.server_id, contest_threads.contest_id, contest_threads.thread_id, contests.name, contests.rating_range_raw, server_settings.language
+        FROM contest_threads
+        JOIN contests ON contests.contest_id = contest_threads.contest_id
+        LEFT JOIN server_settings ON server_settings.server_id = contest_threads.server_id
+        WHERE contest_threads.status = 'locked' AND contests.end_at <= UTC_TIMESTAMP()",
+    )?;
+    if due.is_empty() {
+        return Ok(());
+    }
+    let client = Client::builder().gzip(true).cookie_provider(Arc::clone(cookie_store)).build().unwrap();
+    // Fetched once per contest, however many servers have a thread for it.
+    let mut tasks: BTreeMap<String, (Vec<ContestTask>, BTreeMap<String, i32>)> = BTreeMap::new();
+    for (server_id, contest_id, thread_id, name, rating_range_raw, lang) in due {
+        let contest = ContestId::new(&contest_id);
+        if !tasks.contains_key(&contest_id) {
+            let task_list = get_task_list(&client, &contest).await.unwrap_or_else(|err| {
+                log::warn!("could not get the tasks of {}: {}", contest, err);
+                vec![]
+            });
+            let difficulties = get_standings_difficulties(&client, &contest, rating_range_raw.trim() != "-").await.unwrap_or_default();
+            tasks.insert(contest_id.clone(), (task_list, difficulties));
+        }
+        let (task_list, difficulties) = &tasks[&contest_id];
+        let lang = lang.unwrap_or("ja".to_string());
+
+        let thread = ChannelId::new(thread_id);
+        let result = async {
+            thread.edit_thread(&ctx.http, EditThread::new().archived(false).locked(false)).await?;
+            let embed = seed_embed(&contest, &name, task_list, difficulties, &lang);
+            thread.send_message(&ctx.http, CreateMessage::new().embed(embed)).await
+        }
+        .await;
+        match result {
+            Ok(_) => conn.exec_drop(
+                "UPDATE contest_threads SET status='open' WHERE server_id=:server_id AND contest_id=:contest_id",
+                params! {"server_id" => server_id, "contest_id" => &contest_id},
+            )?,
+            Err(err) => {
+                log::warn!("could not open the thread for {} in {}: {}", contest_id, server_id, err);
+                conn.exec_drop(
+                    "UPDATE contest_threads SET status=IF(attempts + 1 >= :max_attempts, 'failed', 'locked'), attempts=attempts + 1
+                    WHERE server_id=:server_id AND contest_id=:contest_id",
+                    params! {"server_id" => server_id, "contest_id" => &contest_id, "max_attempts" => MAX_ATTEMPTS},
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn seed_embed(contest: &ContestId, name: &str, tasks: &[ContestTask], difficulties: &BTreeMap<String, i32>, lang: &str) -> CreateEmbed {
+    let mut description = if lang == "ja" {
+        "コンテストお疲れさまでした！\n".to_string()
+    } else {
+        "The contest is over. Thanks for participating!\n".to_string()
+    };
+    for task in tasks {
+        let mut line = format!("[{}]({}) {}", task.assignment, contest.task_url(&task.task_id), task.name);
+        if let Some(difficulty) = difficulties.get(&task.assignment) {
+            line += &format!(" | diff {}", difficulty);
+        }
+        line += &format!(
+            " | [{}]({})",
+            if lang == "ja" { "解説" } else { "Editorial" },
+            contest.task_editorial_url(&task.task_id)
+        );
+        if description.chars().count() + line.chars().count() + 1 > MAX_DESCRIPTION_LENGTH {
+            break;
+        }
+        description += &line;
+        description.push('\n');
+    }
+    CreateEmbed::new().title(name).url(contest.url()).description(description).field(
+        if lang == "ja" { "解説" } else { "Editorial" },
+        contest.editorial_url(),
+        false,
+    )
+}
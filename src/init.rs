@@ -1,4 +1,6 @@
 use log::LevelFilter;
+use mysql::prelude::*;
+use mysql::*;
 
 pub fn init_logger() {
     let base_config = fern::Dispatch::new();
@@ -46,3 +48,253 @@ pub fn init_logger() {
 
     base_config.chain(console_config).chain(application_config).chain(emergency_config).apply().unwrap();
 }
+
+/// Schema changes applied on startup, in order. Each entry is recorded in `schema_migrations` once it has run.
+const MIGRATIONS: &[(&str, &[&str])] = &[
+    (
+        "0001_account_verification",
+        &[
+            "ALTER TABLE users ADD COLUMN verified TINYINT NOT NULL DEFAULT 0",
+            "ALTER TABLE server_settings ADD COLUMN require_verification TINYINT NOT NULL DEFAULT 0",
+            "CREATE TABLE IF NOT EXISTS account_verifications (
+                id INT AUTO_INCREMENT PRIMARY KEY,
+                server_id BIGINT UNSIGNED NOT NULL,
+                discord_id BIGINT UNSIGNED NOT NULL,
+                atcoder_username VARCHAR(64) NOT NULL,
+                token VARCHAR(64) NOT NULL,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE KEY server_discord (server_id, discord_id)
+            )",
+        ],
+    ),
+    (
+        "0002_atcoder_identities",
+        &[
+            "CREATE TABLE IF NOT EXISTS atcoder_identities (
+                user_key VARCHAR(64) PRIMARY KEY,
+                screen_name VARCHAR(64) NOT NULL,
+                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP
+            )",
+            "INSERT IGNORE INTO atcoder_identities (user_key, screen_name)
+                SELECT LOWER(user_name), MAX(user_name) FROM user_ratings GROUP BY LOWER(user_name)",
+            "INSERT IGNORE INTO atcoder_identities (user_key, screen_name)
+                SELECT DISTINCT LOWER(atcoder_username), atcoder_username FROM users",
+            "UPDATE users SET atcoder_username=LOWER(atcoder_username)",
+            "UPDATE atcoder_user_ratings
+                JOIN atcoder_identities ON atcoder_identities.user_key = LOWER(atcoder_user_ratings.user_name)
+                SET atcoder_user_ratings.user_name = atcoder_identities.screen_name",
+        ],
+    ),
+    (
+        "0003_contest_changes",
+        &["CREATE TABLE IF NOT EXISTS contest_changes (
+            id INT AUTO_INCREMENT PRIMARY KEY,
+            contest_id VARCHAR(255) NOT NULL,
+            field VARCHAR(32) NOT NULL,
+            old_value TEXT NOT NULL,
+            new_value TEXT NOT NULL,
+            detected_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            INDEX contest_id (contest_id)
+        )"],
+    ),
+    (
+        "0004_contest_syncs",
+        &["CREATE TABLE IF NOT EXISTS contest_syncs (
+            id BIGINT UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+            mode VARCHAR(16) NOT NULL,
+            status VARCHAR(16) NOT NULL,
+            archive_pages INT NOT NULL DEFAULT 0,
+            contests_seen INT NOT NULL DEFAULT 0,
+            changes INT NOT NULL DEFAULT 0,
+            error TEXT NULL,
+            started_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            finished_at DATETIME NULL,
+            duration_ms BIGINT UNSIGNED NULL
+        )"],
+    ),
+    (
+        "0005_canonical_contest_ids",
+        &[
+            "UPDATE contests SET contest_id = REPLACE(contest_id, '.contest.atcoder.jp', '')",
+            "UPDATE user_ratings SET contest = REPLACE(contest, '.contest.atcoder.jp', '')",
+            "UPDATE messages SET contest_id = REPLACE(contest_id, '.contest.atcoder.jp', '')",
+            "UPDATE contest_changes SET contest_id = REPLACE(contest_id, '.contest.atcoder.jp', '')",
+        ],
+    ),
+    (
+        // `start_time` was a `2024-10-19 21:00:00+0900` string. `start_at` and `end_at` are UTC.
+        "0006_contest_datetimes",
+        &[
+            "ALTER TABLE contests ADD COLUMN start_at DATETIME NULL, ADD COLUMN end_at DATETIME NULL",
+            "UPDATE contests SET start_at = CONVERT_TZ(STR_TO_DATE(LEFT(start_time, 19), '%Y-%m-%d %H:%i:%s'), INSERT(RIGHT(start_time, 5), 4, 0, ':'), '+00:00')",
+            "UPDATE contests SET end_at = start_at + INTERVAL duration MINUTE",
+            "ALTER TABLE contests
+                MODIFY start_at DATETIME NOT NULL,
+                MODIFY end_at DATETIME NOT NULL,
+                DROP COLUMN start_time,
+                ADD INDEX start_at (start_at),
+                ADD INDEX end_at (end_at)",
+        ],
+    ),
+    (
+        "0007_notification_outbox",
+        &["CREATE TABLE IF NOT EXISTS notification_outbox (
+            id BIGINT UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+            server_id BIGINT UNSIGNED NULL,
+            channel_id BIGINT UNSIGNED NOT NULL,
+            kind VARCHAR(32) NOT NULL,
+            contest_id VARCHAR(255) NULL,
+            edit_message_id BIGINT UNSIGNED NULL,
+            payload MEDIUMTEXT NOT NULL,
+            attachment_name VARCHAR(64) NULL,
+            attachment MEDIUMBLOB NULL,
+            status VARCHAR(16) NOT NULL DEFAULT 'pending',
+            attempts INT NOT NULL DEFAULT 0,
+            last_error TEXT NULL,
+            next_attempt_at DATETIME NOT NULL,
+            created_at DATETIME NOT NULL,
+            sent_at DATETIME NULL,
+            INDEX due (status, next_attempt_at),
+            INDEX channel_id (channel_id)
+        )"],
+    ),
+    (
+        // Replaces `server_settings.do_everyone`, which only covered reminders.
+        "0008_mention_settings",
+        &[
+            "CREATE TABLE IF NOT EXISTS mention_settings (
+                server_id BIGINT UNSIGNED NOT NULL,
+                notification VARCHAR(16) NOT NULL,
+                target VARCHAR(16) NOT NULL,
+                role_id BIGINT UNSIGNED NULL,
+                PRIMARY KEY (server_id, notification)
+            )",
+            "ALTER TABLE server_settings ADD COLUMN participant_role_id BIGINT UNSIGNED NULL",
+            "INSERT IGNORE INTO mention_settings (server_id, notification, target)
+                SELECT server_id, 'reminder', IF(do_everyone = 0, 'none', 'everyone') FROM server_settings",
+        ],
+    ),
+    (
+        "0009_contest_rsvps",
+        &[
+            "CREATE TABLE IF NOT EXISTS contest_rsvps (
+                server_id BIGINT UNSIGNED NOT NULL,
+                contest_id VARCHAR(255) NOT NULL,
+                user_id BIGINT UNSIGNED NOT NULL,
+                status VARCHAR(8) NOT NULL,
+                updated_at DATETIME NOT NULL,
+                PRIMARY KEY (server_id, contest_id, user_id),
+                INDEX contest_id (contest_id)
+            )",
+            "ALTER TABLE contests ADD COLUMN is_rsvp_notified TINYINT NOT NULL DEFAULT 0",
+            "UPDATE contests SET is_rsvp_notified=1 WHERE start_at <= UTC_TIMESTAMP()",
+        ],
+    ),
+    (
+        "0010_discussion_threads",
+        &[
+            "ALTER TABLE server_settings ADD COLUMN discussion_threads TINYINT NOT NULL DEFAULT 0",
+            "CREATE TABLE IF NOT EXISTS contest_threads (
+                server_id BIGINT UNSIGNED NOT NULL,
+                contest_id VARCHAR(255) NOT NULL,
+                thread_id BIGINT UNSIGNED NULL,
+                status VARCHAR(16) NOT NULL,
+                created_at DATETIME NOT NULL,
+                PRIMARY KEY (server_id, contest_id)
+            )",
+        ],
+    ),
+    (
+        "0011_scheduled_events",
+        &[
+            "ALTER TABLE server_settings ADD COLUMN scheduled_events TINYINT NOT NULL DEFAULT 0",
+            "CREATE TABLE IF NOT EXISTS contest_events (
+                server_id BIGINT UNSIGNED NOT NULL,
+                contest_id VARCHAR(255) NOT NULL,
+                event_id BIGINT UNSIGNED NOT NULL,
+                PRIMARY KEY (server_id, contest_id),
+                INDEX contest_id (contest_id)
+            )",
+        ],
+    ),
+    (
+        // `weekly_schedule_weekday` counts from Monday = 0. The schedule is off while it is NULL.
+        "0012_weekly_schedule",
+        &["ALTER TABLE server_settings
+            ADD COLUMN weekly_schedule_weekday TINYINT NULL,
+            ADD COLUMN weekly_schedule_time TIME NOT NULL DEFAULT '09:00:00',
+            ADD COLUMN weekly_schedule_sent_at DATETIME NULL"],
+    ),
+    (
+        // The digest used to be sent at midnight in the host's time zone, which is Japan time.
+        "0013_server_timezones",
+        &[
+            "ALTER TABLE server_settings ADD COLUMN timezone VARCHAR(64) NULL, ADD COLUMN digest_time TIME NOT NULL DEFAULT '00:00:00'",
+            "ALTER TABLE notifications ADD COLUMN digest_sent_at DATETIME NULL",
+            "UPDATE notifications SET digest_sent_at = UTC_TIMESTAMP()",
+        ],
+    ),
+    (
+        // `contest_type` and `series` are filters, NULL when a route matches every contest.
+        "0014_notification_routes",
+        &["CREATE TABLE IF NOT EXISTS notification_routes (
+            id BIGINT UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+            server_id BIGINT UNSIGNED NOT NULL,
+            event VARCHAR(16) NOT NULL,
+            channel_id BIGINT UNSIGNED NOT NULL,
+            contest_type TINYINT NULL,
+            series VARCHAR(8) NULL,
+            INDEX server_id (server_id),
+            INDEX channel_id (channel_id)
+        )"],
+    ),
+    (
+        // Quiet hours are off while `quiet_hours_start` is NULL. Held messages wait in `notification_outbox` with
+        // the status `held`.
+        "0015_quiet_hours",
+        &["ALTER TABLE server_settings
+            ADD COLUMN quiet_hours_start TIME NULL,
+            ADD COLUMN quiet_hours_end TIME NULL,
+            ADD COLUMN quiet_hours_hold_reminders TINYINT NOT NULL DEFAULT 0"],
+    ),
+    (
+        // The submission watcher stored the screen name as AtCoder Problems returned it.
+        "0016_submission_data_user_keys",
+        &["UPDATE submission_data SET user_id=LOWER(user_id)"],
+    ),
+    (
+        // Threads that failed once were given up on. They are tried again up to a few times now.
+        "0017_contest_thread_attempts",
+        &[
+            "ALTER TABLE contest_threads ADD COLUMN attempts INT NOT NULL DEFAULT 0",
+            "UPDATE contest_threads SET status='locked' WHERE status='failed' AND thread_id IS NOT NULL",
+            "UPDATE contest_threads SET status='pending' WHERE status='failed' AND thread_id IS NULL",
+        ],
+    ),
+    (
+        "0018_drop_sent_outbox_attachments",
+        &["UPDATE notification_outbox SET attachment=NULL WHERE status='sent'"],
+    ),
+];
+
+pub fn init_database(pool: &Pool) {
+    let mut conn = pool.get_conn().unwrap();
+    conn.query_drop(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            name VARCHAR(128) PRIMARY KEY,
+            applied_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )",
+    )
+    .unwrap();
+    let applied: Vec<String> = conn.query("SELECT name FROM schema_migrations").unwrap();
+    for (name, statements) in MIGRATIONS {
+        if applied.iter().any(|applied| applied == name) {
+            continue;
+        }
+        log::info!("apply migration: {}", name);
+        for statement in statements.iter() {
+            conn.query_drop(statement).unwrap();
+        }
+        conn.exec_drop("INSERT INTO schema_migrations (name) VALUES (:name)", params! {"name" => name}).unwrap();
+    }
+}
@@ -1,30 +1,36 @@
 extern crate mysql;
 
 mod commands;
+mod components;
+mod discussion;
 mod init;
+mod mentions;
+mod outbox;
+mod quiet_hours;
+mod routing;
+mod rsvp;
+mod scheduled_events;
 mod scraping;
 mod send_message;
+mod timezone;
 mod utils;
 mod web_server;
 
-use commands::contests::create_contest_response;
-use commands::contests::Contest;
+use commands::contests;
+use commands::set_mention;
+use commands::setup::{self, send_welcome_message};
+use components::{ComponentRouter, ComponentStates};
+use init::init_database;
 use init::init_logger;
 
 use chrono::Timelike;
-use mysql::prelude::*;
 use mysql::*;
 use poise::serenity_prelude::ActivityData;
-use poise::serenity_prelude::CreateActionRow;
-use poise::serenity_prelude::CreateButton;
-use poise::serenity_prelude::CreateInteractionResponse;
-use poise::serenity_prelude::CreateInteractionResponseMessage;
 use poise::CreateReply;
 use reqwest::cookie::Jar;
 use scraping::atcoder_ratings::get_ratings;
-use scraping::contest_type::ContestRatingType;
-use scraping::contest_type::ContestType;
-use scraping::contests::update_contests;
+use scraping::contest_info::ProblemDifficultyCache;
+use scraping::contests::{update_contests, SyncMode};
 use scraping::get_ranking::get_ranking;
 use scraping::get_submission::get_submission;
 use scraping::notify;
@@ -36,7 +42,9 @@ use std::time::Duration;
 use tokio::sync::Mutex;
 use web_server::start;
 
+use commands::admin;
 use commands::atcoder;
+use commands::rating;
 use commands::server;
 use poise::serenity_prelude as serenity;
 use scraping::login;
@@ -48,11 +56,15 @@ type Context<'a> = poise::Context<'a, Data, Error>;
 pub struct Data {
     conn: Arc<Mutex<Pool>>,
     avatar_url: String,
+    component_states: ComponentStates,
+    component_router: ComponentRouter,
+    /// Logged in to AtCoder by [`interval`].
+    cookie_store: Arc<Jar>,
+    problem_difficulties: ProblemDifficultyCache,
 }
 
-async fn interval(ctx: serenity::Context) {
+async fn interval(ctx: serenity::Context, cookie_store: Arc<Jar>) {
     log::info!("interval");
-    let cookie_store = Arc::new(Jar::default());
     let mut last_minute = 100;
     let mut date = chrono::Local::now().date_naive();
     let url = format!(
@@ -66,9 +78,13 @@ async fn interval(ctx: serenity::Context) {
     let pool_raw = Pool::new(url.as_str()).unwrap();
     let pool = Arc::new(Mutex::new(pool_raw.clone()));
     let pool_clone = Arc::new(Mutex::new(pool_raw.clone()));
+    tokio::spawn(async move {
+        get_submission(&pool_clone).await;
+    });
+    let pool_clone = Arc::new(Mutex::new(pool_raw.clone()));
     let ctx_clone = ctx.clone();
     tokio::spawn(async move {
-        get_submission(&pool_clone, &ctx_clone).await;
+        outbox::deliver(&pool_clone, &ctx_clone).await;
     });
     login::login(
         std::env::var("ATCODER_USER").expect(""),
@@ -86,15 +102,25 @@ async fn interval(ctx: serenity::Context) {
                 std::env::var("ATCODER_PASS").expect(""),
                 &cookie_store,
             );
-            update_contests(&pool).await.unwrap_or_default();
-            send_message::send_notify(&pool, &ctx).await.unwrap_or_default();
+            let sync = update_contests(&pool, SyncMode::Incremental).await.unwrap_or_else(|err| {
+                log::warn!("could not update contests: {}", err);
+                Default::default()
+            });
+            send_message::send_contest_changes(&pool, &sync.changes).await.unwrap_or_default();
+            scheduled_events::sync_events(&pool, &ctx.http, &sync.changes).await.unwrap_or_else(|err| log::warn!("could not update scheduled events: {}", err));
+            send_message::clear_ranking_messages(&pool).await.unwrap_or_default();
             log::info!("日ごとの処理終了");
             date = now.date_naive();
         } else if last_minute != now.minute() {
             log::info!("分ごとの処理");
-            get_ranking(&pool, &cookie_store, &ctx).await.unwrap_or_default();
+            get_ranking(&pool, &cookie_store).await.unwrap_or_default();
             get_ratings(&cookie_store, &pool, &ctx, false).await.unwrap_or_default();
-            notify::notify(&pool, &ctx).await.unwrap_or_default();
+            notify::notify(&pool).await.unwrap_or_default();
+            rsvp::remind(&pool).await.unwrap_or_else(|err| log::warn!("could not remind RSVPs: {}", err));
+            send_message::send_notify(&pool).await.unwrap_or_else(|err| log::warn!("could not send daily digests: {}", err));
+            send_message::send_weekly_schedule(&pool).await.unwrap_or_else(|err| log::warn!("could not send weekly schedules: {}", err));
+            discussion::update_threads(&pool, &ctx, &cookie_store).await.unwrap_or_else(|err| log::warn!("could not update discussion threads: {}", err));
+            outbox::release_held(&pool).await.unwrap_or_else(|err| log::warn!("could not release messages held during quiet hours: {}", err));
             log::info!("分ごとの処理終了");
             last_minute = now.minute();
         }
@@ -133,10 +159,12 @@ async fn main() {
         std::env::var("MYSQL_DATABASE").expect("")
     );
     let pool = Pool::new(url.as_str()).unwrap();
+    init_database(&pool);
+    let cookie_store = Arc::new(Jar::default());
 
     let framework = poise::Framework::builder()
         .options(poise::FrameworkOptions {
-            commands: vec![atcoder::atcoder(), server::server()],
+            commands: vec![atcoder::atcoder(), server::server(), rating::atcoder_profile(), admin::admin()],
             on_error: |error| {
                 println!("{}", error);
                 Box::pin(async move {
@@ -152,63 +180,13 @@ async fn main() {
                         if format!("{:x}", sha2::Sha256::digest(&message.content)) == "a69893e03d93e1e4d0f66dd41e9df574b70d8f3281ef499eaf04e0437d3cad17" {
                             message.reply(&ctx.http, std::env::var("SECRET_COMMAND_OUTPUT").unwrap_or("** **".to_string())).await.unwrap_or_default();
                         }
+                    } else if let serenity::FullEvent::GuildCreate { guild, is_new: Some(true) } = event {
+                        send_welcome_message(ctx, data, guild).await;
                     } else if let serenity::FullEvent::InteractionCreate {
                         interaction: serenity::Interaction::Component(interaction),
                     } = event
                     {
-                        if interaction.data.custom_id.starts_with("goto_") {
-                            let page = interaction.data.custom_id[5..].parse::<usize>().unwrap();
-                            let pool = data.conn.lock().await;
-                            let mut conn = pool.get_conn().unwrap();
-                            let contests: Vec<Contest> = conn
-                                .query_map(
-                                    "select start_time,duration,contest_type,rating_type,name,rating_range_raw from contests",
-                                    |(start_time, duration, contest_type, rating_type, name, rating_raw): (String, i64, i8, i8, String, String)| {
-                                        let start_time = chrono::DateTime::parse_from_str(&start_time, "%Y-%m-%d %H:%M:%S%z").unwrap();
-                                        let offset = chrono::Duration::minutes(duration);
-                                        Contest {
-                                            start_time,
-                                            end_time: start_time + offset,
-                                            contest_type: match contest_type {
-                                                0 => ContestType::Algorithm,
-                                                _ => ContestType::Heuristic,
-                                            },
-                                            rating_type: match rating_type {
-                                                0 => ContestRatingType::ABC,
-                                                1 => ContestRatingType::ARC,
-                                                2 => ContestRatingType::AGC,
-                                                _ => ContestRatingType::None,
-                                            },
-                                            name,
-                                            rating_raw,
-                                        }
-                                    },
-                                )
-                                .unwrap();
-                            let mut contests: Vec<&Contest> = contests.iter().filter(|contest| chrono::Local::now() >= contest.start_time).collect();
-                            contests.sort_by(|a, b| b.end_time.partial_cmp(&(a.end_time)).unwrap());
-                            let mut next = CreateButton::new(format!("goto_{}", page - 1)).label("<").style(poise::serenity_prelude::ButtonStyle::Primary);
-                            let mut prev = CreateButton::new(format!("goto_{}", page + 1)).label(">").style(poise::serenity_prelude::ButtonStyle::Primary);
-                            if page == 0 {
-                                next = next.disabled(true)
-                            }
-                            if page == contests.len() / 20 {
-                                prev = prev.disabled(true)
-                            }
-                            let components = vec![CreateActionRow::Buttons(vec![next, prev])];
-                            let (components, attachment) = create_contest_response(
-                                format!("past contests (page {})", page + 1).as_str(),
-                                pool.clone(),
-                                contests[page * 20..std::cmp::min(page * 20 + 20, contests.len())].to_vec(),
-                                components,
-                                (page * 20) as i32,
-                            )
-                            .await;
-                            let reply = CreateInteractionResponse::UpdateMessage(
-                                CreateInteractionResponseMessage::default().add_file(attachment).components(components),
-                            );
-                            interaction.create_response(&ctx.http, reply).await?;
-                        }
+                        data.component_router.dispatch(ctx, data, interaction).await?;
                     }
                     Ok(())
                 })
@@ -219,11 +197,20 @@ async fn main() {
             Box::pin(async move {
                 thread::spawn(start);
                 poise::builtins::register_globally(ctx, &framework.options().commands).await?;
-                tokio::spawn(interval(ctx.clone()));
+                tokio::spawn(interval(ctx.clone(), Arc::clone(&cookie_store)));
                 log::info!("Bot started as \"{}\"", ready.user.name);
                 Ok(Data {
                     conn: Arc::new(Mutex::new(pool)),
                     avatar_url: ready.user.avatar_url().unwrap(),
+                    component_states: ComponentStates::default(),
+                    component_router: ComponentRouter::default()
+                        .route("contests", contests::handle_component)
+                        .route("history", rating::handle_history_component)
+                        .route("setup", setup::handle_component)
+                        .route("mention", set_mention::handle_component)
+                        .route("rsvp", rsvp::handle_component),
+                    cookie_store,
+                    problem_difficulties: ProblemDifficultyCache::default(),
                 })
             })
         })
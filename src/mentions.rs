@@ -0,0 +1,189 @@
+//! Who is pinged by each kind of notification, stored per server in `mention_settings`.
+
+use mysql::prelude::*;
+use mysql::*;
+
+/// Custom id of the button on reminders that toggles the contest participants role.
+pub const JOIN_BUTTON_ID: &str = "mention:join";
+
+/// Name of the role the bot creates for [`MentionTarget::Participants`].
+pub const PARTICIPANT_ROLE_NAME: &str = "AtCoder Contest Participants";
+
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum NotificationKind {
+    /// One hour before a contest.
+    #[name = "reminder"]
+    Reminder,
+    /// The list of today's contests.
+    #[name = "daily"]
+    Daily,
+    /// Rating updates after a contest.
+    #[name = "results"]
+    Results,
+    #[name = "ac"]
+    Submission,
+}
+
+impl NotificationKind {
+    pub const ALL: [NotificationKind; 4] = [
+        NotificationKind::Reminder,
+        NotificationKind::Daily,
+        NotificationKind::Results,
+        NotificationKind::Submission,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NotificationKind::Reminder => "reminder",
+            NotificationKind::Daily => "daily",
+            NotificationKind::Results => "results",
+            NotificationKind::Submission => "ac",
+        }
+    }
+
+    pub fn label(self, lang: &str) -> &'static str {
+        match (self, lang) {
+            (NotificationKind::Reminder, "ja") => "1時間前通知",
+            (NotificationKind::Daily, "ja") => "今日のコンテスト",
+            (NotificationKind::Results, "ja") => "レーティング更新",
+            (NotificationKind::Submission, "ja") => "AC通知",
+            (NotificationKind::Reminder, _) => "Reminder",
+            (NotificationKind::Daily, _) => "Daily summary",
+            (NotificationKind::Results, _) => "Results",
+            (NotificationKind::Submission, _) => "AC notification",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MentionTarget {
+    #[default]
+    None,
+    Everyone,
+    Here,
+    Role(u64),
+    /// The opt-in role that members toggle with the button on reminders.
+    Participants,
+}
+
+impl MentionTarget {
+    /// What a server gets before it changes anything. Reminders used to ping @everyone by default.
+    pub fn default_for(kind: NotificationKind) -> MentionTarget {
+        match kind {
+            NotificationKind::Reminder => MentionTarget::Everyone,
+            _ => MentionTarget::None,
+        }
+    }
+
+    pub fn from_row(target: &str, role_id: Option<u64>) -> MentionTarget {
+        match (target, role_id) {
+            ("everyone", _) => MentionTarget::Everyone,
+            ("here", _) => MentionTarget::Here,
+            ("role", Some(role_id)) => MentionTarget::Role(role_id),
+            ("participants", _) => MentionTarget::Participants,
+            _ => MentionTarget::None,
+        }
+    }
+
+    pub fn to_row(self) -> (&'static str, Option<u64>) {
+        match self {
+            MentionTarget::None => ("none", None),
+            MentionTarget::Everyone => ("everyone", None),
+            MentionTarget::Here => ("here", None),
+            MentionTarget::Role(role_id) => ("role", Some(role_id)),
+            MentionTarget::Participants => ("participants", None),
+        }
+    }
+
+    /// The text that pings the target. `participant_role` is the server's contest participants role.
+    pub fn text(self, participant_role: Option<u64>) -> Option<String> {
+        match self {
+            MentionTarget::None => None,
+            MentionTarget::Everyone => Some("@everyone".to_string()),
+            MentionTarget::Here => Some("@here".to_string()),
+            MentionTarget::Role(role_id) => Some(format!("<@&{}>", role_id)),
+            MentionTarget::Participants => participant_role.map(|role_id| format!("<@&{}>", role_id)),
+        }
+    }
+
+    pub fn label(self, lang: &str) -> String {
+        match (self, lang) {
+            (MentionTarget::None, "ja") => "なし".to_string(),
+            (MentionTarget::None, _) => "None".to_string(),
+            (MentionTarget::Everyone, _) => "@everyone".to_string(),
+            (MentionTarget::Here, _) => "@here".to_string(),
+            (MentionTarget::Role(role_id), _) => format!("<@&{}>", role_id),
+            (MentionTarget::Participants, "ja") => "コンテスト参加者ロール".to_string(),
+            (MentionTarget::Participants, _) => "Contest participants role".to_string(),
+        }
+    }
+}
+
+/// The mention setting of every notification kind, with defaults filled in.
+pub fn load_mentions<Q: Queryable>(conn: &mut Q, server_id: u64) -> mysql::Result<Vec<(NotificationKind, MentionTarget)>> {
+    let rows: Vec<(String, String, Option<u64>)> = conn.exec(
+        "SELECT notification, target, role_id FROM mention_settings WHERE server_id=:server_id",
+        params! {"server_id" => server_id},
+    )?;
+    Ok(NotificationKind::ALL
+        .into_iter()
+        .map(|kind| {
+            let target = rows
+                .iter()
+                .find(|(notification, _, _)| notification == kind.as_str())
+                .map(|(_, target, role_id)| MentionTarget::from_row(target, *role_id))
+                .unwrap_or(MentionTarget::default_for(kind));
+            (kind, target)
+        })
+        .collect())
+}
+
+pub fn load_mention<Q: Queryable>(conn: &mut Q, server_id: u64, kind: NotificationKind) -> mysql::Result<MentionTarget> {
+    let row: Option<(String, Option<u64>)> = conn.exec_first(
+        "SELECT target, role_id FROM mention_settings WHERE server_id=:server_id AND notification=:notification",
+        params! {"server_id" => server_id, "notification" => kind.as_str()},
+    )?;
+    Ok(row.map(|(target, role_id)| MentionTarget::from_row(&target, role_id)).unwrap_or(MentionTarget::default_for(kind)))
+}
+
+pub fn save_mention<Q: Queryable>(conn: &mut Q, server_id: u64, kind: NotificationKind, target: MentionTarget) -> mysql::Result<()> {
+    let (target, role_id) = target.to_row();
+    conn.exec_drop(
+        "INSERT INTO mention_settings (server_id, notification, target, role_id) VALUES (:server_id, :notification, :target, :role_id)
+        ON DUPLICATE KEY UPDATE target=:target, role_id=:role_id",
+        params! {"server_id" => server_id, "notification" => kind.as_str(), "target" => target, "role_id" => role_id},
+    )
+}
+
+/// Whether any kind of notification in the server pings the contest participants role. Members then need the
+/// button on reminders to add the role, whichever kind pings it.
+pub fn uses_participants<Q: Queryable>(conn: &mut Q, server_id: u64) -> mysql::Result<bool> {
+    Ok(load_mentions(conn, server_id)?.into_iter().any(|(_, target)| target == MentionTarget::Participants))
+}
+
+/// The contest participants role of the server, if the bot has created one.
+pub fn participant_role<Q: Queryable>(conn: &mut Q, server_id: u64) -> mysql::Result<Option<u64>> {
+    let role: Option<Option<u64>> = conn.exec_first(
+        "SELECT participant_role_id FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => server_id},
+    )?;
+    Ok(role.flatten())
+}
+
+/// The text that pings whoever `kind` notifications in the server should ping.
+pub fn mention_text<Q: Queryable>(conn: &mut Q, server_id: u64, kind: NotificationKind) -> mysql::Result<Option<String>> {
+    let target = load_mention(conn, server_id, kind)?;
+    let participant_role = match target {
+        MentionTarget::Participants => participant_role(conn, server_id)?,
+        _ => None,
+    };
+    Ok(target.text(participant_role))
+}
+
+/// `content` with the mention in front of it.
+pub fn with_mention(mention: Option<String>, content: &str) -> String {
+    match mention {
+        Some(mention) => format!("{} {}", mention, content),
+        None => content.to_string(),
+    }
+}
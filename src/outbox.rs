@@ -0,0 +1,517 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use mysql::prelude::*;
+use mysql::*;
+use poise::serenity_prelude::{
+    self as serenity, ButtonStyle, ChannelId, CreateActionRow, CreateAttachment, CreateButton, CreateEmbed, CreateMessage, EditMessage, Embed, GuildId,
+    HttpError, MessageId,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+
+use crate::quiet_hours;
+use crate::scraping::contest_id::ContestId;
+use crate::utils::MAX_DESCRIPTION_LENGTH;
+
+/// Deliveries are given up after this many failed attempts.
+const MAX_ATTEMPTS: i32 = 6;
+/// The first retry waits this long, and every further retry twice as long as the one before.
+const RETRY_BASE_SECONDS: i64 = 30;
+/// Delivered rows are kept this long for troubleshooting.
+const KEEP_SENT_DAYS: i64 = 7;
+
+/// What a queued message is about. Stored in `notification_outbox.kind`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutboxKind {
+    ContestReminder,
+    DailyContests,
+    ContestChange,
+    Submission,
+    Ranking,
+    RatingUpdate,
+    RsvpReminder,
+    WeeklySchedule,
+    /// The messages held during quiet hours, merged by [`release_held`].
+    QuietSummary,
+}
+
+impl OutboxKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            OutboxKind::ContestReminder => "contest_reminder",
+            OutboxKind::DailyContests => "daily_contests",
+            OutboxKind::ContestChange => "contest_change",
+            OutboxKind::Submission => "submission",
+            OutboxKind::Ranking => "ranking",
+            OutboxKind::RatingUpdate => "rating_update",
+            OutboxKind::RsvpReminder => "rsvp_reminder",
+            OutboxKind::WeeklySchedule => "weekly_schedule",
+            OutboxKind::QuietSummary => "quiet_summary",
+        }
+    }
+}
+
+/// A channel message waiting to be delivered by [`deliver`].
+#[derive(Debug)]
+pub struct OutboxMessage {
+    kind: OutboxKind,
+    server_id: Option<u64>,
+    channel_id: u64,
+    contest_id: Option<ContestId>,
+    edit_message_id: Option<u64>,
+    content: Option<String>,
+    embeds: Vec<CreateEmbed>,
+    button_rows: Vec<Vec<(String, String)>>,
+    attachment: Option<(String, Vec<u8>)>,
+}
+
+/// The part of a message that is stored as JSON in `notification_outbox.payload`.
+#[derive(Serialize, Deserialize)]
+struct Payload {
+    content: Option<String>,
+    #[serde(default)]
+    embeds: Vec<serde_json::Value>,
+    /// Rows of `(custom id, label)` buttons shown below the message.
+    #[serde(default)]
+    button_rows: Vec<Vec<(String, String)>>,
+}
+
+impl OutboxMessage {
+    /// `server_id` is the guild whose owner is told when the channel turns out to be gone.
+    pub fn new(kind: OutboxKind, server_id: Option<u64>, channel_id: u64) -> OutboxMessage {
+        OutboxMessage {
+            kind,
+            server_id,
+            channel_id,
+            contest_id: None,
+            edit_message_id: None,
+            content: None,
+            embeds: vec![],
+            button_rows: vec![],
+            attachment: None,
+        }
+    }
+
+    pub fn content(mut self, content: impl Into<String>) -> Self {
+        self.content = Some(content.into());
+        self
+    }
+
+    pub fn embeds(mut self, embeds: Vec<CreateEmbed>) -> Self {
+        self.embeds.extend(embeds);
+        self
+    }
+
+    /// Add a row of `(custom id, label)` buttons routed through [`crate::components::ComponentRouter`].
+    /// Discord shows at most 5 rows of 5 buttons.
+    pub fn button_row(mut self, buttons: Vec<(String, String)>) -> Self {
+        self.button_rows.push(buttons);
+        self
+    }
+
+    pub fn attachment(mut self, filename: &str, data: Vec<u8>) -> Self {
+        self.attachment = Some((filename.to_string(), data));
+        self
+    }
+
+    /// Tie the message to a contest. A newer message for the same contest and channel replaces one that is still
+    /// queued, and a delivered [`OutboxKind::Ranking`] message is recorded in `messages` so that later updates edit it.
+    pub fn contest(mut self, contest_id: &ContestId) -> Self {
+        self.contest_id = Some(contest_id.clone());
+        self
+    }
+
+    /// Edit an earlier message instead of sending a new one.
+    pub fn edit(mut self, message_id: u64) -> Self {
+        self.edit_message_id = Some(message_id);
+        self
+    }
+}
+
+/// Queue a message for [`deliver`], or hold it until the quiet hours of its server end.
+pub fn enqueue<Q: Queryable>(conn: &mut Q, message: OutboxMessage) -> mysql::Result<()> {
+    let held = match message.server_id {
+        Some(server_id) => quiet_hours::load(conn, server_id)?.is_some_and(|quiet_hours| quiet_hours.holds(message.kind, Utc::now())),
+        None => false,
+    };
+    let payload = Payload {
+        content: message.content,
+        embeds: message.embeds.iter().filter_map(|embed| serde_json::to_value(embed).ok()).collect(),
+        button_rows: message.button_rows,
+    };
+    if let Some(contest_id) = &message.contest_id {
+        conn.exec_drop(
+            "DELETE FROM notification_outbox
+            WHERE status IN ('pending', 'held') AND kind=:kind AND channel_id=:channel_id AND contest_id=:contest_id",
+            params! {"kind" => message.kind.as_str(), "channel_id" => message.channel_id, "contest_id" => contest_id.as_str()},
+        )?;
+    }
+    let (attachment_name, attachment) = message.attachment.unzip();
+    conn.exec_drop(
+        "INSERT INTO notification_outbox
+            (server_id, channel_id, kind, contest_id, edit_message_id, payload, attachment_name, attachment, status, next_attempt_at, created_at)
+        VALUES
+            (:server_id, :channel_id, :kind, :contest_id, :edit_message_id, :payload, :attachment_name, :attachment, :status, UTC_TIMESTAMP(), UTC_TIMESTAMP())",
+        params! {
+            "status" => if held { "held" } else { "pending" },
+            "server_id" => message.server_id,
+            "channel_id" => message.channel_id,
+            "kind" => message.kind.as_str(),
+            "contest_id" => message.contest_id.as_ref().map(|contest_id| contest_id.as_str()),
+            "edit_message_id" => message.edit_message_id,
+            "payload" => serde_json::to_string(&payload).unwrap_or_default(),
+            "attachment_name" => attachment_name,
+            "attachment" => attachment,
+        },
+    )
+}
+
+/// One message with everything held for a channel during quiet hours. The ACs are listed in a single embed and the
+/// embeds of other messages are kept as they were.
+fn quiet_summary(server_id: u64, channel_id: u64, held: &[(String, Payload)], lang: &str) -> OutboxMessage {
+    let mut ac_lines = vec![];
+    let mut embeds = vec![];
+    for (kind, payload) in held {
+        if kind == OutboxKind::Submission.as_str() {
+            for embed in &payload.embeds {
+                let unique = embed["title"].as_str().is_some_and(|title| title.starts_with("[unique]"));
+                if let Some(description) = embed["description"].as_str() {
+                    ac_lines.push(format!("- {}{}", if unique { "[unique] " } else { "" }, description));
+                }
+            }
+        } else {
+            embeds.extend(payload.embeds.iter().filter_map(|embed| serde_json::from_value::<Embed>(embed.clone()).ok()).map(CreateEmbed::from));
+        }
+    }
+    if !ac_lines.is_empty() {
+        let mut description = String::new();
+        let mut listed = 0;
+        for line in &ac_lines {
+            // Leave room for the line that counts the rest.
+            if description.chars().count() + line.chars().count() + 32 > MAX_DESCRIPTION_LENGTH {
+                break;
+            }
+            description += line;
+            description += "\n";
+            listed += 1;
+        }
+        if listed < ac_lines.len() {
+            description += &if lang == "ja" {
+                format!("ほか{}件", ac_lines.len() - listed)
+            } else {
+                format!("and {} more", ac_lines.len() - listed)
+            };
+        }
+        embeds.insert(0, CreateEmbed::new().title(format!("AC Notify ({})", ac_lines.len())).description(description));
+    }
+    // Discord accepts at most 10 embeds per message.
+    if embeds.len() > 10 {
+        let rest = embeds.len() - 9;
+        embeds.truncate(9);
+        embeds.push(CreateEmbed::new().description(if lang == "ja" {
+            format!("ほか{}件の通知は省略しました。", rest)
+        } else {
+            format!("{} more notifications were left out.", rest)
+        }));
+    }
+    let content = if lang == "ja" {
+        format!("静かな時間帯に届いた{}件の通知です", held.len())
+    } else {
+        format!("{} notifications arrived during quiet hours", held.len())
+    };
+    OutboxMessage::new(OutboxKind::QuietSummary, Some(server_id), channel_id).content(content).embeds(embeds)
+}
+
+/// `(id, channel id, kind, edit message id, payload, attachment name)` of a held message.
+type HeldRow = (u64, u64, String, Option<u64>, String, Option<String>);
+
+/// Deliver the messages of every server whose quiet hours are over. The messages of each channel are merged into one
+/// summary, except those with an attachment, buttons or an edit, which are delivered as they were.
+pub async fn release_held(pool: &Arc<Mutex<Pool>>) -> mysql::Result<()> {
+    let pool = pool.lock().await.clone();
+    let mut conn = pool.get_conn()?;
+    let servers: Vec<u64> = conn.query("SELECT DISTINCT server_id FROM notification_outbox WHERE status='held' AND server_id IS NOT NULL")?;
+    let now = Utc::now();
+    for server_id in servers {
+        if quiet_hours::load(&mut conn, server_id)?.is_some_and(|quiet_hours| quiet_hours.contains(now)) {
+            continue;
+        }
+        let lang: Option<String> = conn.exec_first(
+            r"SELECT language FROM server_settings WHERE server_id=:server_id",
+            params! {"server_id" => server_id},
+        )?;
+        let lang = lang.unwrap_or("ja".to_string());
+        let rows: Vec<HeldRow> = conn.exec(
+            "SELECT id, channel_id, kind, edit_message_id, payload, attachment_name FROM notification_outbox
+            WHERE status='held' AND server_id=:server_id ORDER BY id",
+            params! {"server_id" => server_id},
+        )?;
+
+        let mut transaction = conn.start_transaction(TxOpts::default())?;
+        let mut channels: BTreeMap<u64, Vec<(u64, String, Payload)>> = BTreeMap::new();
+        for (id, channel_id, kind, edit_message_id, payload, attachment_name) in rows {
+            match serde_json::from_str::<Payload>(&payload) {
+                Ok(payload) if edit_message_id.is_none() && attachment_name.is_none() && payload.button_rows.is_empty() && !payload.embeds.is_empty() => {
+                    channels.entry(channel_id).or_default().push((id, kind, payload));
+                }
+                _ => transaction.exec_drop(
+                    "UPDATE notification_outbox SET status='pending', next_attempt_at=UTC_TIMESTAMP() WHERE id=:id",
+                    params! {"id" => id},
+                )?,
+            }
+        }
+        for (channel_id, held) in channels {
+            let ids: Vec<u64> = held.iter().map(|(id, _, _)| *id).collect();
+            let held: Vec<(String, Payload)> = held.into_iter().map(|(_, kind, payload)| (kind, payload)).collect();
+            enqueue(&mut transaction, quiet_summary(server_id, channel_id, &held, &lang))?;
+            transaction.exec_batch("DELETE FROM notification_outbox WHERE id=:id", ids.iter().map(|id| params! {"id" => id}))?;
+        }
+        transaction.commit()?;
+    }
+    Ok(())
+}
+
+/// How a failed delivery is handled.
+#[derive(Debug, PartialEq)]
+enum Failure {
+    /// The channel was deleted or the bot can no longer see it.
+    DeadChannel,
+    /// The message to edit was deleted.
+    UnknownMessage,
+    /// Discord rejected the message itself, so sending it again will not help.
+    Permanent,
+    Retry,
+}
+
+fn classify(err: &serenity::Error) -> Failure {
+    match err {
+        serenity::Error::Http(HttpError::UnsuccessfulRequest(response)) => match response.error.code {
+            // Unknown Channel, Missing Access
+            10003 | 50001 => Failure::DeadChannel,
+            // Unknown Message
+            10008 => Failure::UnknownMessage,
+            _ if response.status_code.is_server_error() || response.status_code.as_u16() == 429 => Failure::Retry,
+            _ => Failure::Permanent,
+        },
+        serenity::Error::Model(_) | serenity::Error::Json(_) => Failure::Permanent,
+        _ => Failure::Retry,
+    }
+}
+
+type OutboxRow = (
+    u64,
+    Option<u64>,
+    u64,
+    String,
+    Option<String>,
+    Option<u64>,
+    String,
+    Option<String>,
+    Option<Vec<u8>>,
+    i32,
+);
+
+/// Send or edit the queued message. Returns the id of a newly sent message.
+async fn send(ctx: &serenity::Context, row: &OutboxRow) -> std::result::Result<Option<MessageId>, serenity::Error> {
+    let (_, _, channel_id, _, _, edit_message_id, payload, attachment_name, attachment, _) = row;
+    let payload: Payload = serde_json::from_str(payload)?;
+    let embeds: Vec<CreateEmbed> = payload.embeds.into_iter().filter_map(|embed| serde_json::from_value::<Embed>(embed).ok()).map(CreateEmbed::from).collect();
+    let attachment = match (attachment_name, attachment) {
+        (Some(name), Some(data)) => Some(CreateAttachment::bytes(data.clone(), name.as_str())),
+        _ => None,
+    };
+    let components: Vec<CreateActionRow> = payload
+        .button_rows
+        .into_iter()
+        .map(|row| {
+            CreateActionRow::Buttons(
+                row.into_iter().map(|(custom_id, label)| CreateButton::new(custom_id).label(label).style(ButtonStyle::Secondary)).collect(),
+            )
+        })
+        .collect();
+    let channel = ChannelId::new(*channel_id);
+    match edit_message_id {
+        Some(message_id) => {
+            let mut message = EditMessage::new().embeds(embeds).components(components);
+            if let Some(content) = payload.content {
+                message = message.content(content);
+            }
+            if let Some(attachment) = attachment {
+                message = message.new_attachment(attachment);
+            }
+            channel.edit_message(&ctx.http, MessageId::new(*message_id), message).await?;
+            Ok(None)
+        }
+        None => {
+            let mut message = CreateMessage::new().embeds(embeds).components(components);
+            if let Some(content) = payload.content {
+                message = message.content(content);
+            }
+            if let Some(attachment) = attachment {
+                message = message.add_file(attachment);
+            }
+            Ok(Some(channel.send_message(&ctx.http, message).await?.id))
+        }
+    }
+}
+
+/// Deliver queued messages forever, retrying failures with exponential backoff.
+///
+/// When Discord reports that a channel is gone or no longer accessible, the channel is removed from
+/// `notifications`, every message still queued for it is dropped, and the owner of the server is told.
+pub async fn deliver(pool: &Arc<Mutex<Pool>>, ctx: &serenity::Context) {
+    let pool = pool.lock().await.clone();
+    let mut last_cleanup = Instant::now();
+    loop {
+        if let Err(err) = deliver_due(&pool, ctx).await {
+            log::warn!("outbox delivery failed: {}", err);
+        }
+        if last_cleanup.elapsed() >= Duration::from_secs(60 * 60) {
+            if let Ok(mut conn) = pool.get_conn() {
+                conn.exec_drop(
+                    "DELETE FROM notification_outbox WHERE status='sent' AND sent_at < UTC_TIMESTAMP() - INTERVAL :days DAY",
+                    params! {"days" => KEEP_SENT_DAYS},
+                )
+                .unwrap_or_else(|err| log::warn!("could not clean up the outbox: {}", err));
+            }
+            last_cleanup = Instant::now();
+        }
+        sleep(Duration::from_secs(2)).await;
+    }
+}
+
+async fn deliver_due(pool: &Pool, ctx: &serenity::Context) -> mysql::Result<()> {
+    let mut conn = pool.get_conn()?;
+    let rows: Vec<OutboxRow> = conn.query(
+        "SELECT id, server_id, channel_id, kind, contest_id, edit_message_id, payload, attachment_name, attachment, attempts
+        FROM notification_outbox
+        WHERE status='pending' AND next_attempt_at <= UTC_TIMESTAMP()
+        ORDER BY id
+        LIMIT 20",
+    )?;
+    for row in rows {
+        let (id, server_id, channel_id, kind, contest_id, _, _, _, _, attempts) = &row;
+        // A channel found dead earlier in this batch has had its messages dropped already.
+        let status: Option<String> = conn.exec_first("SELECT status FROM notification_outbox WHERE id=:id", params! {"id" => id})?;
+        if status.as_deref() != Some("pending") {
+            continue;
+        }
+        let err = match send(ctx, &row).await {
+            Ok(message_id) => {
+                // Sent rows are only kept for their metadata, so the image of each standings update is dropped right away.
+                conn.exec_drop(
+                    "UPDATE notification_outbox SET status='sent', attempts=attempts+1, sent_at=UTC_TIMESTAMP(), attachment=NULL WHERE id=:id",
+                    params! {"id" => id},
+                )?;
+                if let (Some(message_id), Some(contest_id)) = (message_id, contest_id) {
+                    if kind == OutboxKind::Ranking.as_str() {
+                        conn.exec_drop(
+                            "INSERT INTO messages (contest_id, channel_id, message_id) VALUES (:contest_id, :channel_id, :message_id)",
+                            params! {"contest_id" => contest_id, "channel_id" => channel_id, "message_id" => message_id.get()},
+                        )?;
+                    }
+                }
+                continue;
+            }
+            Err(err) => err,
+        };
+        let failure = classify(&err);
+        log::warn!("outbox {} to channel {} failed ({:?}): {}", id, channel_id, failure, err);
+        match failure {
+            Failure::Retry if attempts + 1 < MAX_ATTEMPTS => {
+                conn.exec_drop(
+                    "UPDATE notification_outbox
+                    SET attempts=attempts+1, last_error=:error, next_attempt_at=UTC_TIMESTAMP() + INTERVAL :delay SECOND
+                    WHERE id=:id",
+                    params! {"id" => id, "error" => err.to_string(), "delay" => RETRY_BASE_SECONDS << attempts},
+                )?;
+            }
+            Failure::DeadChannel => {
+                conn.exec_drop(
+                    "UPDATE notification_outbox SET status='dead', attempts=attempts+1, last_error=:error
+                    WHERE channel_id=:channel_id AND status IN ('pending', 'held')",
+                    params! {"channel_id" => channel_id, "error" => err.to_string()},
+                )?;
+                let channel = channel_id.to_string();
+                let unset: u64 = ["contest_channel_id", "submission_channel_id"]
+                    .iter()
+                    .map(|column| {
+                        conn.exec_drop(
+                            format!("UPDATE notifications SET {0}=NULL WHERE {0}=:channel_id", column),
+                            params! {"channel_id" => &channel},
+                        )
+                        .map(|_| conn.affected_rows())
+                    })
+                    .sum::<mysql::Result<u64>>()?;
+                conn.exec_drop(
+                    "DELETE FROM notification_routes WHERE channel_id=:channel_id",
+                    params! {"channel_id" => channel_id},
+                )?;
+                let unset = unset + conn.affected_rows();
+                if unset > 0 {
+                    if let Some(server_id) = server_id {
+                        tell_owner(ctx, &mut conn, *server_id, *channel_id).await;
+                    }
+                }
+            }
+            Failure::UnknownMessage => {
+                conn.exec_drop(
+                    "UPDATE notification_outbox SET status='failed', attempts=attempts+1, last_error=:error WHERE id=:id",
+                    params! {"id" => id, "error" => err.to_string()},
+                )?;
+                // The next update posts a fresh message.
+                conn.exec_drop(
+                    "DELETE FROM messages WHERE channel_id=:channel_id AND contest_id=:contest_id",
+                    params! {"channel_id" => channel_id, "contest_id" => contest_id},
+                )?;
+            }
+            Failure::Retry | Failure::Permanent => {
+                conn.exec_drop(
+                    "UPDATE notification_outbox SET status='failed', attempts=attempts+1, last_error=:error WHERE id=:id",
+                    params! {"id" => id, "error" => err.to_string()},
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// DM the server owner that notifications to `channel_id` were turned off.
+async fn tell_owner(ctx: &serenity::Context, conn: &mut PooledConn, server_id: u64, channel_id: u64) {
+    let guild_id = GuildId::new(server_id);
+    let cached = ctx.cache.guild(guild_id).map(|guild| (guild.owner_id, guild.name.clone()));
+    let (owner_id, guild_name) = match cached {
+        Some(guild) => guild,
+        None => match guild_id.to_partial_guild(&ctx.http).await {
+            Ok(guild) => (guild.owner_id, guild.name),
+            Err(err) => {
+                log::info!("could not look up the owner of {}: {}", server_id, err);
+                return;
+            }
+        },
+    };
+    let lang: Option<String> = conn
+        .exec_first(
+            r"SELECT language FROM server_settings WHERE server_id=:server_id",
+            params! {"server_id" => server_id},
+        )
+        .unwrap_or_default();
+    let content = if lang.as_deref().unwrap_or("ja") == "ja" {
+        format!(
+            "サーバー「{}」の通知チャンネル <#{}> に投稿できなくなったため、通知設定を解除しました。`/atcoder set-notification` で設定し直してください。",
+            guild_name, channel_id
+        )
+    } else {
+        format!(
+            "The bot can no longer post to the notification channel <#{}> in \"{}\", so that notification has been turned off. Use `/atcoder set-notification` to choose a new channel.",
+            channel_id, guild_name
+        )
+    };
+    if let Err(err) = owner_id.dm(&ctx.http, CreateMessage::new().content(content)).await {
+        log::info!("could not tell the owner of {}: {}", server_id, err);
+    }
+}
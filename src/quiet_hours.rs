@@ -0,0 +1,58 @@
+//! Quiet hours of each server, kept in `server_settings.quiet_hours_start` and `server_settings.quiet_hours_end` in
+//! the server's time zone. Messages queued during them are held in the outbox and delivered as one summary per
+//! channel by [`crate::outbox::release_held`] when they end.
+
+use chrono::{DateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
+use mysql::prelude::*;
+use mysql::*;
+
+use crate::outbox::OutboxKind;
+use crate::timezone;
+
+#[derive(Debug, Clone, Copy)]
+pub struct QuietHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub timezone: Tz,
+    /// Whether contest reminders and live standings are held too. They are sent right away by default.
+    pub hold_reminders: bool,
+}
+
+impl QuietHours {
+    /// Whether `now` falls in the quiet hours, which may run past midnight such as from 23:00 to 07:00.
+    pub fn contains(&self, now: DateTime<Utc>) -> bool {
+        let time = now.with_timezone(&self.timezone).time();
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+
+    /// Whether a message of `kind` queued at `now` waits for the end of the quiet hours.
+    pub fn holds(&self, kind: OutboxKind, now: DateTime<Utc>) -> bool {
+        let exempt = matches!(kind, OutboxKind::ContestReminder | OutboxKind::RsvpReminder | OutboxKind::Ranking) && !self.hold_reminders;
+        !exempt && self.contains(now)
+    }
+}
+
+/// `(start, end, hold reminders, time zone)` of a server.
+type QuietHoursTuple = (Option<NaiveTime>, Option<NaiveTime>, i32, Option<String>);
+
+/// The quiet hours of a server, or `None` when it has none.
+pub fn load<Q: Queryable>(conn: &mut Q, server_id: u64) -> Result<Option<QuietHours>> {
+    let settings: Option<QuietHoursTuple> = conn.exec_first(
+        "SELECT quiet_hours_start, quiet_hours_end, quiet_hours_hold_reminders, timezone FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => server_id},
+    )?;
+    Ok(match settings {
+        Some((Some(start), Some(end), hold_reminders, timezone)) if start != end => Some(QuietHours {
+            start,
+            end,
+            timezone: timezone.as_deref().and_then(timezone::parse_timezone).unwrap_or(timezone::DEFAULT_TIMEZONE),
+            hold_reminders: hold_reminders == 1,
+        }),
+        _ => None,
+    })
+}
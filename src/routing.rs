@@ -0,0 +1,351 @@
+//! Rules that send one kind of notification to other channels than the contest or submission channel, stored per
+//! server in `notification_routes`.
+//!
+//! A route matches a contest when its filters, the contest type and the series, all match. The notifications about a
+//! contest go to every channel with a matching route for that kind, or to the default channel of `notifications`
+//! when none matches.
+
+use std::collections::HashMap;
+
+use mysql::prelude::*;
+use mysql::*;
+
+use crate::scraping::contest_id::ContestId;
+
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum RouteEvent {
+    /// One hour before a contest, including the pings of members who answered in.
+    #[name = "reminder"]
+    Reminder,
+    /// The list of today's contests, and the weekly schedule.
+    #[name = "daily"]
+    Daily,
+    /// The server ranking while a contest runs.
+    #[name = "standings"]
+    Standings,
+    /// Rating updates after a contest.
+    #[name = "results"]
+    Results,
+    #[name = "ac"]
+    Submission,
+    /// Newly announced, changed and cancelled contests.
+    #[name = "announced"]
+    Announced,
+}
+
+impl RouteEvent {
+    pub const ALL: [RouteEvent; 6] = [
+        RouteEvent::Reminder,
+        RouteEvent::Daily,
+        RouteEvent::Standings,
+        RouteEvent::Results,
+        RouteEvent::Submission,
+        RouteEvent::Announced,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RouteEvent::Reminder => "reminder",
+            RouteEvent::Daily => "daily",
+            RouteEvent::Standings => "standings",
+            RouteEvent::Results => "results",
+            RouteEvent::Submission => "ac",
+            RouteEvent::Announced => "announced",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<RouteEvent> {
+        RouteEvent::ALL.into_iter().find(|event| event.as_str() == value)
+    }
+
+    pub fn label(self, lang: &str) -> &'static str {
+        match (self, lang) {
+            (RouteEvent::Reminder, "ja") => "1時間前通知",
+            (RouteEvent::Daily, "ja") => "今日のコンテスト",
+            (RouteEvent::Standings, "ja") => "サーバー内ランキング",
+            (RouteEvent::Results, "ja") => "レーティング更新",
+            (RouteEvent::Submission, "ja") => "AC通知",
+            (RouteEvent::Announced, "ja") => "コンテストの告知",
+            (RouteEvent::Reminder, _) => "Reminder",
+            (RouteEvent::Daily, _) => "Daily summary",
+            (RouteEvent::Standings, _) => "Live standings",
+            (RouteEvent::Results, _) => "Results",
+            (RouteEvent::Submission, _) => "AC notification",
+            (RouteEvent::Announced, _) => "Contest announcements",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum RouteContestType {
+    #[name = "algorithm"]
+    Algorithm,
+    #[name = "heuristic"]
+    Heuristic,
+}
+
+impl RouteContestType {
+    /// As stored in `contests.contest_type`.
+    pub fn value(self) -> i8 {
+        match self {
+            RouteContestType::Algorithm => 0,
+            RouteContestType::Heuristic => 1,
+        }
+    }
+
+    pub fn from_value(value: i8) -> RouteContestType {
+        match value {
+            0 => RouteContestType::Algorithm,
+            _ => RouteContestType::Heuristic,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            RouteContestType::Algorithm => "Algorithm",
+            RouteContestType::Heuristic => "Heuristic",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum Series {
+    #[name = "ABC"]
+    Abc,
+    #[name = "ARC"]
+    Arc,
+    #[name = "AGC"]
+    Agc,
+    #[name = "AHC"]
+    Ahc,
+    /// Every other contest, such as sponsored and unrated ones.
+    #[name = "other"]
+    Other,
+}
+
+impl Series {
+    pub const ALL: [Series; 5] = [Series::Abc, Series::Arc, Series::Agc, Series::Ahc, Series::Other];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Series::Abc => "abc",
+            Series::Arc => "arc",
+            Series::Agc => "agc",
+            Series::Ahc => "ahc",
+            Series::Other => "other",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Series> {
+        Series::ALL.into_iter().find(|series| series.as_str() == value)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Series::Abc => "ABC",
+            Series::Arc => "ARC",
+            Series::Agc => "AGC",
+            Series::Ahc => "AHC",
+            Series::Other => "Other",
+        }
+    }
+
+    /// The series of a contest, from the prefix of its id such as `abc` in `abc123`.
+    pub fn of(contest_id: &ContestId) -> Series {
+        [Series::Abc, Series::Arc, Series::Agc, Series::Ahc]
+            .into_iter()
+            .find(|series| contest_id.as_str().strip_prefix(series.as_str()).is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit())))
+            .unwrap_or(Series::Other)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Route {
+    pub event: RouteEvent,
+    pub channel_id: u64,
+    pub contest_type: Option<RouteContestType>,
+    pub series: Option<Series>,
+}
+
+impl Route {
+    /// A filter on the contest type never matches when the type is unknown.
+    fn matches(&self, contest_id: &ContestId, contest_type: Option<i8>) -> bool {
+        let type_matches = match self.contest_type {
+            Some(filter) => contest_type == Some(filter.value()),
+            None => true,
+        };
+        let series_matches = match self.series {
+            Some(filter) => Series::of(contest_id) == filter,
+            None => true,
+        };
+        type_matches && series_matches
+    }
+
+    /// One line for the settings, such as `Live standings: #heuristic (Heuristic)`.
+    pub fn text(&self, lang: &str) -> String {
+        format!(
+            "{}: <#{}> ({})",
+            self.event.label(lang),
+            self.channel_id,
+            filter_text(self.contest_type, self.series, lang)
+        )
+    }
+}
+
+/// The contests that a route with these filters matches.
+pub fn filter_text(contest_type: Option<RouteContestType>, series: Option<Series>, lang: &str) -> String {
+    let filters: Vec<&str> = [contest_type.map(RouteContestType::label), series.map(Series::label)].into_iter().flatten().collect();
+    if filters.is_empty() {
+        if lang == "ja" {
+            "すべてのコンテスト".to_string()
+        } else {
+            "all contests".to_string()
+        }
+    } else {
+        filters.join(" / ")
+    }
+}
+
+/// `(server id, event, channel id, contest type, series)` of a row of `notification_routes`.
+type RouteTuple = (u64, String, u64, Option<i8>, Option<String>);
+
+fn route_from_row((server_id, event, channel_id, contest_type, series): RouteTuple) -> Option<(u64, Route)> {
+    let route = Route {
+        event: RouteEvent::parse(&event)?,
+        channel_id,
+        contest_type: contest_type.map(RouteContestType::from_value),
+        series: series.as_deref().and_then(Series::parse),
+    };
+    Some((server_id, route))
+}
+
+/// The routes of a server, in the order they were added.
+pub fn server_routes<Q: Queryable>(conn: &mut Q, server_id: u64) -> Result<Vec<Route>> {
+    let rows: Vec<RouteTuple> = conn.exec(
+        "SELECT server_id, event, channel_id, contest_type, series FROM notification_routes WHERE server_id=:server_id ORDER BY id",
+        params! {"server_id" => server_id},
+    )?;
+    Ok(rows.into_iter().filter_map(route_from_row).map(|(_, route)| route).collect())
+}
+
+/// The routes of every server for one kind of notification, loaded once by each sender.
+pub struct Routes(HashMap<u64, Vec<Route>>);
+
+impl Routes {
+    pub fn load<Q: Queryable>(conn: &mut Q, event: RouteEvent) -> Result<Routes> {
+        let rows: Vec<RouteTuple> = conn.exec(
+            "SELECT server_id, event, channel_id, contest_type, series FROM notification_routes WHERE event=:event ORDER BY id",
+            params! {"event" => event.as_str()},
+        )?;
+        let mut routes: HashMap<u64, Vec<Route>> = HashMap::new();
+        for (server_id, route) in rows.into_iter().filter_map(route_from_row) {
+            routes.entry(server_id).or_default().push(route);
+        }
+        Ok(Routes(routes))
+    }
+
+    /// The channels of `server_id` that get the notification about a contest: those of the matching routes, or
+    /// `default_channel` when none matches.
+    pub fn channels(&self, server_id: u64, default_channel: Option<u64>, contest_id: &ContestId, contest_type: Option<i8>) -> Vec<u64> {
+        let mut channels = vec![];
+        for route in self.0.get(&server_id).into_iter().flatten() {
+            if route.matches(contest_id, contest_type) && !channels.contains(&route.channel_id) {
+                channels.push(route.channel_id);
+            }
+        }
+        if channels.is_empty() {
+            channels.extend(default_channel);
+        }
+        channels
+    }
+
+    /// Whether a contest passes the filters of `server_id` for this kind of notification, for features that follow
+    /// them without sending anything. A server without routes for it takes every contest, and one with routes takes
+    /// the contests that [`Routes::channels`] sends somewhere.
+    pub fn includes(&self, server_id: u64, default_channel: Option<u64>, contest_id: &ContestId, contest_type: Option<i8>) -> bool {
+        !self.0.contains_key(&server_id) || !self.channels(server_id, default_channel, contest_id, contest_type).is_empty()
+    }
+
+    /// Split the `items` of a notification about several contests by the channels they go to, keeping their order.
+    pub fn group<'a, T>(
+        &self,
+        server_id: u64,
+        default_channel: Option<u64>,
+        items: &'a [T],
+        contest: impl Fn(&T) -> (&ContestId, Option<i8>),
+    ) -> Vec<(u64, Vec<&'a T>)> {
+        let mut groups: Vec<(u64, Vec<&'a T>)> = vec![];
+        for item in items {
+            let (contest_id, contest_type) = contest(item);
+            for channel_id in self.channels(server_id, default_channel, contest_id, contest_type) {
+                match groups.iter_mut().find(|(group_channel_id, _)| *group_channel_id == channel_id) {
+                    Some((_, group)) => group.push(item),
+                    None => groups.push((channel_id, vec![item])),
+                }
+            }
+        }
+        groups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(channel_id: u64, contest_type: Option<RouteContestType>, series: Option<Series>) -> Route {
+        Route {
+            event: RouteEvent::Reminder,
+            channel_id,
+            contest_type,
+            series,
+        }
+    }
+
+    fn routes() -> Routes {
+        Routes(HashMap::from([
+            (1, vec![route(10, Some(RouteContestType::Heuristic), None), route(11, None, Some(Series::Abc))]),
+            (2, vec![route(20, None, Some(Series::Agc))]),
+        ]))
+    }
+
+    fn contests() -> Vec<(ContestId, Option<i8>)> {
+        vec![
+            (ContestId::new("abc350"), Some(0)),
+            (ContestId::new("arc170"), Some(0)),
+            (ContestId::new("agc066"), Some(0)),
+            (ContestId::new("ahc030"), Some(1)),
+            (ContestId::new("tessoku-book"), None),
+        ]
+    }
+
+    #[test]
+    fn includes_what_channels_sends_somewhere() {
+        let routes = routes();
+        for server_id in [1, 2] {
+            for default_channel in [None, Some(99)] {
+                for (contest_id, contest_type) in contests() {
+                    assert_eq!(
+                        routes.includes(server_id, default_channel, &contest_id, contest_type),
+                        !routes.channels(server_id, default_channel, &contest_id, contest_type).is_empty(),
+                        "server {} default {:?} contest {}",
+                        server_id,
+                        default_channel,
+                        contest_id.as_str()
+                    );
+                }
+            }
+        }
+        assert!(routes.includes(1, None, &ContestId::new("ahc030"), Some(1)));
+        assert!(!routes.includes(1, None, &ContestId::new("arc170"), Some(0)));
+        assert!(routes.includes(1, Some(99), &ContestId::new("arc170"), Some(0)));
+    }
+
+    #[test]
+    fn includes_every_contest_without_routes() {
+        let routes = routes();
+        for (contest_id, contest_type) in contests() {
+            assert!(routes.includes(3, None, &contest_id, contest_type));
+            assert!(routes.channels(3, None, &contest_id, contest_type).is_empty());
+        }
+    }
+}
@@ -0,0 +1,288 @@
+//! "I'm in" / "Skipping" answers to contest reminders, kept per contest and server in `contest_rsvps`.
+//!
+//! The buttons carry the contest in their custom id (`rsvp:in:<contest id>`), so they keep working
+//! after a restart. Their labels show the current counts and are refreshed on every click.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use mysql::prelude::*;
+use mysql::*;
+use poise::serenity_prelude::{
+    self as serenity, ActionRowComponent, ButtonKind, ComponentInteraction, CreateActionRow, CreateButton, CreateInteractionResponse,
+    CreateInteractionResponseFollowup, CreateInteractionResponseMessage,
+};
+use poise::BoxFuture;
+use tokio::sync::Mutex;
+
+use crate::components::ComponentRequest;
+use crate::outbox::{self, OutboxKind, OutboxMessage};
+use crate::routing::{RouteEvent, Routes};
+use crate::scraping::contest_id::ContestId;
+use crate::{Data, Error};
+
+/// Participants are pinged this many minutes before the contest starts.
+const REMIND_MINUTES: i64 = 5;
+/// Keeps a ping message well below the 2000 character limit.
+const MENTIONS_PER_MESSAGE: usize = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RsvpStatus {
+    In,
+    Skip,
+}
+
+impl RsvpStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            RsvpStatus::In => "in",
+            RsvpStatus::Skip => "skip",
+        }
+    }
+
+    fn from_str(value: &str) -> Option<RsvpStatus> {
+        match value {
+            "in" => Some(RsvpStatus::In),
+            "skip" => Some(RsvpStatus::Skip),
+            _ => None,
+        }
+    }
+}
+
+fn custom_id(status: RsvpStatus, contest_id: &ContestId) -> String {
+    format!("rsvp:{}:{}", status.as_str(), contest_id.as_str())
+}
+
+/// `in:abc300` → (In, abc300)
+fn parse_action(action: &str) -> Option<(RsvpStatus, ContestId)> {
+    let (status, contest_id) = action.split_once(':')?;
+    Some((RsvpStatus::from_str(status)?, ContestId::parse(contest_id)?))
+}
+
+/// `contest_id` is shown in front when the message has buttons for more than one contest.
+fn label(status: RsvpStatus, count: i64, contest_id: Option<&ContestId>, lang: &str) -> String {
+    let text = match (status, lang) {
+        (RsvpStatus::In, "ja") => "参加する",
+        (RsvpStatus::In, _) => "I'm in",
+        (RsvpStatus::Skip, "ja") => "見送る",
+        (RsvpStatus::Skip, _) => "Skipping",
+    };
+    match contest_id {
+        Some(contest_id) => format!("{}: {} ({})", contest_id.as_str(), text, count),
+        None => format!("{} ({})", text, count),
+    }
+}
+
+/// The number of members who answered in and skip.
+pub fn counts<Q: Queryable>(conn: &mut Q, server_id: u64, contest_id: &ContestId) -> mysql::Result<(i64, i64)> {
+    let rows: Vec<(String, i64)> = conn.exec(
+        "SELECT status, COUNT(*) FROM contest_rsvps WHERE server_id=:server_id AND contest_id=:contest_id GROUP BY status",
+        params! {"server_id" => server_id, "contest_id" => contest_id.as_str()},
+    )?;
+    let count = |status: RsvpStatus| rows.iter().find(|(value, _)| value == status.as_str()).map_or(0, |(_, count)| *count);
+    Ok((count(RsvpStatus::In), count(RsvpStatus::Skip)))
+}
+
+/// The RSVP buttons of one contest as `(custom id, label)` for [`OutboxMessage::button_row`].
+pub fn button_row<Q: Queryable>(conn: &mut Q, server_id: u64, contest_id: &ContestId, show_contest: bool, lang: &str) -> mysql::Result<Vec<(String, String)>> {
+    let (going, skipping) = counts(conn, server_id, contest_id)?;
+    let shown = show_contest.then_some(contest_id);
+    Ok(vec![
+        (custom_id(RsvpStatus::In, contest_id), label(RsvpStatus::In, going, shown, lang)),
+        (custom_id(RsvpStatus::Skip, contest_id), label(RsvpStatus::Skip, skipping, shown, lang)),
+    ])
+}
+
+/// Record the answer of `user_id`. Choosing the current answer again withdraws it.
+fn answer<Q: Queryable>(conn: &mut Q, server_id: u64, contest_id: &ContestId, user_id: u64, status: RsvpStatus) -> mysql::Result<Option<RsvpStatus>> {
+    let params = params! {"server_id" => server_id, "contest_id" => contest_id.as_str(), "user_id" => user_id, "status" => status.as_str()};
+    let current: Option<String> = conn.exec_first(
+        "SELECT status FROM contest_rsvps WHERE server_id=:server_id AND contest_id=:contest_id AND user_id=:user_id",
+        params.clone(),
+    )?;
+    if current.as_deref() == Some(status.as_str()) {
+        conn.exec_drop(
+            "DELETE FROM contest_rsvps WHERE server_id=:server_id AND contest_id=:contest_id AND user_id=:user_id",
+            params,
+        )?;
+        return Ok(None);
+    }
+    conn.exec_drop(
+        "INSERT INTO contest_rsvps (server_id, contest_id, user_id, status, updated_at) VALUES (:server_id, :contest_id, :user_id, :status, UTC_TIMESTAMP())
+        ON DUPLICATE KEY UPDATE status=:status, updated_at=UTC_TIMESTAMP()",
+        params,
+    )?;
+    Ok(Some(status))
+}
+
+/// AtCoder screen names of the members who answered in and have linked an account.
+pub fn attendee_accounts<Q: Queryable>(conn: &mut Q, server_id: u64, contest_id: &ContestId) -> mysql::Result<Vec<String>> {
+    conn.exec(
+        "SELECT COALESCE(atcoder_identities.screen_name, users.atcoder_username)
+        FROM contest_rsvps
+        JOIN users ON users.server_id = contest_rsvps.server_id AND users.discord_id = contest_rsvps.user_id
+        LEFT JOIN atcoder_identities ON atcoder_identities.user_key = users.atcoder_username
+        WHERE contest_rsvps.server_id=:server_id AND contest_rsvps.contest_id=:contest_id AND contest_rsvps.status='in'",
+        params! {"server_id" => server_id, "contest_id" => contest_id.as_str()},
+    )
+}
+
+/// Ping the members who answered in shortly before their contest starts, where each server gets the reminders.
+pub async fn remind(pool: &Arc<Mutex<Pool>>) -> Result<()> {
+    let pool = pool.lock().await;
+    let mut conn = pool.get_conn()?;
+    let routes = Routes::load(&mut conn, RouteEvent::Reminder)?;
+    let contests: Vec<(String, String, i8)> = conn.exec(
+        "SELECT contest_id, name, contest_type FROM contests WHERE is_rsvp_notified=0 AND start_at <= UTC_TIMESTAMP() + INTERVAL :minutes MINUTE",
+        params! {"minutes" => REMIND_MINUTES},
+    )?;
+    for (contest_id, name, contest_type) in contests {
+        let contest_id = ContestId::new(&contest_id);
+        let rsvps: Vec<(u64, u64, Option<String>, Option<String>)> = conn.exec(
+            "SELECT contest_rsvps.server_id, contest_rsvps.user_id, notifications.contest_channel_id, server_settings.language
+            FROM contest_rsvps
+            JOIN notifications ON notifications.server_id = contest_rsvps.server_id
+            LEFT JOIN server_settings ON server_settings.server_id = contest_rsvps.server_id
+            WHERE contest_rsvps.contest_id=:contest_id AND contest_rsvps.status='in'",
+            params! {"contest_id" => contest_id.as_str()},
+        )?;
+        let mut servers: BTreeMap<u64, (Option<u64>, String, Vec<u64>)> = BTreeMap::new();
+        for (server_id, user_id, channel_id, language) in rsvps {
+            let channel_id = channel_id.and_then(|channel_id| channel_id.parse::<u64>().ok());
+            servers.entry(server_id).or_insert((channel_id, language.unwrap_or("ja".to_string()), vec![])).2.push(user_id);
+        }
+
+        let mut transaction = conn.start_transaction(TxOpts::default())?;
+        for (server_id, (channel_id, lang, user_ids)) in servers {
+            // The members are pinged once, in the first channel that gets the reminder.
+            let Some(channel_id) = routes.channels(server_id, channel_id, &contest_id, Some(contest_type)).first().copied() else {
+                continue;
+            };
+            for chunk in user_ids.chunks(MENTIONS_PER_MESSAGE) {
+                let mentions = chunk.iter().map(|user_id| format!("<@{}>", user_id)).collect::<Vec<String>>().join(" ");
+                let content = if lang == "ja" {
+                    format!("{}\n[{}]({}) があと{}分で始まります！", mentions, name, contest_id.url(), REMIND_MINUTES)
+                } else {
+                    format!("{}\n[{}]({}) starts in {} minutes!", mentions, name, contest_id.url(), REMIND_MINUTES)
+                };
+                outbox::enqueue(
+                    &mut transaction,
+                    OutboxMessage::new(OutboxKind::RsvpReminder, Some(server_id), channel_id).content(content),
+                )?;
+            }
+        }
+        transaction.exec_drop(
+            "UPDATE contests SET is_rsvp_notified=1 WHERE contest_id=:contest_id",
+            params! {"contest_id" => contest_id.as_str()},
+        )?;
+        transaction.commit()?;
+    }
+    Ok(())
+}
+
+/// Record an answer from the buttons on a reminder and refresh the counts on it.
+pub fn handle_component<'a>(
+    ctx: &'a serenity::Context,
+    data: &'a Data,
+    interaction: &'a ComponentInteraction,
+    request: ComponentRequest<'a>,
+) -> BoxFuture<'a, Result<(), Error>> {
+    Box::pin(async move {
+        match parse_action(request.action) {
+            Some((status, contest_id)) => record_answer(ctx, data, interaction, status, &contest_id).await,
+            None => Ok(()),
+        }
+    })
+}
+
+async fn record_answer(
+    ctx: &serenity::Context,
+    data: &Data,
+    interaction: &ComponentInteraction,
+    status: RsvpStatus,
+    contest_id: &ContestId,
+) -> Result<(), Error> {
+    let Some(guild_id) = interaction.guild_id else {
+        return Ok(());
+    };
+    let pool = data.conn.lock().await.clone();
+    let mut conn = pool.get_conn()?;
+
+    let selected_data: Vec<String> = conn.exec(
+        r"SELECT language FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => guild_id.get()},
+    )?;
+    let mut lang = "ja";
+    if selected_data.len() == 1 {
+        lang = selected_data[0].as_str();
+    }
+
+    let started: Option<i64> = conn.exec_first(
+        "SELECT COUNT(*) FROM contests WHERE contest_id=:contest_id AND start_at <= UTC_TIMESTAMP()",
+        params! {"contest_id" => contest_id.as_str()},
+    )?;
+    if started.unwrap_or(0) > 0 {
+        let content = if lang == "ja" {
+            "このコンテストはすでに始まっています。"
+        } else {
+            "This contest has already started."
+        };
+        let response = CreateInteractionResponseMessage::default().content(content).ephemeral(true);
+        interaction.create_response(ctx, CreateInteractionResponse::Message(response)).await?;
+        return Ok(());
+    }
+
+    let answered = answer(&mut conn, guild_id.get(), contest_id, interaction.user.id.get(), status)?;
+
+    // Rebuild every button on the reminder so that the counts of all contests on it stay current.
+    let rsvp_contests = interaction
+        .message
+        .components
+        .iter()
+        .flat_map(|row| row.components.iter())
+        .filter(|component| match component {
+            ActionRowComponent::Button(button) => matches!(&button.data, ButtonKind::NonLink { custom_id, .. } if custom_id.starts_with("rsvp:in:")),
+            _ => false,
+        })
+        .count();
+    let mut rows = vec![];
+    for row in &interaction.message.components {
+        let mut buttons = vec![];
+        for component in &row.components {
+            let ActionRowComponent::Button(button) = component else {
+                continue;
+            };
+            let ButtonKind::NonLink { custom_id, style } = &button.data else {
+                continue;
+            };
+            let label = match custom_id.strip_prefix("rsvp:").and_then(parse_action) {
+                Some((status, contest_id)) => {
+                    let (going, skipping) = counts(&mut conn, guild_id.get(), &contest_id)?;
+                    let count = if status == RsvpStatus::In { going } else { skipping };
+                    label(status, count, (rsvp_contests > 1).then_some(&contest_id), lang)
+                }
+                None => button.label.clone().unwrap_or_default(),
+            };
+            buttons.push(CreateButton::new(custom_id).label(label).style(*style));
+        }
+        if !buttons.is_empty() {
+            rows.push(CreateActionRow::Buttons(buttons));
+        }
+    }
+    let response = CreateInteractionResponseMessage::default().components(rows);
+    interaction.create_response(ctx, CreateInteractionResponse::UpdateMessage(response)).await?;
+
+    let content = match (answered, lang) {
+        (Some(RsvpStatus::In), "ja") => format!("`{}` に参加で登録しました。開始{}分前にお知らせします。", contest_id, REMIND_MINUTES),
+        (Some(RsvpStatus::In), _) => format!(
+            "You are in for `{}`. You will be pinged {} minutes before it starts.",
+            contest_id, REMIND_MINUTES
+        ),
+        (Some(RsvpStatus::Skip), "ja") => format!("`{}` を見送りで登録しました。", contest_id),
+        (Some(RsvpStatus::Skip), _) => format!("You are skipping `{}`.", contest_id),
+        (None, "ja") => format!("`{}` への回答を取り消しました。", contest_id),
+        (None, _) => format!("Withdrew your answer for `{}`.", contest_id),
+    };
+    interaction.create_followup(ctx, CreateInteractionResponseFollowup::new().content(content).ephemeral(true)).await?;
+    Ok(())
+}
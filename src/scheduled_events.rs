@@ -0,0 +1,155 @@
+//! Discord scheduled events for servers that turned on `server_settings.scheduled_events`.
+//!
+//! An external event is created for every upcoming contest so that members can mark it as interesting and see it in
+//! the calendar of the server. The event of each contest is kept in `contest_events`, so that it can be edited or
+//! deleted when [`update_contests`](crate::scraping::contests::update_contests) notices that the contest changed.
+
+use std::sync::Arc;
+
+use chrono::{NaiveDateTime, Utc};
+use mysql::prelude::*;
+use mysql::*;
+use poise::serenity_prelude::{self as serenity, CreateScheduledEvent, EditScheduledEvent, GuildId, ScheduledEventId, ScheduledEventType};
+use tokio::sync::Mutex;
+
+use crate::routing::{RouteEvent, Routes};
+use crate::scraping::contest_id::ContestId;
+use crate::scraping::contests::{ContestChange, ContestChangeKind};
+
+/// Discord rejects event names longer than 100 characters.
+const MAX_EVENT_NAME_LENGTH: usize = 100;
+
+/// `(server id, contest id, name, start, end, rated range, contest type, language, contest channel id)` of a contest
+/// that a server has no event for yet.
+type UpcomingContestTuple = (u64, String, String, NaiveDateTime, NaiveDateTime, String, i8, Option<String>, Option<String>);
+
+fn event_name(name: &str) -> String {
+    name.chars().take(MAX_EVENT_NAME_LENGTH).collect()
+}
+
+fn event_description(contest: &ContestId, rating_range_raw: &str, lang: &str) -> String {
+    if lang == "ja" {
+        format!("{}\nRated対象: `{}`", contest.url(), rating_range_raw)
+    } else {
+        format!("{}\nRated target: `{}`", contest.url(), rating_range_raw)
+    }
+}
+
+/// Edit or delete the events of changed contests, then create the events that are missing.
+pub async fn sync_events(pool: &Arc<Mutex<Pool>>, http: &serenity::Http, changes: &[ContestChange]) -> Result<()> {
+    let pool = pool.lock().await.clone();
+    let mut conn = pool.get_conn()?;
+    apply_changes(&mut conn, http, changes).await?;
+    create_events(&mut conn, http, None).await?;
+    Ok(())
+}
+
+/// Create an event for each upcoming contest that has none, in every server or only in `server_id`. Like the calendar
+/// feed of a server, only the contests that its `reminder` routes include, as in [`Routes::includes`], get an event.
+/// Returns how many events were created.
+pub async fn create_events(conn: &mut PooledConn, http: &serenity::Http, server_id: Option<u64>) -> Result<usize> {
+    // Discord lists ended events on its own, so the rows are only needed until the contest is over.
+    conn.query_drop(
+        "DELETE contest_events FROM contest_events
+        JOIN contests ON contests.contest_id = contest_events.contest_id
+        WHERE contests.end_at < UTC_TIMESTAMP()",
+    )?;
+    let upcoming: Vec<UpcomingContestTuple> = conn.exec(
+        "SELECT server_settings.server_id, contests.contest_id, contests.name, contests.start_at, contests.end_at, contests.rating_range_raw,
+            contests.contest_type, server_settings.language, notifications.contest_channel_id
+        FROM contests
+        JOIN server_settings ON server_settings.scheduled_events = 1
+        LEFT JOIN notifications ON notifications.server_id = server_settings.server_id
+        LEFT JOIN contest_events ON contest_events.server_id = server_settings.server_id AND contest_events.contest_id = contests.contest_id
+        WHERE contests.start_at > UTC_TIMESTAMP() AND contest_events.contest_id IS NULL
+            AND (:server_id IS NULL OR server_settings.server_id = :server_id)
+        ORDER BY contests.start_at",
+        params! {"server_id" => server_id},
+    )?;
+    let routes = Routes::load(conn, RouteEvent::Reminder)?;
+    let mut created = 0;
+    for (server_id, contest_id, name, start_at, end_at, rating_range_raw, contest_type, lang, channel_id) in upcoming {
+        let contest = ContestId::new(&contest_id);
+        let channel_id = channel_id.and_then(|channel_id| channel_id.parse::<u64>().ok());
+        if !routes.includes(server_id, channel_id, &contest, Some(contest_type)) {
+            continue;
+        }
+        let lang = lang.unwrap_or("ja".to_string());
+        let event = CreateScheduledEvent::new(ScheduledEventType::External, event_name(&name), start_at.and_utc())
+            .end_time(end_at.and_utc())
+            .location(contest.url())
+            .description(event_description(&contest, &rating_range_raw, &lang));
+        match GuildId::new(server_id).create_scheduled_event(http, event).await {
+            Ok(event) => {
+                conn.exec_drop(
+                    "INSERT INTO contest_events (server_id, contest_id, event_id) VALUES (:server_id, :contest_id, :event_id)",
+                    params! {"server_id" => server_id, "contest_id" => &contest_id, "event_id" => event.id.get()},
+                )?;
+                created += 1;
+            }
+            // Most likely the bot lacks the permission to create events. It is tried again on the next sync.
+            Err(err) => log::info!("could not create the event for {} in {}: {}", contest_id, server_id, err),
+        }
+    }
+    Ok(created)
+}
+
+async fn apply_changes(conn: &mut PooledConn, http: &serenity::Http, changes: &[ContestChange]) -> Result<()> {
+    let now = Utc::now();
+    for change in changes {
+        let events: Vec<(u64, u64, Option<String>)> = conn.exec(
+            "SELECT contest_events.server_id, contest_events.event_id, server_settings.language
+            FROM contest_events
+            LEFT JOIN server_settings ON server_settings.server_id = contest_events.server_id
+            WHERE contest_events.contest_id = :contest_id",
+            params! {"contest_id" => change.contest_id.as_str()},
+        )?;
+        for (server_id, event_id, lang) in events {
+            let guild_id = GuildId::new(server_id);
+            let event_id = ScheduledEventId::new(event_id);
+            match change.kind {
+                ContestChangeKind::Announced => {}
+                // Discord does not allow rescheduling an event that has started.
+                ContestChangeKind::Changed if change.start_time <= now => {}
+                ContestChangeKind::Changed => {
+                    let lang = lang.unwrap_or("ja".to_string());
+                    let event = EditScheduledEvent::new()
+                        .name(event_name(&change.name))
+                        .start_time(change.start_time)
+                        .end_time(change.start_time + chrono::Duration::minutes(change.duration as i64))
+                        .description(event_description(&change.contest_id, &change.rating_range_raw, &lang));
+                    if let Err(err) = guild_id.edit_scheduled_event(http, event_id, event).await {
+                        log::info!("could not edit the event for {} in {}: {}", change.contest_id, server_id, err);
+                    }
+                }
+                ContestChangeKind::Cancelled => {
+                    if let Err(err) = guild_id.delete_scheduled_event(http, event_id).await {
+                        log::info!("could not delete the event for {} in {}: {}", change.contest_id, server_id, err);
+                    }
+                }
+            }
+        }
+        if change.kind == ContestChangeKind::Cancelled {
+            conn.exec_drop(
+                "DELETE FROM contest_events WHERE contest_id=:contest_id",
+                params! {"contest_id" => change.contest_id.as_str()},
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Delete the events the bot created in a server, for when the server turns the events off.
+pub async fn delete_events(conn: &mut PooledConn, http: &serenity::Http, server_id: u64) -> Result<()> {
+    let events: Vec<u64> = conn.exec(
+        "SELECT event_id FROM contest_events WHERE server_id=:server_id",
+        params! {"server_id" => server_id},
+    )?;
+    for event_id in events {
+        if let Err(err) = GuildId::new(server_id).delete_scheduled_event(http, ScheduledEventId::new(event_id)).await {
+            log::info!("could not delete the event {} in {}: {}", event_id, server_id, err);
+        }
+    }
+    conn.exec_drop("DELETE FROM contest_events WHERE server_id=:server_id", params! {"server_id" => server_id})?;
+    Ok(())
+}
@@ -1,4 +1,7 @@
+pub mod atcoder_identity;
 pub mod atcoder_ratings;
+pub mod contest_id;
+pub mod contest_info;
 pub mod contest_type;
 pub mod contests;
 pub mod diff;
@@ -8,3 +11,4 @@ pub mod get_user_list;
 pub mod login;
 pub mod notify;
 pub mod ranking_types;
+pub mod user_profile;
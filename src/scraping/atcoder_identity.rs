@@ -0,0 +1,89 @@
+use mysql::prelude::*;
+use mysql::*;
+use regex::Regex;
+use std::sync::OnceLock;
+
+use super::user_profile::get_user_profile;
+
+static USERNAME_REGEX: OnceLock<Regex> = OnceLock::new();
+
+/// An AtCoder account as stored in `atcoder_identities`.
+///
+/// `user_key` is what other tables (`users`, `submissions`, ...) store, and `screen_name` is the
+/// name with the case AtCoder uses, which is what standings and results JSON contain.
+#[derive(Debug, Clone)]
+pub struct AtCoderIdentity {
+    pub user_key: String,
+    pub screen_name: String,
+}
+
+pub fn user_key(screen_name: &str) -> String {
+    screen_name.to_lowercase()
+}
+
+pub fn is_valid_username(name: &str) -> bool {
+    USERNAME_REGEX.get_or_init(|| Regex::new(r"^[A-Za-z0-9_]{3,16}$").unwrap()).is_match(name)
+}
+
+pub fn save_identity(conn: &mut PooledConn, screen_name: &str) -> Result<AtCoderIdentity> {
+    let identity = AtCoderIdentity {
+        user_key: user_key(screen_name),
+        screen_name: screen_name.to_string(),
+    };
+    conn.exec_drop(
+        "INSERT INTO atcoder_identities (user_key, screen_name) VALUES (:user_key, :screen_name) ON DUPLICATE KEY UPDATE screen_name=VALUES(screen_name)",
+        params! {"user_key" => &identity.user_key, "screen_name" => &identity.screen_name},
+    )?;
+    Ok(identity)
+}
+
+/// Store the case of `screen_names` as seen in standings or results, which always use the case AtCoder does. Names
+/// typed by members are only a guess when the profile did not tell.
+pub fn update_screen_names<Q: Queryable>(conn: &mut Q, screen_names: &[&str]) -> Result<()> {
+    conn.exec_batch(
+        "INSERT INTO atcoder_identities (user_key, screen_name) VALUES (:user_key, :screen_name)
+        ON DUPLICATE KEY UPDATE screen_name=VALUES(screen_name)",
+        screen_names.iter().map(|screen_name| params! {"user_key" => user_key(screen_name), "screen_name" => screen_name}),
+    )
+}
+
+/// Check that `name` is an existing AtCoder user and return its canonical screen name.
+///
+/// Known identities are answered from the database. Otherwise the profile page is fetched, and when
+/// AtCoder cannot be reached the local rating data is used instead.
+pub async fn resolve_atcoder_user(conn: &mut PooledConn, name: &str) -> Result<Option<AtCoderIdentity>> {
+    let name = name.trim();
+    if !is_valid_username(name) {
+        return Ok(None);
+    }
+
+    let known: Vec<String> = conn.exec(
+        "SELECT screen_name FROM atcoder_identities WHERE user_key=:user_key",
+        params! {"user_key" => user_key(name)},
+    )?;
+    if let Some(screen_name) = known.first() {
+        return Ok(Some(AtCoderIdentity {
+            user_key: user_key(screen_name),
+            screen_name: screen_name.clone(),
+        }));
+    }
+
+    let screen_name = match get_user_profile(name).await {
+        Ok(Some(profile)) if !profile.screen_name.is_empty() => Some(profile.screen_name),
+        Ok(Some(_)) => Some(name.to_string()),
+        Ok(None) => None,
+        Err(err) => {
+            log::warn!("could not reach AtCoder, checking local rating data for {}: {}", name, err);
+            let local: Vec<String> = conn.exec(
+                "SELECT user_name FROM user_ratings WHERE user_name=:user_name LIMIT 1",
+                params! {"user_name" => name},
+            )?;
+            local.into_iter().next()
+        }
+    };
+
+    match screen_name {
+        Some(screen_name) => Ok(Some(save_identity(conn, &screen_name)?)),
+        None => Ok(None),
+    }
+}
@@ -1,25 +1,30 @@
-use chrono::DateTime;
 use fontdb::{Database, Query, Source};
 use fontdue::layout::{CoordinateSystem, Layout, TextStyle};
 use fontdue::Font;
 use mysql::prelude::*;
 use mysql::*;
-use poise::serenity_prelude::{CacheHttp, ChannelId, Context, CreateAttachment, CreateMessage, UserId};
+use poise::serenity_prelude::{CacheHttp, Context, CreateAttachment, CreateMessage, UserId};
 use reqwest::blocking::Client;
 use reqwest::cookie::Jar;
 use serde::{Deserialize, Serialize};
 use serde_json;
-use std::cmp::Ordering;
 use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::Mutex;
 use tokio::time::sleep;
 
+use crate::mentions::{self, NotificationKind};
+use crate::outbox::{self, OutboxKind, OutboxMessage};
+use crate::routing::{RouteEvent, Routes};
+use crate::rsvp;
 use crate::utils::svg::create_table::{self, Align, RatingType, Row, TableRowsRating, TableRowsText, TextConfig, Title};
 use crate::utils::svg::create_user_rating::Theme;
 use crate::utils::svg_to_png::svg_to_png;
 
+use super::atcoder_identity::{update_screen_names, user_key};
+use super::contest_id::ContestId;
+use super::contest_type::ContestType;
 use super::get_user_list;
 use super::ranking_types::StandingsJson;
 
@@ -89,28 +94,12 @@ fn ordinal_suffix(n: i32) -> String {
 pub async fn get_ratings(cookie_store: &Arc<Jar>, conn_raw: &Arc<Mutex<Pool>>, ctx: &Context, get_all: bool) -> Result<()> {
     let pool = conn_raw.lock().await;
     let mut conn = pool.get_conn().unwrap();
-    let contests: Vec<(String, i8, i32, bool, String, i32)> = conn
-        .query(r"select contest_id,contest_type,rating_range_end,get_user_ratings_flag,start_time,duration from contests where rating_range_end>=0 ORDER BY start_time")
+    let contests: Vec<(String, i8, i32, bool)> = conn
+        .query(
+            r"select contest_id,contest_type,rating_range_end,get_user_ratings_flag from contests
+            where rating_range_end>=0 AND end_at <= UTC_TIMESTAMP() ORDER BY start_at",
+        )
         .unwrap();
-    let mut contests: Vec<&(String, i8, i32, bool, String, i32)> = contests
-        .iter()
-        .filter(|contest| {
-            let start_time = chrono::DateTime::parse_from_str(&contest.4, "%Y-%m-%d %H:%M:%S%z").unwrap();
-            let offset = chrono::Duration::minutes(contest.5 as i64);
-            let end_time = start_time + offset;
-            chrono::Local::now() >= end_time
-        })
-        .collect();
-
-    contests.sort_by(|a, b| {
-        let duration: chrono::Duration =
-            DateTime::parse_from_str(&a.4, "%Y-%m-%d %H:%M:%S%z").unwrap() - DateTime::parse_from_str(&b.4, "%Y-%m-%d %H:%M:%S%z").unwrap();
-        if duration.num_milliseconds() > 0 {
-            Ordering::Greater
-        } else {
-            Ordering::Less
-        }
-    });
 
     let mut user_history: Vec<UserRatings> = vec![];
 
@@ -120,30 +109,39 @@ pub async fn get_ratings(cookie_store: &Arc<Jar>, conn_raw: &Arc<Mutex<Pool>>, c
     let client = Client::builder().cookie_store(true).cookie_provider(Arc::clone(cookie_store)).build().unwrap();
 
     let users = conn
-        .query_map("SELECT atcoder_username, server_id from users", |(atcoder_username, server_id)| User {
-            atcoder_username,
-            server_id,
-        })
+        .query_map(
+            "SELECT COALESCE(atcoder_identities.screen_name, users.atcoder_username), users.server_id FROM users
+            LEFT JOIN atcoder_identities ON atcoder_identities.user_key = users.atcoder_username",
+            |(atcoder_username, server_id)| User { atcoder_username, server_id },
+        )
         .unwrap();
 
+    // Keyed by `user_key`, as the stored case of a name is not always the one in the results.
     let mut contest_users_map: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
     let mut users_set = BTreeSet::new();
     for user in users {
         let user = user.clone();
         let mut vec = contest_users_map.remove(&user.server_id.to_string()).unwrap_or_default();
-        vec.insert(user.atcoder_username.to_lowercase());
+        vec.insert(user_key(&user.atcoder_username));
         contest_users_map.insert(user.server_id.to_string(), vec);
-        users_set.insert(user.atcoder_username.to_lowercase());
+        users_set.insert(user_key(&user.atcoder_username));
     }
 
     let mut rating_data: BTreeMap<String, ResultData> = BTreeMap::new();
+    let mut seen_users: Vec<String> = vec![];
 
-    let channels: Vec<(String, String)> = conn.query("SELECT contest_channel_id,server_id FROM notifications WHERE contest_channel_id is not null").unwrap();
+    let channels: Vec<(Option<String>, String)> = conn
+        .query(
+            "SELECT contest_channel_id,server_id FROM notifications
+            WHERE contest_channel_id is not null OR server_id IN (SELECT server_id FROM notification_routes WHERE event='results')",
+        )
+        .unwrap();
+    let routes = Routes::load(&mut conn, RouteEvent::Results).unwrap();
 
     let mut contests_list: Vec<String> = vec![];
     let mut is_first = true;
 
-    for (contest_id, contest_type, rating_range_end, is_already_get, _, _) in contests {
+    for (contest_id, contest_type, rating_range_end, is_already_get) in &contests {
         if !get_all && *is_already_get {
             continue;
         }
@@ -152,7 +150,7 @@ pub async fn get_ratings(cookie_store: &Arc<Jar>, conn_raw: &Arc<Mutex<Pool>>, c
         }
         sleep(Duration::from_millis(500)).await;
         log::info!("get all user rating: {}", contest_id);
-        let url = format!("https://{}/results/json", contest_id);
+        let url = ContestId::new(contest_id).results_json_url();
         let data: Vec<ResultData>;
         loop {
             let response = client.get(&url).send().unwrap().text().unwrap_or_default();
@@ -208,7 +206,7 @@ pub async fn get_ratings(cookie_store: &Arc<Jar>, conn_raw: &Arc<Mutex<Pool>>, c
                             }
                         }
                         for i in &rating_history {
-                            if i.ContestScreenName == contest_id.clone() {
+                            if ContestId::new(&i.ContestScreenName).as_str() == contest_id {
                                 performance = i.InnerPerformance
                             }
                         }
@@ -223,20 +221,22 @@ pub async fn get_ratings(cookie_store: &Arc<Jar>, conn_raw: &Arc<Mutex<Pool>>, c
                             user_name: i.UserScreenName.clone(),
                         })
                     }
-                    if users_set.contains(&i.UserScreenName.to_lowercase()) {
+                    if users_set.contains(&user_key(&i.UserScreenName)) {
                         i.Performance = performance;
-                        rating_data.insert(i.UserScreenName.to_lowercase().clone(), i.clone());
+                        rating_data.insert(user_key(&i.UserScreenName), i.clone());
+                        seen_users.push(i.UserScreenName.clone());
                     }
                 }
             }
         }
     }
+    update_screen_names(&mut conn, &seen_users.iter().map(String::as_str).collect::<Vec<_>>()).unwrap();
     if !contests_list.is_empty() && !get_all {
         let attachment = CreateAttachment::bytes(include_bytes!("../../static/img/cyan_rating_its_D_problem.png"), "img.png");
         let contest = &contests_list[0];
-        let abc_regex = regex::Regex::new(r"^abc\d{3}\.contest\.atcoder\.jp$").unwrap();
-        if abc_regex.is_match(contest) {
-            let url = format!("https://{}/standings/json", contest);
+        let contest = ContestId::new(contest);
+        if contest.is_abc() {
+            let url = contest.standings_json_url();
             let json = client.get(url).send().unwrap().text().unwrap_or_default();
             let data: StandingsJson = serde_json::from_str(&json).unwrap_or_default();
             let users: Vec<(u64, String)> = conn.query("SELECT discord_id,atcoder_username from users WHERE discord_id is not null").unwrap();
@@ -257,12 +257,12 @@ pub async fn get_ratings(cookie_store: &Arc<Jar>, conn_raw: &Arc<Mutex<Pool>>, c
                     && (!result.TaskResults.contains_key(d_problem) || result.TaskResults.get(d_problem).unwrap().Score == 0)
                     && result.IsRated
                 {
-                    un_solve_d_problem_cyan.insert(result.UserScreenName.to_lowercase());
+                    un_solve_d_problem_cyan.insert(user_key(&result.UserScreenName));
                 }
             }
 
             for (discord_id, atcoder_username) in discord_id_to_atcoder {
-                if un_solve_d_problem_cyan.contains(&atcoder_username.to_lowercase()) {
+                if un_solve_d_problem_cyan.contains(&atcoder_username) {
                     let user = UserId::new(discord_id);
                     user.dm(ctx.http(), CreateMessage::new().add_file(attachment.clone())).await.unwrap_or_default();
                 }
@@ -288,7 +288,16 @@ pub async fn get_ratings(cookie_store: &Arc<Jar>, conn_raw: &Arc<Mutex<Pool>>, c
         let font = Font::from_bytes(font_data, fontdue::FontSettings::default()).expect("Error loading font");
         let scale = 70.0;
 
+        let contest_type = contests.iter().find(|(contest_id, ..)| ContestId::new(contest_id) == contest).map(|(_, contest_type, ..)| *contest_type);
         for i in channels {
+            let Ok(server_id) = i.1.parse::<u64>() else {
+                continue;
+            };
+            let default_channel = i.0.as_ref().and_then(|channel_id| channel_id.parse::<u64>().ok());
+            let channel_ids = routes.channels(server_id, default_channel, &contest, contest_type);
+            if channel_ids.is_empty() {
+                continue;
+            }
             let users = contest_users_map.get(&i.1).unwrap();
             let mut user_data = vec![];
             for user in users {
@@ -319,8 +328,21 @@ pub async fn get_ratings(cookie_store: &Arc<Jar>, conn_raw: &Arc<Mutex<Pool>>, c
             let mut new_rating = vec![];
             let mut diff = vec![];
             let mut performance = vec![];
+            let mut rsvp_marks = vec![];
             let mut user_width = 0;
+            // Linked accounts of the members who answered "I'm in" on the reminder. Those who took part are removed
+            // below, which leaves the no-shows.
+            let mut attendees: BTreeMap<String, String> = rsvp::attendee_accounts(&mut conn, server_id, &contest)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|screen_name| (user_key(&screen_name), screen_name))
+                .collect();
             for (i, result_data) in user_data.iter().enumerate() {
+                let attended = attendees.remove(&user_key(&result_data.UserScreenName)).is_some();
+                rsvp_marks.push(TextConfig {
+                    value: if attended { "✓".to_string() } else { "".to_string() },
+                    color: "Aquamarine".to_string(),
+                });
                 all_rank_vec.push(TextConfig {
                     value: ordinal_suffix(result_data.Place),
                     color: match result_data.Place {
@@ -389,7 +411,46 @@ pub async fn get_ratings(cookie_store: &Arc<Jar>, conn_raw: &Arc<Mutex<Pool>>, c
                 let width = layout.glyphs().last().map_or(0.0, |g| g.x + g.width as f32);
                 user_width = user_width.max(width as i32);
             }
-            let rows = vec![
+            let table_contest_type = if contest_type == Some(1) {
+                ContestType::Heuristic
+            } else {
+                ContestType::Algorithm
+            };
+            let blank = || TextConfig {
+                value: "-".to_string(),
+                color: "white".to_string(),
+            };
+            let blank_rating = || {
+                RatingType::Custom(create_table::RatingCustom {
+                    has_bronze: false,
+                    rating: 0,
+                    title: "-".to_string(),
+                    color_theme: Theme::Dark,
+                })
+            };
+            for screen_name in attendees.values() {
+                all_rank_vec.push(blank());
+                server_rank_vec.push(blank());
+                user_name.push(RatingType::UserRating(create_table::UserRating {
+                    color_theme: Theme::Dark,
+                    username: screen_name.clone(),
+                    contest_type: table_contest_type.clone(),
+                }));
+                performance.push(blank_rating());
+                old_rating.push(blank_rating());
+                new_rating.push(blank_rating());
+                diff.push(blank());
+                rsvp_marks.push(TextConfig {
+                    value: "No show".to_string(),
+                    color: "red".to_string(),
+                });
+
+                let mut layout = Layout::new(CoordinateSystem::PositiveYDown);
+                layout.append(std::slice::from_ref(&font), &TextStyle::new(screen_name, scale, 0));
+                let width = layout.glyphs().last().map_or(0.0, |g| g.x + g.width as f32);
+                user_width = user_width.max(width as i32);
+            }
+            let mut rows = vec![
                 Row::Text(TableRowsText {
                     title: Title::Text("All".to_string()),
                     width: 300,
@@ -429,25 +490,38 @@ pub async fn get_ratings(cookie_store: &Arc<Jar>, conn_raw: &Arc<Mutex<Pool>>, c
                     data: diff,
                 }),
             ];
+            if rsvp_marks.iter().any(|mark| !mark.value.is_empty()) {
+                rows.push(Row::Text(TableRowsText {
+                    title: Title::Text("RSVP".to_string()),
+                    width: 300,
+                    align: Align::Middle,
+                    data: rsvp_marks,
+                }));
+            }
 
-            if !user_data.is_empty() {
-                let svg = create_table::create_table(
-                    &Arc::new(Mutex::new(pool.clone())),
-                    format!("レーティング更新: {}", user_data[0].ContestNameEn),
-                    rows,
-                )
-                .await;
-                let response = {
-                    let mut message = CreateMessage::new();
-                    message = message.content("レーティングが更新されました").add_file(CreateAttachment::bytes(
-                        svg_to_png(svg.svg.as_str(), svg.width as u32, svg.height as u32, 1.0, 1.0),
-                        "ranking.png",
-                    ));
-                    message
+            // Shown even when nobody took part, as long as someone who answered "I'm in" skipped the contest.
+            if !user_data.is_empty() || !attendees.is_empty() {
+                let contest_name = match user_data.first() {
+                    Some(data) => data.ContestNameEn.clone(),
+                    None => {
+                        let name: Option<String> = conn
+                            .exec_first(
+                                "SELECT name FROM contests WHERE contest_id=:contest_id",
+                                params! {"contest_id" => contest.as_str()},
+                            )
+                            .unwrap();
+                        name.unwrap_or(contest.to_string())
+                    }
                 };
-
-                let channel = ChannelId::new(i.0.parse::<u64>().unwrap());
-                channel.send_message(ctx.http(), response).await.unwrap_or_default();
+                let svg = create_table::create_table(&Arc::new(Mutex::new(pool.clone())), format!("レーティング更新: {}", contest_name), rows).await;
+                let mention = mentions::mention_text(&mut conn, server_id, NotificationKind::Results).unwrap();
+                let png = svg_to_png(svg.svg.as_str(), svg.width as u32, svg.height as u32, 1.0, 1.0);
+                for channel_id in channel_ids {
+                    let message = OutboxMessage::new(OutboxKind::RatingUpdate, Some(server_id), channel_id)
+                        .content(mentions::with_mention(mention.clone(), "レーティングが更新されました"))
+                        .attachment("ranking.png", png.clone());
+                    outbox::enqueue(&mut conn, message).unwrap();
+                }
             }
         }
     }
@@ -0,0 +1,94 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::OnceLock;
+
+static SLUG_REGEX: OnceLock<Regex> = OnceLock::new();
+static ABC_REGEX: OnceLock<Regex> = OnceLock::new();
+
+/// The legacy host form that contests used to be stored and linked with.
+const LEGACY_HOST_SUFFIX: &str = ".contest.atcoder.jp";
+
+/// An AtCoder contest, identified by the slug in `https://atcoder.jp/contests/{slug}` such as `abc123`.
+///
+/// This is what `contests.contest_id`, `user_ratings.contest` and `messages.contest_id` store, and what kenkoooo
+/// calls `contest_id`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ContestId(String);
+
+impl ContestId {
+    /// Normalize an id from a trusted source, such as the database or an AtCoder API, that may still use the
+    /// legacy `abc123.contest.atcoder.jp` form.
+    pub fn new(id: &str) -> ContestId {
+        ContestId(id.trim().trim_end_matches(LEGACY_HOST_SUFFIX).to_lowercase())
+    }
+
+    /// Read a contest from user input: the slug, the legacy host form, or a contest URL or path.
+    pub fn parse(value: &str) -> Option<ContestId> {
+        let value = value.trim();
+        let value = value.strip_prefix("https://").or_else(|| value.strip_prefix("http://")).unwrap_or(value);
+        let value = value.strip_prefix("atcoder.jp").unwrap_or(value);
+        let value = value.strip_prefix("/contests/").unwrap_or(value);
+        let value = value.split(['/', '?', '#']).next().unwrap_or_default();
+        let id = ContestId::new(value);
+        SLUG_REGEX.get_or_init(|| Regex::new(r"^[a-z0-9][a-z0-9_-]*$").unwrap()).is_match(&id.0).then_some(id)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether this is a numbered AtCoder Beginner Contest.
+    pub fn is_abc(&self) -> bool {
+        ABC_REGEX.get_or_init(|| Regex::new(r"^abc\d{3}$").unwrap()).is_match(&self.0)
+    }
+
+    pub fn url(&self) -> String {
+        format!("https://atcoder.jp/contests/{}", self.0)
+    }
+
+    pub fn tasks_url(&self) -> String {
+        format!("{}/tasks", self.url())
+    }
+
+    pub fn task_url(&self, task_id: &str) -> String {
+        format!("{}/tasks/{}", self.url(), task_id)
+    }
+
+    pub fn task_editorial_url(&self, task_id: &str) -> String {
+        format!("{}/tasks/{}/editorial", self.url(), task_id)
+    }
+
+    pub fn editorial_url(&self) -> String {
+        format!("{}/editorial", self.url())
+    }
+
+    pub fn standings_url(&self) -> String {
+        format!("{}/standings", self.url())
+    }
+
+    pub fn standings_json_url(&self) -> String {
+        format!("{}/standings/json", self.url())
+    }
+
+    pub fn results_url(&self) -> String {
+        format!("{}/results", self.url())
+    }
+
+    pub fn results_json_url(&self) -> String {
+        format!("{}/results/json", self.url())
+    }
+}
+
+impl fmt::Display for ContestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<ContestId> for mysql::Value {
+    fn from(id: ContestId) -> Self {
+        mysql::Value::from(id.0)
+    }
+}
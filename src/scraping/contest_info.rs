@@ -0,0 +1,183 @@
+use reqwest::Client;
+use scraper::{ElementRef, Selector};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use super::contest_id::ContestId;
+use super::diff;
+use super::ranking_types::StandingsJson;
+
+static SELECTOR_STATEMENT: OnceLock<Selector> = OnceLock::new();
+static SELECTOR_STATEMENT_EN: OnceLock<Selector> = OnceLock::new();
+static SELECTOR_LIST_ITEM: OnceLock<Selector> = OnceLock::new();
+static SELECTOR_USERNAME: OnceLock<Selector> = OnceLock::new();
+static SELECTOR_TABLE_ROW: OnceLock<Selector> = OnceLock::new();
+static SELECTOR_TABLE_CELL: OnceLock<Selector> = OnceLock::new();
+static SELECTOR_TASK_ROWS: OnceLock<Selector> = OnceLock::new();
+static SELECTOR_LINK: OnceLock<Selector> = OnceLock::new();
+
+/// What the contest top page says about a contest.
+#[derive(Debug, Default, Clone)]
+pub struct ContestOverview {
+    pub writers: Vec<String>,
+    /// Points keyed by task assignment (`A`, `B`, ...).
+    pub points: BTreeMap<String, i32>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ContestTask {
+    /// `A`, `B`, ..., `Ex`
+    pub assignment: String,
+    pub name: String,
+    /// Task screen name such as `abc300_a`, which is also the problem id used by kenkoooo.
+    pub task_id: String,
+}
+
+#[derive(Deserialize)]
+struct ProblemModel {
+    difficulty: Option<f64>,
+}
+
+/// Difficulties below 400 are shown compressed, the same way AtCoder Problems does.
+pub fn clip_difficulty(difficulty: f64) -> i32 {
+    if difficulty <= 400.0 {
+        (400.0 / f64::exp((400.0 - difficulty) / 400.0)) as i32
+    } else {
+        difficulty as i32
+    }
+}
+
+fn text(element: ElementRef) -> String {
+    element.text().collect::<String>().trim().to_string()
+}
+
+pub fn parse_contest_overview(html: &str) -> ContestOverview {
+    let document = scraper::Html::parse_document(html);
+    let statement_selector = SELECTOR_STATEMENT.get_or_init(|| Selector::parse("#contest-statement").unwrap());
+    let statement_en_selector = SELECTOR_STATEMENT_EN.get_or_init(|| Selector::parse("#contest-statement span.lang-en").unwrap());
+    let list_item_selector = SELECTOR_LIST_ITEM.get_or_init(|| Selector::parse("li").unwrap());
+    let username_selector = SELECTOR_USERNAME.get_or_init(|| Selector::parse("a.username").unwrap());
+    let row_selector = SELECTOR_TABLE_ROW.get_or_init(|| Selector::parse("table tr").unwrap());
+    let cell_selector = SELECTOR_TABLE_CELL.get_or_init(|| Selector::parse("td").unwrap());
+
+    let mut overview = ContestOverview::default();
+    // Prefer the English statement so that the Japanese copy does not count twice.
+    let Some(statement) = document.select(statement_en_selector).next().or_else(|| document.select(statement_selector).next()) else {
+        return overview;
+    };
+    for item in statement.select(list_item_selector) {
+        let label = text(item);
+        if !(label.starts_with("Writer") || label.starts_with("作問") || label.starts_with("Problem Writer")) {
+            continue;
+        }
+        for user in item.select(username_selector) {
+            let user = text(user);
+            if !user.is_empty() && !overview.writers.contains(&user) {
+                overview.writers.push(user);
+            }
+        }
+    }
+    for row in statement.select(row_selector) {
+        let cells: Vec<String> = row.select(cell_selector).map(text).collect();
+        let [assignment, point] = cells.as_slice() else {
+            continue;
+        };
+        if let Ok(point) = point.parse::<i32>() {
+            overview.points.entry(assignment.clone()).or_insert(point);
+        }
+    }
+    overview
+}
+
+pub fn parse_task_list(html: &str) -> Vec<ContestTask> {
+    let document = scraper::Html::parse_document(html);
+    let rows_selector = SELECTOR_TASK_ROWS.get_or_init(|| Selector::parse("#main-container table tbody tr").unwrap());
+    let cell_selector = SELECTOR_TABLE_CELL.get_or_init(|| Selector::parse("td").unwrap());
+    let link_selector = SELECTOR_LINK.get_or_init(|| Selector::parse("a").unwrap());
+
+    let mut tasks = vec![];
+    for row in document.select(rows_selector) {
+        let cells: Vec<ElementRef> = row.select(cell_selector).collect();
+        let (Some(assignment), Some(name)) = (cells.first(), cells.get(1)) else {
+            continue;
+        };
+        let Some(link) = name.select(link_selector).next() else {
+            continue;
+        };
+        let task_id = link.attr("href").and_then(|href| href.rsplit('/').next()).unwrap_or_default().to_string();
+        tasks.push(ContestTask {
+            assignment: text(*assignment),
+            name: text(link),
+            task_id,
+        });
+    }
+    tasks
+}
+
+pub async fn get_contest_overview(client: &Client, contest_id: &ContestId) -> Result<ContestOverview, reqwest::Error> {
+    let html = client.get(format!("{}?lang=en", contest_id.url())).send().await?.error_for_status()?.text().await?;
+    Ok(parse_contest_overview(&html))
+}
+
+/// The tasks page is only visible once the contest has started.
+pub async fn get_task_list(client: &Client, contest_id: &ContestId) -> Result<Vec<ContestTask>, reqwest::Error> {
+    let html = client.get(format!("{}?lang=en", contest_id.tasks_url())).send().await?.error_for_status()?.text().await?;
+    Ok(parse_task_list(&html))
+}
+
+/// Estimated difficulties from AtCoder Problems, keyed by problem id.
+pub async fn get_problem_difficulties(client: &Client) -> Result<BTreeMap<String, i32>, reqwest::Error> {
+    let text = client.get("https://kenkoooo.com/atcoder/resources/problem-models.json").send().await?.error_for_status()?.text().await?;
+    let models: BTreeMap<String, ProblemModel> = serde_json::from_str(&text).unwrap_or_default();
+    Ok(models.into_iter().filter_map(|(problem_id, model)| model.difficulty.map(|difficulty| (problem_id, clip_difficulty(difficulty)))).collect())
+}
+
+/// How long a download of the problem models, several megabytes, is reused.
+const PROBLEM_MODELS_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Difficulties keyed by problem id and when they were downloaded.
+type CachedDifficulties = (Instant, Arc<BTreeMap<String, i32>>);
+
+/// The last [`get_problem_difficulties`], shared by every `/atcoder contest info`.
+#[derive(Default)]
+pub struct ProblemDifficultyCache(Mutex<Option<CachedDifficulties>>);
+
+impl ProblemDifficultyCache {
+    /// Download the difficulties again once the copy is older than [`PROBLEM_MODELS_TTL`].
+    /// A failed download keeps the previous copy.
+    pub async fn get(&self, client: &Client) -> Arc<BTreeMap<String, i32>> {
+        let mut cached = self.0.lock().await;
+        if let Some((loaded_at, difficulties)) = cached.as_ref() {
+            if loaded_at.elapsed() < PROBLEM_MODELS_TTL {
+                return Arc::clone(difficulties);
+            }
+        }
+        match get_problem_difficulties(client).await {
+            Ok(difficulties) if !difficulties.is_empty() => {
+                let difficulties = Arc::new(difficulties);
+                *cached = Some((Instant::now(), Arc::clone(&difficulties)));
+                difficulties
+            }
+            result => {
+                if let Err(err) = result {
+                    log::warn!("could not get the problem models: {}", err);
+                }
+                cached.as_ref().map(|(_, difficulties)| Arc::clone(difficulties)).unwrap_or_default()
+            }
+        }
+    }
+}
+
+/// Estimate difficulties from the final standings with [`diff::get_diff`], keyed by task assignment.
+/// `client` has to be logged in to AtCoder.
+pub async fn get_standings_difficulties(client: &Client, contest_id: &ContestId, is_rated: bool) -> Result<BTreeMap<String, i32>, reqwest::Error> {
+    let text = client.get(contest_id.standings_json_url()).send().await?.error_for_status()?.text().await?;
+    let standings: StandingsJson = serde_json::from_str(&text).unwrap_or_default();
+    if standings.StandingsData.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+    Ok(diff::get_diff(standings, is_rated).into_iter().map(|(assignment, model)| (assignment, clip_difficulty(model.difficulty))).collect())
+}
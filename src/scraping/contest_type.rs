@@ -1,3 +1,7 @@
+use chrono::{DateTime, Utc};
+
+use super::contest_id::ContestId;
+
 #[derive(Default, Debug, Clone)]
 pub enum ContestType {
     #[default]
@@ -17,12 +21,13 @@ pub enum ContestRatingType {
 #[derive(Default, Debug)]
 pub struct Contest {
     pub contest_name: String,
-    pub start_time: String,
+    /// `None` for contests without a schedule.
+    pub start_time: Option<DateTime<Utc>>,
     pub contest_duration: i32,
     pub contest_type: ContestType,
     pub url: String,
     pub contest_rating_type: ContestRatingType,
     pub rating_ragnge: (i32, i32),
     pub rating_range_raw: String,
-    pub contest_id: String,
+    pub contest_id: ContestId,
 }
@@ -1,14 +1,24 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
 use mysql::prelude::*;
 use mysql::*;
-use scraper::Selector;
-use std::{collections::HashSet, sync::Arc, time::Duration};
+use scraper::{ElementRef, Selector};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 use tokio::{sync::Mutex, time::sleep};
 use url::Url;
 
+use super::contest_id::ContestId;
 use super::contest_type::{Contest, ContestRatingType, ContestType};
 use std::sync::OnceLock;
 
-type ContestDataTuple = (i32, String, String, String, i32, String, i8, i8, i32, i32, String, i8);
+type ContestDataTuple = (i32, String, String, NaiveDateTime, i32, String, i8, i8, i32, i32, String, i8);
 
 #[derive(Debug)]
 struct GetContestPageResult {
@@ -16,283 +26,596 @@ struct GetContestPageResult {
     contests: Vec<Contest>,
 }
 
-static SELECTOR_CONTESTS: OnceLock<Selector> = OnceLock::new();
-static SELECTOR_CONTEST_TYPE: OnceLock<Selector> = OnceLock::new();
-static SELECTOR_CONTEST_RATING_TYPE: OnceLock<Selector> = OnceLock::new();
-static SELECTOR_CONTEST_NAME: OnceLock<Selector> = OnceLock::new();
-static SELECTOR_CONTEST_DURATION: OnceLock<Selector> = OnceLock::new();
-static SELECTOR_CONTEST_RATING_RANGE: OnceLock<Selector> = OnceLock::new();
-static SELECTOR_CONTEST_PAGE_PAGINATION: OnceLock<Selector> = OnceLock::new();
-static SELECTOR2_CONTESTS: OnceLock<Selector> = OnceLock::new();
-static SELECTOR2_CONTEST_TYPE: OnceLock<Selector> = OnceLock::new();
-static SELECTOR2_CONTEST_RATING_TYPE: OnceLock<Selector> = OnceLock::new();
-static SELECTOR2_CONTEST_NAME: OnceLock<Selector> = OnceLock::new();
-static SELECTOR2_CONTEST_DURATION: OnceLock<Selector> = OnceLock::new();
-static SELECTOR2_CONTEST_RATING_RANGE: OnceLock<Selector> = OnceLock::new();
-
-async fn get_contest_page() -> GetContestPageResult {
-    log::info!("Get Contests Page");
-    let mut contests: Vec<Contest> = vec![];
-
-    let contest_page_html = reqwest::get("https://atcoder.jp/contests/?lang=ja").await.unwrap().text().await.unwrap();
-
-    let document = scraper::Html::parse_document(&contest_page_html);
-
-    let contests_time_selector = SELECTOR2_CONTESTS.get_or_init(|| Selector::parse("div#contest-table-upcoming tbody tr td:nth-child(1) time").unwrap());
-    let contests_time = document.select(contests_time_selector);
-    for element in contests_time {
-        contests.push(Contest {
-            start_time: element.inner_html(),
-            ..Default::default()
-        });
-    }
+/// The contest tables on `/contests` and `/contests/archive`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContestTable {
+    /// Contests running right now.
+    Action,
+    Upcoming,
+    /// Contests without a schedule, such as practice contests. They have no start time or duration.
+    Permanent,
+    Archive,
+}
 
-    let contests_type_selector =
-        SELECTOR2_CONTEST_TYPE.get_or_init(|| Selector::parse("div#contest-table-upcoming tbody tr td:nth-child(2) span:nth-child(1)").unwrap());
-    let contest_type = document.select(contests_type_selector);
-    for (i, element) in contest_type.enumerate() {
-        let is_algo = element.inner_html() == "Ⓐ";
-        if is_algo {
-            contests[i].contest_type = ContestType::Algorithm;
-        } else {
-            contests[i].contest_type = ContestType::Heuristic;
+impl ContestTable {
+    fn index(self) -> usize {
+        match self {
+            ContestTable::Action => 0,
+            ContestTable::Upcoming => 1,
+            ContestTable::Permanent => 2,
+            ContestTable::Archive => 3,
         }
     }
+}
+
+#[derive(Debug)]
+pub enum ContestPageError {
+    Request(reqwest::Error),
+    MissingTable(ContestTable),
+    /// `row` counts from 0 within the table.
+    Row {
+        table: ContestTable,
+        row: usize,
+        reason: String,
+    },
+}
 
-    let contests_rating_type_selector =
-        SELECTOR2_CONTEST_RATING_TYPE.get_or_init(|| Selector::parse("div#contest-table-upcoming tbody tr td:nth-child(2) span:nth-child(2)").unwrap());
-    let contest_rating_type = document.select(contests_rating_type_selector);
-    for (i, element) in contest_rating_type.enumerate() {
-        let contest_rating_type = element.attr("class").unwrap();
-        if contest_rating_type == "user-blue" {
-            contests[i].contest_rating_type = ContestRatingType::ABC;
-        } else if contest_rating_type == "user-orange" {
-            contests[i].contest_rating_type = ContestRatingType::ARC;
-        } else if contest_rating_type == "user-red" {
-            contests[i].contest_rating_type = ContestRatingType::AGC;
-        } else {
-            contests[i].contest_rating_type = ContestRatingType::None;
+impl fmt::Display for ContestPageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContestPageError::Request(err) => write!(f, "could not fetch the contest page: {}", err),
+            ContestPageError::MissingTable(table) => write!(f, "the {:?} contest table was not found", table),
+            ContestPageError::Row { table, row, reason } => write!(f, "row {} of the {:?} contest table: {}", row, table, reason),
         }
     }
+}
 
-    let contest_name_selector = SELECTOR2_CONTEST_NAME.get_or_init(|| Selector::parse("div#contest-table-upcoming tbody tr td:nth-child(2) a").unwrap());
-    let contest_name = document.select(contest_name_selector);
-    for (i, element) in contest_name.enumerate() {
-        contests[i].contest_name = element.inner_html();
-        let contest_link = element.attr("href").unwrap();
-        let contest_id = &contest_link[10..];
-        contests[i].contest_id = format!("{}.contest.atcoder.jp", contest_id);
-        contests[i].url = contest_link.to_string();
-    }
+impl std::error::Error for ContestPageError {}
 
-    let contest_duration_selector = SELECTOR2_CONTEST_DURATION.get_or_init(|| Selector::parse("div#contest-table-upcoming tbody tr td:nth-child(3)").unwrap());
-    let contest_duration = document.select(contest_duration_selector);
-    for (i, element) in contest_duration.enumerate() {
-        let contest_duration_raw = element.inner_html();
-        let contest_duration_split: Vec<&str> = contest_duration_raw.split(':').collect();
-        let hour = contest_duration_split[0].parse::<i32>().unwrap();
-        let minute = contest_duration_split[1].parse::<i32>().unwrap();
-        contests[i].contest_duration = hour * 60 + minute;
+impl From<reqwest::Error> for ContestPageError {
+    fn from(err: reqwest::Error) -> Self {
+        ContestPageError::Request(err)
     }
+}
+
+static SELECTOR_CONTEST_TABLES: OnceLock<[Selector; 4]> = OnceLock::new();
+static SELECTOR_CONTEST_ROWS: OnceLock<Selector> = OnceLock::new();
+static SELECTOR_CONTEST_CELLS: OnceLock<Selector> = OnceLock::new();
+static SELECTOR_CONTEST_TIME: OnceLock<Selector> = OnceLock::new();
+static SELECTOR_CONTEST_SPANS: OnceLock<Selector> = OnceLock::new();
+static SELECTOR_CONTEST_LINK: OnceLock<Selector> = OnceLock::new();
+static SELECTOR_CONTEST_PAGE_PAGINATION: OnceLock<Selector> = OnceLock::new();
 
-    let contest_rating_range_selector =
-        SELECTOR2_CONTEST_RATING_RANGE.get_or_init(|| Selector::parse("div#contest-table-upcoming tbody tr td:nth-child(4)").unwrap());
-    let contest_rating_range = document.select(contest_rating_range_selector);
-    for (i, element) in contest_rating_range.enumerate() {
-        let contest_rating_range_raw = element.inner_html();
-        if contest_rating_range_raw == "All" {
-            contests[i].rating_ragnge = (-998244353, 998244353);
-        } else if contest_rating_range_raw == "-" {
-            contests[i].rating_ragnge = (-998244353, -998244353);
-        } else {
-            let contest_rating_range_split: Vec<&str> = contest_rating_range_raw.split('~').collect();
-            let rating_first_str = contest_rating_range_split[0].trim();
-            let rating_end_str = contest_rating_range_split[1].trim();
-
-            let rating_first = if rating_first_str.is_empty() {
+/// Parse `"All"`, `"-"`, `"~ 1999"`, `"1200 ~ 2799"` or `"1200 ~"`. Unrated contests get an empty range below every rating.
+fn parse_rating_range(raw: &str) -> Option<(i32, i32)> {
+    match raw.trim() {
+        "All" => Some((-998244353, 998244353)),
+        "-" => Some((-998244353, -998244353)),
+        raw => {
+            let (first, end) = raw.split_once('~')?;
+            let first = if first.trim().is_empty() {
                 -998244353
             } else {
-                rating_first_str.parse::<i32>().unwrap()
-            };
-            let rating_end = if rating_end_str.is_empty() {
-                998244353
-            } else {
-                rating_end_str.parse::<i32>().unwrap()
+                first.trim().parse::<i32>().ok()?
             };
-
-            contests[i].rating_ragnge = (rating_first, rating_end);
+            let end = if end.trim().is_empty() { 998244353 } else { end.trim().parse::<i32>().ok()? };
+            Some((first, end))
         }
-        contests[i].rating_range_raw = contest_rating_range_raw;
     }
+}
 
-    GetContestPageResult { pages: 0, contests }
+/// Parse `"01:40"` into minutes. Long contests have more than two digits of hours.
+fn parse_duration(raw: &str) -> Option<i32> {
+    let (hour, minute) = raw.trim().split_once(':')?;
+    Some(hour.parse::<i32>().ok()? * 60 + minute.parse::<i32>().ok()?)
 }
 
-async fn get_past_contest_page(page: i32, get_pages: bool) -> GetContestPageResult {
+/// Read the contest name cell: the type mark, the colored rating type mark and the link to the contest.
+fn parse_name_cell(cell: ElementRef, contest: &mut Contest) -> Result<(), String> {
+    let span_selector = SELECTOR_CONTEST_SPANS.get_or_init(|| Selector::parse("span").unwrap());
+    let link_selector = SELECTOR_CONTEST_LINK.get_or_init(|| Selector::parse("a").unwrap());
+
+    let mut spans = cell.select(span_selector);
+    let type_mark = spans.next().ok_or("no contest type mark")?.text().collect::<String>();
+    contest.contest_type = match type_mark.trim() {
+        "Ⓐ" => ContestType::Algorithm,
+        "Ⓗ" => ContestType::Heuristic,
+        mark => return Err(format!("unknown contest type mark {:?}", mark)),
+    };
+    let rating_class = spans.find_map(|span| span.attr("class").filter(|class| class.starts_with("user-"))).unwrap_or_default();
+    contest.contest_rating_type = match rating_class {
+        "user-blue" => ContestRatingType::ABC,
+        "user-orange" => ContestRatingType::ARC,
+        "user-red" => ContestRatingType::AGC,
+        _ => ContestRatingType::None,
+    };
+
+    let link = cell.select(link_selector).find(|link| link.attr("href").is_some_and(|href| href.starts_with("/contests/"))).ok_or("no contest link")?;
+    let href = link.attr("href").unwrap_or_default();
+    contest.contest_id = ContestId::parse(href).ok_or(format!("unexpected contest link {:?}", href))?;
+    contest.contest_name = link.inner_html();
+    contest.url = href.to_string();
+    Ok(())
+}
+
+fn parse_contest_row(row: ElementRef, table: ContestTable) -> Result<Contest, String> {
+    let cell_selector = SELECTOR_CONTEST_CELLS.get_or_init(|| Selector::parse("td").unwrap());
+    let time_selector = SELECTOR_CONTEST_TIME.get_or_init(|| Selector::parse("time").unwrap());
+
+    let cells: Vec<ElementRef> = row.select(cell_selector).collect();
+    let mut contest = Contest::default();
+    let rating_range_cell = if table == ContestTable::Permanent {
+        let [name, rating_range] = cells.as_slice() else {
+            return Err(format!("expected 2 cells, found {}", cells.len()));
+        };
+        parse_name_cell(*name, &mut contest)?;
+        rating_range
+    } else {
+        let [start_time, name, duration, rating_range] = cells.as_slice() else {
+            return Err(format!("expected 4 cells, found {}", cells.len()));
+        };
+        let start_time = start_time.select(time_selector).next().ok_or("no start time")?.inner_html();
+        let start_time = DateTime::parse_from_str(&start_time, "%Y-%m-%d %H:%M:%S%z").map_err(|err| format!("start time {:?}: {}", start_time, err))?;
+        contest.start_time = Some(start_time.with_timezone(&Utc));
+        parse_name_cell(*name, &mut contest)?;
+        let duration = duration.text().collect::<String>();
+        contest.contest_duration = parse_duration(&duration).ok_or(format!("unexpected duration {:?}", duration))?;
+        rating_range
+    };
+    contest.rating_range_raw = rating_range_cell.inner_html().trim().to_string();
+    contest.rating_ragnge = parse_rating_range(&contest.rating_range_raw).ok_or(format!("unexpected rated range {:?}", contest.rating_range_raw))?;
+    Ok(contest)
+}
+
+/// Parse one contest table row by row. A row that cannot be read fails the whole table, because a contest silently
+/// missing from the list would look like a cancellation to [`update_contests`].
+///
+/// AtCoder leaves out the table of running contests when there are none, so a missing [`ContestTable::Action`] is empty.
+pub fn parse_contest_table(html: &str, table: ContestTable) -> Result<Vec<Contest>, ContestPageError> {
+    let document = scraper::Html::parse_document(html);
+    let table_selectors = SELECTOR_CONTEST_TABLES.get_or_init(|| {
+        [
+            Selector::parse("#contest-table-action").unwrap(),
+            Selector::parse("#contest-table-upcoming").unwrap(),
+            Selector::parse("#contest-table-permanent").unwrap(),
+            Selector::parse("div.table-responsive").unwrap(),
+        ]
+    });
+    let row_selector = SELECTOR_CONTEST_ROWS.get_or_init(|| Selector::parse("tbody tr").unwrap());
+
+    let Some(element) = document.select(&table_selectors[table.index()]).next() else {
+        return match table {
+            ContestTable::Action => Ok(vec![]),
+            _ => Err(ContestPageError::MissingTable(table)),
+        };
+    };
+    element
+        .select(row_selector)
+        .enumerate()
+        .map(|(row, element)| parse_contest_row(element, table).map_err(|reason| ContestPageError::Row { table, row, reason }))
+        .collect()
+}
+
+/// Running and upcoming contests from `/contests`.
+async fn get_contest_page() -> Result<Vec<Contest>, ContestPageError> {
+    log::info!("Get Contests Page");
+    let contest_page_html = reqwest::get("https://atcoder.jp/contests/?lang=ja").await?.error_for_status()?.text().await?;
+
+    let mut contests = parse_contest_table(&contest_page_html, ContestTable::Action)?;
+    contests.append(&mut parse_contest_table(&contest_page_html, ContestTable::Upcoming)?);
+    Ok(contests)
+}
+
+async fn get_past_contest_page(page: i32, get_pages: bool) -> Result<GetContestPageResult, ContestPageError> {
     log::info!("Get Contest Page: page={}", page);
+    let contest_page_html = reqwest::get(format!("https://atcoder.jp/contests/archive?page={}&lang=ja", page)).await?.error_for_status()?.text().await?;
+
+    let contests = parse_contest_table(&contest_page_html, ContestTable::Archive)?;
+    let pages = if get_pages { parse_page_count(&contest_page_html) } else { -1 };
 
-    let mut contests: Vec<Contest> = vec![];
+    Ok(GetContestPageResult { pages, contests })
+}
+
+/// The last page number linked from the archive pagination.
+fn parse_page_count(html: &str) -> i32 {
+    let document = scraper::Html::parse_document(html);
+    let contest_page_pagination_selector = SELECTOR_CONTEST_PAGE_PAGINATION.get_or_init(|| Selector::parse("a[href^=\"/contests/archive?lang=ja&\"]").unwrap());
+    document
+        .select(contest_page_pagination_selector)
+        .filter_map(|element| {
+            let url = Url::parse(format!("https://atcoder.jp{}", element.attr("href")?).as_str()).ok()?;
+            let page = url.query_pairs().find(|(k, _)| k == "page")?.1.parse::<i32>().ok()?;
+            Some(page)
+        })
+        .max()
+        .unwrap_or(-1)
+}
 
-    let contest_page_html = reqwest::get(format!("https://atcoder.jp/contests/archive?page={}&lang=ja", page)).await.unwrap().text().await.unwrap();
+/// What [`update_contests`] noticed about a contest compared to the stored row.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContestChangeKind {
+    Announced,
+    Changed,
+    Cancelled,
+}
 
-    let document = scraper::Html::parse_document(&contest_page_html);
+#[derive(Debug, Clone)]
+pub struct ContestChange {
+    pub kind: ContestChangeKind,
+    pub contest_id: ContestId,
+    pub name: String,
+    /// As stored in `contests.contest_type`.
+    pub contest_type: i8,
+    pub start_time: DateTime<Utc>,
+    pub duration: i32,
+    pub rating_range_raw: String,
+    /// `(field, old value, new value)` for [`ContestChangeKind::Changed`].
+    pub fields: Vec<(String, String, String)>,
+}
 
-    let contests_time_selector = SELECTOR_CONTESTS.get_or_init(|| Selector::parse("div.table-responsive tbody tr td:nth-child(1) time").unwrap());
-    let contests_time = document.select(contests_time_selector);
-    for element in contests_time {
-        contests.push(Contest {
-            start_time: element.inner_html(),
-            ..Default::default()
-        });
+fn contest_type_value(contest: &Contest) -> i8 {
+    match contest.contest_type {
+        ContestType::Algorithm => 0,
+        ContestType::Heuristic => 1,
     }
+}
 
-    let contests_type_selector =
-        SELECTOR_CONTEST_TYPE.get_or_init(|| Selector::parse("div.table-responsive tbody tr td:nth-child(2) span:nth-child(1)").unwrap());
-    let contest_type = document.select(contests_type_selector);
-    for (i, element) in contest_type.enumerate() {
-        let is_algo = element.inner_html() == "Ⓐ";
-        if is_algo {
-            contests[i].contest_type = ContestType::Algorithm;
-        } else {
-            contests[i].contest_type = ContestType::Heuristic;
-        }
+fn rating_type_value(contest: &Contest) -> i8 {
+    match contest.contest_rating_type {
+        ContestRatingType::ABC => 0,
+        ContestRatingType::ARC => 1,
+        ContestRatingType::AGC => 2,
+        ContestRatingType::None => 3,
     }
+}
+
+fn end_time(start_time: DateTime<Utc>, duration: i32) -> DateTime<Utc> {
+    start_time + chrono::Duration::minutes(duration as i64)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncMode {
+    /// Page through the archive until a page holds only known contests.
+    Incremental,
+    /// Fetch every archive page.
+    Full,
+}
 
-    let contests_rating_type_selector =
-        SELECTOR_CONTEST_RATING_TYPE.get_or_init(|| Selector::parse("div.table-responsive tbody tr td:nth-child(2) span:nth-child(2)").unwrap());
-    let contest_rating_type = document.select(contests_rating_type_selector);
-    for (i, element) in contest_rating_type.enumerate() {
-        let contest_rating_type = element.attr("class").unwrap();
-        if contest_rating_type == "user-blue" {
-            contests[i].contest_rating_type = ContestRatingType::ABC;
-        } else if contest_rating_type == "user-orange" {
-            contests[i].contest_rating_type = ContestRatingType::ARC;
-        } else if contest_rating_type == "user-red" {
-            contests[i].contest_rating_type = ContestRatingType::AGC;
-        } else {
-            contests[i].contest_rating_type = ContestRatingType::None;
+impl SyncMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            SyncMode::Incremental => "incremental",
+            SyncMode::Full => "full",
         }
     }
+}
 
-    let contest_name_selector = SELECTOR_CONTEST_NAME.get_or_init(|| Selector::parse("div.table-responsive tbody tr td:nth-child(2) a").unwrap());
-    let contest_name = document.select(contest_name_selector);
-    for (i, element) in contest_name.enumerate() {
-        contests[i].contest_name = element.inner_html();
-        let contest_link = element.attr("href").unwrap();
-        let contest_id = &contest_link[10..];
-        contests[i].contest_id = format!("{}.contest.atcoder.jp", contest_id);
-        contests[i].url = contest_link.to_string();
-    }
+/// The outcome of one [`update_contests`] run, also recorded in `contest_syncs`.
+#[derive(Debug, Default)]
+pub struct ContestSync {
+    pub archive_pages: i32,
+    pub contests_seen: usize,
+    pub changes: Vec<ContestChange>,
+}
 
-    let contest_duration_selector = SELECTOR_CONTEST_DURATION.get_or_init(|| Selector::parse("div.table-responsive tbody tr td:nth-child(3)").unwrap());
-    let contest_duration = document.select(contest_duration_selector);
-    for (i, element) in contest_duration.enumerate() {
-        let contest_duration_raw = element.inner_html();
-        let contest_duration_split: Vec<&str> = contest_duration_raw.split(':').collect();
-        let hour = contest_duration_split[0].parse::<i32>().unwrap();
-        let minute = contest_duration_split[1].parse::<i32>().unwrap();
-        contests[i].contest_duration = hour * 60 + minute;
-    }
+static SYNC_RUNNING: AtomicBool = AtomicBool::new(false);
 
-    let contest_rating_range_selector = SELECTOR_CONTEST_RATING_RANGE.get_or_init(|| Selector::parse("div.table-responsive tbody tr td:nth-child(4)").unwrap());
-    let contest_rating_range = document.select(contest_rating_range_selector);
-    for (i, element) in contest_rating_range.enumerate() {
-        let contest_rating_range_raw = element.inner_html();
-        if contest_rating_range_raw == "All" {
-            contests[i].rating_ragnge = (-998244353, 998244353);
-        } else if contest_rating_range_raw == "-" {
-            contests[i].rating_ragnge = (-998244353, -998244353);
-        } else {
-            let contest_rating_range_split: Vec<&str> = contest_rating_range_raw.split('~').collect();
-            let rating_first_str = contest_rating_range_split[0].trim();
-            let rating_end_str = contest_rating_range_split[1].trim();
-
-            let rating_first = if rating_first_str.is_empty() {
-                -998244353
-            } else {
-                rating_first_str.parse::<i32>().unwrap()
-            };
-            let rating_end = if rating_end_str.is_empty() {
-                998244353
-            } else {
-                rating_end_str.parse::<i32>().unwrap()
-            };
+/// Clears [`SYNC_RUNNING`] when the sync ends, even when it panics.
+struct SyncRunningGuard;
 
-            contests[i].rating_ragnge = (rating_first, rating_end);
-        }
-        contests[i].rating_range_raw = contest_rating_range_raw;
+impl Drop for SyncRunningGuard {
+    fn drop(&mut self) {
+        SYNC_RUNNING.store(false, Ordering::SeqCst);
     }
+}
 
-    let mut max = -1;
-    if get_pages {
-        let contest_page_pagination_selector =
-            SELECTOR_CONTEST_PAGE_PAGINATION.get_or_init(|| Selector::parse("a[href^=\"/contests/archive?lang=ja&\"]").unwrap());
-        let contest_page_pagination = document.select(contest_page_pagination_selector);
-        for element in contest_page_pagination {
-            let query = Url::parse(format!("https://atcoder.jp{}", element.attr("href").unwrap()).as_str())
-                .unwrap()
-                .query_pairs()
-                .find(|(k, _)| k == "page")
-                .unwrap()
-                .1
-                .to_string()
-                .parse::<i32>()
-                .unwrap();
-            max = std::cmp::max(max, query);
-        }
+/// Bring the `contests` table in line with the contest pages and return what changed.
+///
+/// New contests are inserted, contests whose schedule, name or rated range changed are updated, and upcoming
+/// contests that disappeared from AtCoder are deleted. Every change is recorded in `contest_changes`, and the run
+/// itself in `contest_syncs`. Only one sync runs at a time.
+pub async fn update_contests(pool: &Arc<Mutex<Pool>>, mode: SyncMode) -> std::result::Result<ContestSync, crate::Error> {
+    if SYNC_RUNNING.swap(true, Ordering::SeqCst) {
+        return Err("a contest sync is already running".into());
     }
+    let _running = SyncRunningGuard;
+    let pool = pool.lock().await.clone();
+    let started_at = Instant::now();
+    let result = async {
+        let mut conn = pool.get_conn()?;
+        conn.exec_drop(
+            "INSERT INTO contest_syncs (mode, status) VALUES (:mode, 'running')",
+            params! {"mode" => mode.as_str()},
+        )?;
+        let sync_id = conn.last_insert_id();
+        let result = sync_contests(&pool, mode, sync_id).await;
+        let (status, changes, error) = match &result {
+            Ok(sync) => ("ok", sync.changes.len(), None),
+            Err(err) => ("failed", 0, Some(err.to_string())),
+        };
+        conn.exec_drop(
+            "UPDATE contest_syncs SET status=:status, changes=:changes, error=:error, finished_at=NOW(), duration_ms=:duration_ms WHERE id=:id",
+            params! {
+                "status" => status,
+                "changes" => changes,
+                "error" => error,
+                "duration_ms" => started_at.elapsed().as_millis() as u64,
+                "id" => sync_id,
+            },
+        )?;
+        result
+    }
+    .await;
+    if let Ok(sync) = &result {
+        log::info!(
+            "{} contest sync: {} archive pages, {} changes in {:?}",
+            mode.as_str(),
+            sync.archive_pages,
+            sync.changes.len(),
+            started_at.elapsed()
+        );
+    }
+    result
+}
 
-    let pages = max;
+async fn sync_contests(pool: &Pool, mode: SyncMode, sync_id: u64) -> std::result::Result<ContestSync, crate::Error> {
+    let contests: Vec<ContestDataTuple> = pool.get_conn()?.query(r"SELECT `id`,  `contest_id`,  `name`,  `start_at`,  `duration`,  `url`,  `contest_type`,  `rating_type`,  `rating_range_start`,  `rating_range_end`,  `rating_range_raw`,  `get_user_ratings_flag` FROM contests")?;
+    // A fresh database is filled from the archive, which is not news to anyone.
+    let announce = !contests.is_empty();
+    let mut stored: HashMap<ContestId, ContestDataTuple> = contests.into_iter().map(|contest| (ContestId::new(&contest.1), contest)).collect();
 
-    GetContestPageResult { pages, contests }
-}
-pub async fn update_contests(pool: &Arc<Mutex<Pool>>) -> mysql::Result<()> {
-    let first_page = get_contest_page().await;
-    let mut contest_vec: Vec<Contest> = first_page.contests;
-    sleep(Duration::from_millis(100)).await;
-    let mut first_page = get_past_contest_page(1, true).await;
-    contest_vec.append(&mut first_page.contests);
-    sleep(Duration::from_millis(100)).await;
-    log::info!("pages: {}", first_page.pages);
-    for i in 2..=first_page.pages {
-        let mut page = get_past_contest_page(i, false).await;
-        contest_vec.append(&mut page.contests);
+    let mut contest_vec: Vec<Contest> = get_contest_page().await?;
+    let mut page = 1;
+    let mut pages = 1;
+    loop {
         sleep(Duration::from_millis(100)).await;
+        let mut result = get_past_contest_page(page, page == 1).await?;
+        if page == 1 {
+            pages = result.pages;
+            log::info!("pages: {}", pages);
+        }
+        let has_new_contest = result.contests.iter().any(|contest| !stored.contains_key(&contest.contest_id));
+        contest_vec.append(&mut result.contests);
+        pool.get_conn()?.exec_drop(
+            "UPDATE contest_syncs SET archive_pages=:archive_pages, contests_seen=:contests_seen WHERE id=:id",
+            params! {"archive_pages" => page, "contests_seen" => contest_vec.len(), "id" => sync_id},
+        )?;
+        if page >= pages || (mode == SyncMode::Incremental && !has_new_contest) {
+            break;
+        }
+        page += 1;
     }
+    let mut sync = ContestSync {
+        archive_pages: page,
+        contests_seen: contest_vec.len(),
+        changes: vec![],
+    };
     log::info!("Start Database Insert");
-    let pool = pool.lock().await;
-    let mut conn = pool.get_conn().unwrap();
-    let contests: Vec<ContestDataTuple> = conn.query(r"SELECT `id`,  `contest_id`,  `name`,  `start_time`,  `duration`,  `url`,  `contest_type`,  `rating_type`,  `rating_range_start`,  `rating_range_end`,  `rating_range_raw`,  `get_user_ratings_flag` FROM contests").unwrap();
-    let mut contests_list: HashSet<String> = HashSet::new();
-    for i in contests {
-        contests_list.insert(i.1);
-    }
+    let mut conn = pool.get_conn()?;
+    let mut seen: HashSet<ContestId> = HashSet::new();
+    let changes = &mut sync.changes;
+    let now = Utc::now();
     for i in contest_vec {
-        if !contests_list.contains(&i.contest_id) {
+        if !seen.insert(i.contest_id.clone()) {
+            continue;
+        }
+        // Only contests without a schedule lack a start time, and those are not stored.
+        let Some(start_time) = i.start_time else {
+            continue;
+        };
+        let end_time = end_time(start_time, i.contest_duration);
+        let Some(row) = stored.remove(&i.contest_id) else {
             log::info!("Insert to database: {}", &i.contest_id);
-            conn.exec_drop(r"INSERT INTO contests (contest_id, name, start_time, duration, url, contest_type, rating_type, rating_range_start, rating_range_end, rating_range_raw, get_user_ratings_flag)
-                            VALUES (:contest_id, :name, :start_time, :duration, :url, :type, :rating_type, :rating_range_start, :rating_range_end, :rating_range_raw, 0)",
+            conn.exec_drop(r"INSERT INTO contests (contest_id, name, start_at, end_at, duration, url, contest_type, rating_type, rating_range_start, rating_range_end, rating_range_raw, get_user_ratings_flag)
+                            VALUES (:contest_id, :name, :start_at, :end_at, :duration, :url, :type, :rating_type, :rating_range_start, :rating_range_end, :rating_range_raw, 0)",
                             params! {
                                 "contest_id" => &i.contest_id,
-                                "name" => i.contest_name,
-                                "start_time" => i.start_time,
+                                "name" => &i.contest_name,
+                                "start_at" => start_time.naive_utc(),
+                                "end_at" => end_time.naive_utc(),
                                 "duration" => i.contest_duration,
-                                "url" => i.url,
-                                "type" => match i.contest_type {
-                                    ContestType::Algorithm => 0,
-                                    ContestType::Heuristic => 1
-                                },
-                                "rating_type" => match i.contest_rating_type {
-                                    ContestRatingType::ABC => 0,
-                                    ContestRatingType::ARC => 1,
-                                    ContestRatingType::AGC => 2,
-                                    ContestRatingType::None => 3,
-                                },
+                                "url" => &i.url,
+                                "type" => contest_type_value(&i),
+                                "rating_type" => rating_type_value(&i),
                                 "rating_range_start" => i.rating_ragnge.0,
                                 "rating_range_end" => i.rating_ragnge.1,
-                                "rating_range_raw" => i.rating_range_raw
-            }).unwrap();
+                                "rating_range_raw" => &i.rating_range_raw
+            })?;
+            if announce && now < start_time {
+                changes.push(ContestChange {
+                    kind: ContestChangeKind::Announced,
+                    contest_type: contest_type_value(&i),
+                    contest_id: i.contest_id,
+                    name: i.contest_name,
+                    start_time,
+                    duration: i.contest_duration,
+                    rating_range_raw: i.rating_range_raw,
+                    fields: vec![],
+                });
+            }
+            continue;
+        };
+
+        let mut fields = vec![];
+        let mut compare = |field: &str, old: String, new: String| {
+            if old != new {
+                fields.push((field.to_string(), old, new));
+            }
+        };
+        compare("name", row.2.clone(), i.contest_name.clone());
+        compare("start_time", row.3.and_utc().to_rfc3339(), start_time.to_rfc3339());
+        compare("duration", row.4.to_string(), i.contest_duration.to_string());
+        compare("contest_type", row.6.to_string(), contest_type_value(&i).to_string());
+        compare("rating_type", row.7.to_string(), rating_type_value(&i).to_string());
+        compare("rating_range", row.10.clone(), i.rating_range_raw.clone());
+        if fields.is_empty() {
+            continue;
+        }
+        log::info!("Update contest: {} {:?}", &i.contest_id, fields);
+        // A contest moved to a time still ahead gets its reminders again, even if they went out for the old time.
+        let rescheduled = row.3 != start_time.naive_utc() && now < start_time;
+        conn.exec_drop(
+            r"UPDATE contests SET name=:name, start_at=:start_at, end_at=:end_at, duration=:duration, url=:url, contest_type=:type, rating_type=:rating_type,
+                rating_range_start=:rating_range_start, rating_range_end=:rating_range_end, rating_range_raw=:rating_range_raw,
+                is_do_notify=IF(:rescheduled, 0, is_do_notify), is_rsvp_notified=IF(:rescheduled, 0, is_rsvp_notified)
+            WHERE contest_id=:contest_id",
+            params! {
+                "contest_id" => &i.contest_id,
+                "rescheduled" => rescheduled,
+                "name" => &i.contest_name,
+                "start_at" => start_time.naive_utc(),
+                "end_at" => end_time.naive_utc(),
+                "duration" => i.contest_duration,
+                "url" => &i.url,
+                "type" => contest_type_value(&i),
+                "rating_type" => rating_type_value(&i),
+                "rating_range_start" => i.rating_ragnge.0,
+                "rating_range_end" => i.rating_ragnge.1,
+                "rating_range_raw" => &i.rating_range_raw
+            },
+        )?;
+        record_changes(&mut conn, &i.contest_id, &fields)?;
+        if now <= end_time {
+            changes.push(ContestChange {
+                kind: ContestChangeKind::Changed,
+                contest_type: contest_type_value(&i),
+                contest_id: i.contest_id,
+                name: i.contest_name,
+                start_time,
+                duration: i.contest_duration,
+                rating_range_raw: i.rating_range_raw,
+                fields,
+            });
+        }
+    }
+
+    // Upcoming contests are always listed on the contest page, so one that is missing has been cancelled.
+    for (contest_id, row) in stored {
+        let start_time = row.3.and_utc();
+        if start_time <= now {
+            continue;
+        }
+        log::info!("Delete cancelled contest: {}", &contest_id);
+        conn.exec_drop("DELETE FROM contests WHERE contest_id=:contest_id", params! {"contest_id" => &contest_id})?;
+        record_changes(&mut conn, &contest_id, &[("cancelled".to_string(), start_time.to_rfc3339(), "".to_string())])?;
+        changes.push(ContestChange {
+            kind: ContestChangeKind::Cancelled,
+            contest_id,
+            name: row.2,
+            contest_type: row.6,
+            start_time,
+            duration: row.4,
+            rating_range_raw: row.10,
+            fields: vec![],
+        });
+    }
+    for change in changes.iter().filter(|change| change.kind == ContestChangeKind::Announced) {
+        record_changes(
+            &mut conn,
+            &change.contest_id,
+            &[("announced".to_string(), "".to_string(), change.start_time.to_rfc3339())],
+        )?;
+    }
+    Ok(sync)
+}
+
+fn record_changes(conn: &mut PooledConn, contest_id: &ContestId, fields: &[(String, String, String)]) -> mysql::Result<()> {
+    conn.exec_batch(
+        "INSERT INTO contest_changes (contest_id, field, old_value, new_value) VALUES (:contest_id, :field, :old_value, :new_value)",
+        fields.iter().map(|(field, old_value, new_value)| {
+            params! {
+                "contest_id" => contest_id.as_str(),
+                "field" => field,
+                "old_value" => old_value,
+                "new_value" => new_value,
+            }
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTESTS_HTML: &str = include_str!("fixtures/contests.html");
+    const ARCHIVE_HTML: &str = include_str!("fixtures/contests_archive.html");
+
+    #[test]
+    fn parses_upcoming_contests() {
+        let contests = parse_contest_table(CONTESTS_HTML, ContestTable::Upcoming).unwrap();
+        let ids: Vec<&str> = contests.iter().map(|contest| contest.contest_id.as_str()).collect();
+        assert_eq!(ids, ["abc376", "arc186", "agc069", "wtf24-day1"]);
+
+        let abc = &contests[0];
+        assert_eq!(abc.contest_name, "AtCoder Beginner Contest 376");
+        assert_eq!(
+            abc.start_time.map(|start_time| start_time.to_rfc3339()).as_deref(),
+            Some("2024-10-19T12:00:00+00:00")
+        );
+        assert_eq!(abc.contest_duration, 100);
+        assert_eq!(abc.url, "/contests/abc376");
+        assert!(matches!(abc.contest_type, ContestType::Algorithm));
+        assert!(matches!(abc.contest_rating_type, ContestRatingType::ABC));
+        assert_eq!(abc.rating_range_raw, "~ 1999");
+        assert_eq!(abc.rating_ragnge, (-998244353, 1999));
+
+        assert!(matches!(contests[1].contest_rating_type, ContestRatingType::ARC));
+        assert_eq!(contests[1].rating_ragnge, (1600, 2999));
+        assert!(matches!(contests[2].contest_rating_type, ContestRatingType::AGC));
+        assert_eq!(contests[2].rating_ragnge, (1200, 998244353));
+        assert!(matches!(contests[3].contest_rating_type, ContestRatingType::None));
+        assert_eq!(contests[3].rating_ragnge, (-998244353, -998244353));
+    }
+
+    #[test]
+    fn parses_long_running_contests() {
+        let contests = parse_contest_table(CONTESTS_HTML, ContestTable::Action).unwrap();
+        assert_eq!(contests.len(), 1);
+        assert_eq!(contests[0].contest_id.as_str(), "ahc038");
+        assert_eq!(contests[0].contest_duration, 240 * 60);
+        assert!(matches!(contests[0].contest_type, ContestType::Heuristic));
+        assert_eq!(contests[0].rating_ragnge, (-998244353, 998244353));
+    }
+
+    #[test]
+    fn parses_permanent_contests() {
+        let contests = parse_contest_table(CONTESTS_HTML, ContestTable::Permanent).unwrap();
+        let ids: Vec<&str> = contests.iter().map(|contest| contest.contest_id.as_str()).collect();
+        assert_eq!(ids, ["practice", "abs"]);
+        assert_eq!(contests[0].start_time, None);
+        assert_eq!(contests[0].contest_duration, 0);
+        assert_eq!(contests[0].rating_range_raw, "-");
+    }
+
+    #[test]
+    fn parses_archive() {
+        let contests = parse_contest_table(ARCHIVE_HTML, ContestTable::Archive).unwrap();
+        let ids: Vec<&str> = contests.iter().map(|contest| contest.contest_id.as_str()).collect();
+        assert_eq!(ids, ["abc375", "ahc037", "jsc2024-final"]);
+        assert!(matches!(contests[1].contest_type, ContestType::Heuristic));
+        assert_eq!(contests[2].contest_duration, 180);
+        assert_eq!(parse_page_count(ARCHIVE_HTML), 58);
+    }
+
+    #[test]
+    fn missing_action_table_is_empty() {
+        assert!(parse_contest_table(ARCHIVE_HTML, ContestTable::Action).unwrap().is_empty());
+        assert!(matches!(
+            parse_contest_table(ARCHIVE_HTML, ContestTable::Upcoming),
+            Err(ContestPageError::MissingTable(ContestTable::Upcoming))
+        ));
+    }
+
+    #[test]
+    fn reports_the_broken_row() {
+        let html = ARCHIVE_HTML.replacen("<span class=\"user-orange\">◉</span>", "", 1).replacen("04:00", "four hours", 1);
+        match parse_contest_table(&html, ContestTable::Archive) {
+            Err(ContestPageError::Row { table, row, reason }) => {
+                assert_eq!(table, ContestTable::Archive);
+                assert_eq!(row, 1);
+                assert!(reason.contains("four hours"), "{}", reason);
+            }
+            result => panic!("unexpected result: {:?}", result.map(|contests| contests.len())),
         }
     }
-    Ok(())
 }
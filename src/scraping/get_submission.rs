@@ -1,6 +1,6 @@
 use mysql::prelude::*;
 use mysql::*;
-use poise::serenity_prelude::{self as serenity, ChannelId, CreateEmbed, CreateMessage};
+use poise::serenity_prelude::CreateEmbed;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json;
@@ -11,6 +11,11 @@ use std::{
 };
 use tokio::{sync::Mutex, time::sleep};
 
+use crate::mentions::{self, NotificationKind};
+use crate::outbox::{self, OutboxKind, OutboxMessage};
+use crate::routing::{RouteEvent, Routes};
+use crate::scraping::contest_id::ContestId;
+
 #[derive(Deserialize, Serialize)]
 pub struct Submission {
     pub id: i64,
@@ -38,7 +43,7 @@ struct Diff {
 
 const RATING_COLORS: [u32; 9] = [0xFFFFFF, 0xC0C0C0, 0xB08C56, 0x3FAF3F, 0x42E0E0, 0x8888FF, 0xFFFF56, 0xFFB836, 0xFF6767];
 
-pub async fn get_submission(pool: &Arc<Mutex<Pool>>, ctx: &serenity::Context) {
+pub async fn get_submission(pool: &Arc<Mutex<Pool>>) {
     let pool = pool.lock().await;
     log::info!("test");
     let mut conn = pool.get_conn().unwrap();
@@ -47,7 +52,7 @@ pub async fn get_submission(pool: &Arc<Mutex<Pool>>, ctx: &serenity::Context) {
         let diff_response = client.get("https://kenkoooo.com/atcoder/resources/problem-models.json").send().await.unwrap();
         let text = diff_response.text().await.unwrap_or_default();
         let diff: BTreeMap<String, Diff> = serde_json::from_str(&text).unwrap();
-        let users: Vec<(String, u64, u64)> = conn
+        let users: Vec<(String, Option<u64>, u64)> = conn
             .query(
                 "SELECT
                     users.atcoder_username,
@@ -60,10 +65,13 @@ pub async fn get_submission(pool: &Arc<Mutex<Pool>>, ctx: &serenity::Context) {
                 ON
                     users.server_id = notifications.server_id
                 WHERE
-                    notifications.submission_channel_id IS NOT NULL",
+                    notifications.submission_channel_id IS NOT NULL
+                    OR notifications.server_id IN (SELECT server_id FROM notification_routes WHERE event='ac')",
             )
             .unwrap();
-        let mut users_map: BTreeMap<String, Vec<(u64, u64)>> = BTreeMap::new();
+        let routes = Routes::load(&mut conn, RouteEvent::Submission).unwrap();
+        let contest_types: BTreeMap<String, i8> = conn.query("SELECT contest_id, contest_type FROM contests").unwrap().into_iter().collect();
+        let mut users_map: BTreeMap<String, Vec<(Option<u64>, u64)>> = BTreeMap::new();
         for i in &users {
             if users_map.contains_key(&i.0) {
                 users_map.get_mut(&i.0).unwrap().push((i.1, i.2));
@@ -139,7 +147,6 @@ pub async fn get_submission(pool: &Arc<Mutex<Pool>>, ctx: &serenity::Context) {
                         }
                         last = std::cmp::max(last, j.epoch_second + 1);
                         let response_ja = {
-                            let response = CreateMessage::default();
                             let embed = CreateEmbed::default()
                                 .title(if submissions.contains(&j.problem_id) {
                                     "AC Notify"
@@ -151,10 +158,9 @@ pub async fn get_submission(pool: &Arc<Mutex<Pool>>, ctx: &serenity::Context) {
                                     j.user_id, j.contest_id, j.problem_id, diff_text
                                 ))
                                 .color(color);
-                            response.embed(embed)
+                            embed
                         };
                         let response_en = {
-                            let response = CreateMessage::default();
                             let embed = CreateEmbed::default()
                                 .title(if submissions.contains(&j.problem_id) {
                                     "AC Notify"
@@ -163,16 +169,24 @@ pub async fn get_submission(pool: &Arc<Mutex<Pool>>, ctx: &serenity::Context) {
                                 })
                                 .description(format!("{} has solved {} in {}. Diff is {}", j.user_id, j.problem_id, j.contest_id, diff_text))
                                 .color(color);
-                            response.embed(embed)
+                            embed
                         };
+                        let contest_id = ContestId::new(&j.contest_id);
+                        let contest_type = contest_types.get(contest_id.as_str()).copied();
                         let mut channel_ids = BTreeSet::new();
                         for k in users_map.get(&i).unwrap() {
-                            if channel_ids.contains(&k.0) {
-                                log::info!("channel: {} was skipped", k.0);
+                            let mut channels = routes.channels(k.1, k.0, &contest_id, contest_type);
+                            channels.retain(|channel_id| {
+                                if channel_ids.contains(channel_id) {
+                                    log::info!("channel: {} was skipped", channel_id);
+                                    return false;
+                                }
+                                channel_ids.insert(*channel_id);
+                                true
+                            });
+                            if channels.is_empty() {
                                 continue;
                             }
-                            let channel = ChannelId::new(k.0);
-                            channel_ids.insert(k.0);
                             let selected_data: Vec<(String, i32)> = conn
                                 .exec(
                                     r"SELECT language, ac_notify FROM server_settings WHERE server_id=:server_id",
@@ -188,17 +202,21 @@ pub async fn get_submission(pool: &Arc<Mutex<Pool>>, ctx: &serenity::Context) {
                             } else if submissions.contains(&j.problem_id) {
                                 continue;
                             }
-                            if lang == "en" {
-                                let _ = channel.send_message(&ctx.http, response_en.clone()).await;
-                            } else {
-                                let _ = channel.send_message(&ctx.http, response_ja.clone()).await;
+                            let embed = if lang == "en" { response_en.clone() } else { response_ja.clone() };
+                            let mention = mentions::mention_text(&mut conn, k.1, NotificationKind::Submission).unwrap();
+                            for channel_id in channels {
+                                let mut message = OutboxMessage::new(OutboxKind::Submission, Some(k.1), channel_id).embeds(vec![embed.clone()]);
+                                if let Some(mention) = mention.clone() {
+                                    message = message.content(mention);
+                                }
+                                outbox::enqueue(&mut conn, message).unwrap();
                             }
                         }
                         if !submissions.contains(&j.problem_id) {
                             conn.exec_drop(
                                 "INSERT INTO submission_data (user_id, problem_id) VALUES (:user_id, :problem_id)",
                                 params! {
-                                    "user_id" => j.user_id,
+                                    "user_id" => &i,
                                     "problem_id" => &j.problem_id
                                 },
                             )
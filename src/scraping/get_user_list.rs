@@ -1,13 +1,13 @@
-use chrono::DateTime;
 use mysql::prelude::*;
 use mysql::*;
 use poise::serenity_prelude::{Context, GuildId, RoleId, UserId};
-use std::cmp::Ordering;
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 use std::time::Instant;
 use tokio::sync::Mutex;
 
+use super::atcoder_identity::user_key;
+
 pub async fn user_list_update(conn: &Arc<Mutex<Pool>>, ctx: &Context) -> Result<()> {
     let pool = conn.lock().await;
     let mut conn = pool.get_conn().unwrap();
@@ -22,19 +22,21 @@ pub async fn user_list_update(conn: &Arc<Mutex<Pool>>, ctx: &Context) -> Result<
             contests
         ON
             contests.contest_id = user_ratings.contest
-         ORDER BY contests.start_time",
+         ORDER BY contests.start_at DESC",
         )
         .unwrap();
     log::info!("get all of db {:?}", start_time.elapsed());
     let mut user_algo_history: BTreeMap<String, Vec<(i32, i32, String)>> = BTreeMap::new();
     let mut user_heuristic_history: BTreeMap<String, Vec<(i32, i32, String)>> = BTreeMap::new();
-    let mut user_set = HashSet::new();
+    // Screen names keyed by `user_key`, which the maps below use as the stored case of a name may differ.
+    let mut user_set = HashMap::new();
+    // Newest contest first.
     for i in list {
-        user_set.insert(i.4.clone().to_lowercase());
+        user_set.insert(user_key(&i.4), i.4.clone());
         let history = if i.5 == 0 {
-            user_algo_history.entry(i.4.clone().to_lowercase()).or_default()
+            user_algo_history.entry(user_key(&i.4)).or_default()
         } else {
-            user_heuristic_history.entry(i.4.clone().to_lowercase()).or_default()
+            user_heuristic_history.entry(user_key(&i.4)).or_default()
         };
         history.push((i.1, i.2, i.3));
     }
@@ -43,29 +45,32 @@ pub async fn user_list_update(conn: &Arc<Mutex<Pool>>, ctx: &Context) -> Result<
         .query(
             r"SELECT
             users.discord_id,
-            users.atcoder_username,
+            COALESCE(atcoder_identities.screen_name, users.atcoder_username),
             users.server_id,
             COALESCE(atcoder_user_ratings.algo_rating, 0) AS algo_rating
         FROM
             users
+        LEFT JOIN
+            atcoder_identities
+        ON
+            atcoder_identities.user_key = users.atcoder_username
         LEFT JOIN
             atcoder_user_ratings
         ON
-            users.atcoder_username = atcoder_user_ratings.user_name AND users.discord_id IS NOT NULL
+            COALESCE(atcoder_identities.screen_name, users.atcoder_username) = atcoder_user_ratings.user_name AND users.discord_id IS NOT NULL
+        LEFT JOIN
+            server_settings
+        ON
+            users.server_id = server_settings.server_id
         WHERE
-            users.discord_id IS NOT NULL",
+            users.discord_id IS NOT NULL AND (users.verified = 1 OR COALESCE(server_settings.require_verification, 0) = 0)",
         )
         .unwrap();
     log::info!("add to BTreeMap: {:?}", start_time.elapsed());
-    let contests: Vec<(String, String)> = conn.query("select contest_id,start_time from contests").unwrap();
-    let mut contest_data = BTreeMap::new();
-    for (contest_id, start_time) in contests {
-        contest_data.insert(contest_id, DateTime::parse_from_str(&start_time, "%Y-%m-%d %H:%M:%S%z").unwrap());
-    }
     let mut user_rating_map = BTreeMap::new();
     let mut transaction = conn.start_transaction(TxOpts::default()).unwrap();
     transaction.query_drop("delete from atcoder_user_ratings").unwrap();
-    for i in &user_set {
+    for (i, screen_name) in &user_set {
         let mut algo_aperf = 0.0;
         let mut algo_rating = 0;
         let mut heuristic_aperf = 0.0;
@@ -73,15 +78,7 @@ pub async fn user_list_update(conn: &Arc<Mutex<Pool>>, ctx: &Context) -> Result<
         let mut algo_contests = 0;
         let mut heuristic_contests = 0;
         if user_algo_history.contains_key(i) {
-            let mut rating_history = user_algo_history.get(i).unwrap().clone();
-            rating_history.sort_by(|a, b| {
-                let duration: chrono::Duration = *contest_data.get(&a.2).unwrap() - *contest_data.get(&b.2).unwrap();
-                if duration.num_milliseconds() > 0 {
-                    Ordering::Less
-                } else {
-                    Ordering::Greater
-                }
-            });
+            let rating_history = user_algo_history.get(i).unwrap().clone();
             let mut a = 0.0;
             let mut b = 0.0;
             let mut index = 1;
@@ -95,15 +92,7 @@ pub async fn user_list_update(conn: &Arc<Mutex<Pool>>, ctx: &Context) -> Result<
             algo_contests = rating_history.len();
         }
         if user_heuristic_history.contains_key(i) {
-            let mut rating_history = user_heuristic_history.get(i).unwrap().clone();
-            rating_history.sort_by(|a, b| {
-                let duration: chrono::Duration = *contest_data.get(&a.2).unwrap() - *contest_data.get(&b.2).unwrap();
-                if duration.num_milliseconds() > 0 {
-                    Ordering::Less
-                } else {
-                    Ordering::Greater
-                }
-            });
+            let rating_history = user_heuristic_history.get(i).unwrap().clone();
             let mut a = 0.0;
             let mut b = 0.0;
             let mut index = 1;
@@ -116,13 +105,13 @@ pub async fn user_list_update(conn: &Arc<Mutex<Pool>>, ctx: &Context) -> Result<
             heuristic_aperf = a / b;
             heuristic_contests = rating_history.len();
         }
-        user_rating_map.insert(i.clone().to_lowercase(), algo_rating);
+        user_rating_map.insert(i.clone(), algo_rating);
         transaction
             .exec_drop(
                 "insert into atcoder_user_ratings (user_name, algo_aperf, algo_rating, algo_contests, heuristic_aperf, heuristic_rating, heuristic_contests)
                        VALUES (:user_name, :algo_aperf, :algo_rating, :algo_contests, :heuristic_aperf, :heuristic_rating, :heuristic_contests)",
                 params! {
-                    "user_name" => i,
+                    "user_name" => screen_name,
                     "algo_aperf" => algo_aperf,
                     "algo_rating" => algo_rating,
                     "algo_contests" => algo_contests,
@@ -134,7 +123,7 @@ pub async fn user_list_update(conn: &Arc<Mutex<Pool>>, ctx: &Context) -> Result<
             .unwrap();
     }
     for i in atcoder_users_vec {
-        let ur = user_rating_map.get(&i.1.to_lowercase()).unwrap_or(&0);
+        let ur = user_rating_map.get(&user_key(&i.1)).unwrap_or(&0);
         let old_rating_color = if i.3 == 0 { 0 } else { std::cmp::min(8, i.3 / 400 + 1) };
         let new_rating_color = if ur == &0 { 0 } else { std::cmp::min(8, ur / 400 + 1) };
         if old_rating_color != new_rating_color {
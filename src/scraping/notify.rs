@@ -1,107 +1,134 @@
 use std::sync::Arc;
 
+use chrono::NaiveDateTime;
 use mysql::prelude::*;
 use mysql::*;
-use poise::serenity_prelude::{self as serenity, ChannelId, CreateEmbed, CreateMessage};
+use poise::serenity_prelude::CreateEmbed;
 use tokio::sync::Mutex;
 
+use super::contest_id::ContestId;
+use crate::mentions::{self, NotificationKind};
+use crate::outbox::{self, OutboxKind, OutboxMessage};
+use crate::routing::{RouteEvent, Routes};
+use crate::rsvp;
+
 #[derive(Debug)]
 struct Contest {
-    start_time: String,
-    duration: i32,
+    start_time: NaiveDateTime,
+    end_time: NaiveDateTime,
     rating_range_raw: String,
     name: String,
     contest_id: String,
+    contest_type: i8,
 }
 
-pub async fn notify(pool: &Arc<Mutex<Pool>>, ctx: &serenity::Context) -> Result<()> {
+pub async fn notify(pool: &Arc<Mutex<Pool>>) -> Result<()> {
     let pool = pool.lock().await;
     let mut conn = pool.get_conn().unwrap();
     let contests: Vec<Contest> = conn
         .query_map(
-            "select start_time,duration,rating_range_raw,name,contest_id from contests WHERE is_do_notify=0",
-            |(start_time, duration, rating_range_raw, name, contest_id)| Contest {
+            "select start_at,end_at,rating_range_raw,name,contest_id,contest_type from contests
+            WHERE is_do_notify=0 AND start_at <= UTC_TIMESTAMP() + INTERVAL 1 HOUR",
+            |(start_time, end_time, rating_range_raw, name, contest_id, contest_type)| Contest {
                 start_time,
-                duration,
+                end_time,
                 rating_range_raw,
                 name,
                 contest_id,
+                contest_type,
             },
         )
         .unwrap();
-    let contests: Vec<&Contest> = contests
-        .iter()
-        .filter(|contest| {
-            let start_time = chrono::DateTime::parse_from_str(&contest.start_time, "%Y-%m-%d %H:%M:%S%z").unwrap();
-            let offset = chrono::Duration::hours(1);
-            start_time - offset <= chrono::Local::now()
-        })
-        .collect();
     if !contests.is_empty() {
-        let channels: Vec<(String, String)> =
-            conn.query("SELECT server_id,contest_channel_id FROM notifications WHERE contest_channel_id is not null").unwrap();
-        let (response_ja, response_en) = {
-            let mut embed_vec_ja = vec![];
-            let mut embed_vec_en = vec![];
+        let channels: Vec<(String, Option<String>)> = conn
+            .query(
+                "SELECT server_id,contest_channel_id FROM notifications
+                WHERE contest_channel_id is not null OR server_id IN (SELECT server_id FROM notification_routes WHERE event='reminder')",
+            )
+            .unwrap();
+        let routes = Routes::load(&mut conn, RouteEvent::Reminder).unwrap();
+        let mut contest_ids = vec![];
+        // `(contest, contest type, embed in Japanese, embed in English)` of each reminder.
+        let reminders = {
+            let mut reminders = vec![];
             for contest in contests {
-                let start_time = chrono::DateTime::parse_from_str(&contest.start_time, "%Y-%m-%d %H:%M:%S%z").unwrap();
-                let offset = chrono::Duration::minutes(contest.duration as i64);
-                let end_time = start_time + offset;
+                let start_time = contest.start_time.and_utc();
+                let end_time = contest.end_time.and_utc();
                 let embed_ja = CreateEmbed::new()
                     .title(format!("{}が一時間後に開催されます", contest.name))
-                    .url(format!("https://{}", &contest.contest_id))
+                    .url(ContestId::new(&contest.contest_id).url())
                     .field("開催時間", format!("<t:{0}:f>(<t:{0}:R>)", start_time.timestamp()), false)
                     .field("終了時間", format!("<t:{0}:f>(<t:{0}:R>)", end_time.timestamp()), false)
                     .field("Rated対象", format!("`{}`", contest.rating_range_raw), false);
                 let embed_en = CreateEmbed::new()
                     .title(format!("{} will be held in an hour", contest.name))
-                    .url(format!("https://{}", &contest.contest_id))
+                    .url(ContestId::new(&contest.contest_id).url())
                     .field("Start time", format!("<t:{0}:f>(<t:{0}:R>)", start_time.timestamp()), false)
                     .field("End time", format!("<t:{0}:f>(<t:{0}:R>)", end_time.timestamp()), false)
                     .field("Rated target", format!("`{}`", contest.rating_range_raw), false);
-                embed_vec_ja.push(embed_ja);
-                embed_vec_en.push(embed_en);
-                conn.exec_drop(
-                    "UPDATE contests SET is_do_notify=1 WHERE contest_id=:contest_id",
-                    params! {"contest_id" => &contest.contest_id},
+                reminders.push((ContestId::new(&contest.contest_id), contest.contest_type, embed_ja, embed_en));
+                contest_ids.push(contest.contest_id);
+            }
+            reminders
+        };
+        // The reminders are queued in the same transaction that marks the contests as notified, so none is lost.
+        let mut transaction = conn.start_transaction(TxOpts::default()).unwrap();
+        for (server_id, channel_id) in channels {
+            let server_id = server_id.parse::<u64>().unwrap();
+            let channel_id = channel_id.and_then(|channel_id| channel_id.parse::<u64>().ok());
+            let groups = routes.group(server_id, channel_id, &reminders, |(contest_id, contest_type, _, _)| {
+                (contest_id, Some(*contest_type))
+            });
+            if groups.is_empty() {
+                continue;
+            }
+            let selected_data: Vec<String> = transaction
+                .exec(
+                    r"SELECT language FROM server_settings WHERE server_id=:server_id",
+                    params! {"server_id" => server_id},
                 )
                 .unwrap();
+            let mut lang = "ja";
+            if selected_data.len() == 1 {
+                lang = selected_data[0].as_str();
             }
-            (embed_vec_ja, embed_vec_en)
-        };
-        for i in channels {
-            if i.1 != "null" {
-                let selected_data: Vec<(String, i32)> = conn
-                    .exec(
-                        r"SELECT language,do_everyone FROM server_settings WHERE server_id=:server_id",
-                        params! {"server_id" => i.0.parse::<u64>().unwrap()},
-                    )
-                    .unwrap();
-                let mut lang = "ja";
-                if selected_data.len() == 1 {
-                    lang = selected_data[0].0.as_str();
+            let target = mentions::load_mention(&mut transaction, server_id, NotificationKind::Reminder).unwrap();
+            let participant_role = mentions::participant_role(&mut transaction, server_id).unwrap();
+            let show_join_button = participant_role.is_some() && mentions::uses_participants(&mut transaction, server_id).unwrap();
+
+            for (channel_id, reminders) in groups {
+                let embeds = reminders.iter().map(|(_, _, embed_ja, embed_en)| if lang == "ja" { embed_ja.clone() } else { embed_en.clone() }).collect();
+                let mut message = OutboxMessage::new(OutboxKind::ContestReminder, Some(server_id), channel_id).embeds(embeds);
+                if let Some(mention) = target.text(participant_role) {
+                    message = message.content(mention);
                 }
-                let mut do_everyone = true;
-                if selected_data.len() == 1 {
-                    do_everyone = selected_data[0].1 == 1;
+                // Discord shows at most 5 rows of buttons.
+                let mut rows_left = 5;
+                if show_join_button {
+                    let label = if lang == "ja" {
+                        "参加者ロールを付け外し"
+                    } else {
+                        "Toggle participant role"
+                    };
+                    message = message.button_row(vec![(mentions::JOIN_BUTTON_ID.to_string(), label.to_string())]);
+                    rows_left -= 1;
                 }
-
-                let channel_id = ChannelId::new(i.1.parse::<u64>().unwrap());
-                if lang == "ja" {
-                    let mut response = CreateMessage::new().embeds(response_ja.clone());
-                    if do_everyone {
-                        response = response.content("@everyone");
-                    }
-                    let _ = channel_id.send_message(&ctx.http, response).await;
-                } else {
-                    let mut response = CreateMessage::new().embeds(response_en.clone());
-                    if do_everyone {
-                        response = response.content("@everyone");
-                    }
-                    let _ = channel_id.send_message(&ctx.http, response).await;
+                for (contest_id, _, _, _) in reminders.iter().take(rows_left) {
+                    let row = rsvp::button_row(&mut transaction, server_id, contest_id, reminders.len() > 1, lang).unwrap();
+                    message = message.button_row(row);
                 }
+                outbox::enqueue(&mut transaction, message).unwrap();
             }
         }
+        for contest_id in contest_ids {
+            transaction
+                .exec_drop(
+                    "UPDATE contests SET is_do_notify=1 WHERE contest_id=:contest_id",
+                    params! {"contest_id" => &contest_id},
+                )
+                .unwrap();
+        }
+        transaction.commit().unwrap();
     }
     Ok(())
 }
@@ -0,0 +1,45 @@
+use scraper::Selector;
+use std::sync::OnceLock;
+
+static SELECTOR_PROFILE_ROWS: OnceLock<Selector> = OnceLock::new();
+static SELECTOR_PROFILE_HEADER: OnceLock<Selector> = OnceLock::new();
+static SELECTOR_PROFILE_VALUE: OnceLock<Selector> = OnceLock::new();
+static SELECTOR_PROFILE_SCREEN_NAME: OnceLock<Selector> = OnceLock::new();
+
+#[derive(Debug, Default, Clone)]
+pub struct UserProfile {
+    pub screen_name: String,
+    pub affiliation: String,
+}
+
+/// Fetch `https://atcoder.jp/users/{user}`. Returns `Ok(None)` when the user does not exist.
+pub async fn get_user_profile(user: &str) -> Result<Option<UserProfile>, reqwest::Error> {
+    log::info!("Get User Profile: {}", user);
+    let response = reqwest::get(format!("https://atcoder.jp/users/{}?lang=en", user)).await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let html = response.error_for_status()?.text().await?;
+    Ok(Some(parse_user_profile(&html)))
+}
+
+pub fn parse_user_profile(html: &str) -> UserProfile {
+    let document = scraper::Html::parse_document(html);
+    let rows_selector = SELECTOR_PROFILE_ROWS.get_or_init(|| Selector::parse("table.dl-table tr").unwrap());
+    let header_selector = SELECTOR_PROFILE_HEADER.get_or_init(|| Selector::parse("th").unwrap());
+    let value_selector = SELECTOR_PROFILE_VALUE.get_or_init(|| Selector::parse("td").unwrap());
+    let screen_name_selector = SELECTOR_PROFILE_SCREEN_NAME.get_or_init(|| Selector::parse("#main-container a.username span").unwrap());
+
+    let mut profile = UserProfile::default();
+    if let Some(element) = document.select(screen_name_selector).next() {
+        profile.screen_name = element.text().collect::<String>().trim().to_string();
+    }
+    for row in document.select(rows_selector) {
+        let header = row.select(header_selector).next().map(|x| x.text().collect::<String>()).unwrap_or_default();
+        let value = row.select(value_selector).next().map(|x| x.text().collect::<String>()).unwrap_or_default();
+        if header.trim() == "Affiliation" || header.trim() == "所属" {
+            profile.affiliation = value.trim().to_string();
+        }
+    }
+    profile
+}
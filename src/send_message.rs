@@ -1,82 +1,380 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
+use chrono::{NaiveDateTime, NaiveTime, Utc};
 use mysql::prelude::*;
 use mysql::*;
-use poise::serenity_prelude as serenity;
-use poise::serenity_prelude::{ChannelId, CreateEmbed, CreateMessage};
+use poise::serenity_prelude::CreateEmbed;
 use tokio::sync::Mutex;
 
+use crate::commands::contests::{self, create_contest_response, load_next_week_contests};
+use crate::mentions::{self, NotificationKind};
+use crate::outbox::{self, OutboxKind, OutboxMessage};
+use crate::routing::{RouteEvent, Routes};
+use crate::scraping::contest_id::ContestId;
+use crate::scraping::contest_type::{ContestRatingType, ContestType};
+use crate::scraping::contests::{ContestChange, ContestChangeKind};
+use crate::timezone;
+use crate::utils::MAX_DESCRIPTION_LENGTH;
+
 #[derive(Debug)]
 struct Contest {
-    start_time: String,
-    duration: i32,
+    start_time: NaiveDateTime,
+    end_time: NaiveDateTime,
     rating_range_raw: String,
     name: String,
-    contest_id: String,
+    contest_id: ContestId,
+    contest_type: i8,
 }
 
-pub async fn send_notify(pool: &Arc<Mutex<Pool>>, ctx: &serenity::Context) -> Result<()> {
+/// `(server id, contest channel id, language, time zone, digest time, last sent at)` of a contest notification channel.
+type DailyDigestTuple = (String, Option<String>, Option<String>, Option<String>, Option<NaiveTime>, Option<NaiveDateTime>);
+
+/// A digest that is due for longer than this, because the bot was down, waits for the next day.
+const DIGEST_GRACE_MINUTES: i64 = 60;
+
+/// Queue the daily digest for every server whose digest time has come, with the contests running at some point of
+/// that day in the server's time zone.
+pub async fn send_notify(pool: &Arc<Mutex<Pool>>) -> Result<()> {
     let pool = pool.lock().await;
     let mut conn = pool.get_conn().unwrap();
-    let contests: Vec<Contest> = conn
-        .query_map(
-            "select contest_id,duration,start_time,rating_range_raw,name from contests",
-            |(contest_id, duration, start_time, rating_range_raw, name)| Contest {
+    let channels: Vec<DailyDigestTuple> = conn.query(
+        "SELECT notifications.server_id, notifications.contest_channel_id, server_settings.language, server_settings.timezone,
+            server_settings.digest_time, notifications.digest_sent_at
+        FROM notifications
+        LEFT JOIN server_settings ON server_settings.server_id = notifications.server_id
+        WHERE notifications.contest_channel_id IS NOT NULL
+            OR notifications.server_id IN (SELECT server_id FROM notification_routes WHERE event='daily')",
+    )?;
+    let routes = Routes::load(&mut conn, RouteEvent::Daily)?;
+    let now = Utc::now();
+    for (server_id, channel_id, lang, timezone, digest_time, sent_at) in channels {
+        let timezone = timezone.as_deref().and_then(timezone::parse_timezone).unwrap_or(timezone::DEFAULT_TIMEZONE);
+        let Some(due) = timezone::last_daily(now, timezone, digest_time.unwrap_or(NaiveTime::MIN)) else {
+            continue;
+        };
+        if sent_at.is_some_and(|sent_at| due.naive_utc() <= sent_at) || now - due.with_timezone(&Utc) > chrono::Duration::minutes(DIGEST_GRACE_MINUTES) {
+            continue;
+        }
+        let Ok(server_id) = server_id.parse::<u64>() else {
+            continue;
+        };
+        let channel_id = channel_id.and_then(|channel_id| channel_id.parse::<u64>().ok());
+        conn.exec_drop(
+            "UPDATE notifications SET digest_sent_at=:sent_at WHERE server_id=:server_id",
+            params! {"server_id" => &server_id, "sent_at" => now.naive_utc()},
+        )?;
+
+        let (day_start, day_end) = timezone::day_range(due.date_naive(), timezone);
+        let contests: Vec<Contest> = conn.exec_map(
+            "select contest_id,start_at,end_at,rating_range_raw,name,contest_type from contests WHERE start_at < :day_end AND end_at >= :day_start ORDER BY start_at",
+            params! {"day_start" => day_start, "day_end" => day_end},
+            |(contest_id, start_time, end_time, rating_range_raw, name, contest_type): (String, _, _, _, _, _)| Contest {
                 start_time,
-                duration,
+                end_time,
                 rating_range_raw,
                 name,
-                contest_id,
+                contest_id: ContestId::new(&contest_id),
+                contest_type,
             },
-        )
-        .unwrap();
-    let contests: Vec<&Contest> = contests
-        .iter()
-        .filter(|contest| {
-            let start_time = chrono::DateTime::parse_from_str(&contest.start_time, "%Y-%m-%d %H:%M:%S%z").unwrap();
-            let offset = chrono::Duration::minutes(contest.duration as i64);
-            let end_time = (start_time + offset).date_naive();
-            let start_time = start_time.date_naive();
-            let now = chrono::Local::now().date_naive();
-            start_time <= now && now <= end_time
-        })
-        .collect();
-    if !contests.is_empty() {
-        let response = {
-            let message = CreateMessage::new();
-            let mut embed_vec = vec![];
+        )?;
+        if contests.is_empty() {
+            continue;
+        }
+        let lang = lang.unwrap_or("ja".to_string());
+        let mention = mentions::mention_text(&mut conn, server_id, NotificationKind::Daily)?;
+        let content = if lang == "ja" {
+            "今日のコンテストです"
+        } else {
+            "Here are today's contests"
+        };
+        for (channel_id, contests) in routes.group(server_id, channel_id, &contests, |contest| (&contest.contest_id, Some(contest.contest_type))) {
+            let mut embeds = vec![];
             for contest in contests {
-                let start_time = chrono::DateTime::parse_from_str(&contest.start_time, "%Y-%m-%d %H:%M:%S%z").unwrap();
-                let offset = chrono::Duration::minutes(contest.duration as i64);
-                let end_time = start_time + offset;
-                let embed = CreateEmbed::new()
-                    .title(&contest.name)
-                    .url(format!("https://{}", contest.contest_id))
-                    .field("開催時間", format!("<t:{0}:f>(<t:{0}:R>)", start_time.timestamp()), false)
-                    .field("終了時間", format!("<t:{0}:f>(<t:{0}:R>)", end_time.timestamp()), false)
-                    .field("Rated対象", format!("`{}`", contest.rating_range_raw), false);
-                embed_vec.push(embed);
+                let start_time = contest.start_time.and_utc();
+                let end_time = contest.end_time.and_utc();
+                let embed = CreateEmbed::new().title(&contest.name).url(contest.contest_id.url());
+                let embed = if lang == "ja" {
+                    embed
+                        .field("開催時間", format!("<t:{0}:f>(<t:{0}:R>)", start_time.timestamp()), false)
+                        .field("終了時間", format!("<t:{0}:f>(<t:{0}:R>)", end_time.timestamp()), false)
+                        .field("Rated対象", format!("`{}`", contest.rating_range_raw), false)
+                } else {
+                    embed
+                        .field("Start time", format!("<t:{0}:f>(<t:{0}:R>)", start_time.timestamp()), false)
+                        .field("End time", format!("<t:{0}:f>(<t:{0}:R>)", end_time.timestamp()), false)
+                        .field("Rated target", format!("`{}`", contest.rating_range_raw), false)
+                };
+                embeds.push(embed);
+            }
+            // Discord accepts at most 10 embeds per message.
+            for chunk in embeds.chunks(10) {
+                let message = OutboxMessage::new(OutboxKind::DailyContests, Some(server_id), channel_id)
+                    .content(mentions::with_mention(mention.clone(), content))
+                    .embeds(chunk.to_vec());
+                outbox::enqueue(&mut conn, message)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Forget the live standings messages of the previous day, so that the next update starts a new message.
+pub async fn clear_ranking_messages(pool: &Arc<Mutex<Pool>>) -> Result<()> {
+    let pool = pool.lock().await;
+    let mut conn = pool.get_conn()?;
+    conn.query_drop("delete from messages")
+}
+
+fn format_change_value(field: &str, value: &str, lang: &str) -> String {
+    match field {
+        "start_time" => match chrono::DateTime::parse_from_rfc3339(value) {
+            Ok(start_time) => format!("<t:{}:f>", start_time.timestamp()),
+            Err(_) => value.to_string(),
+        },
+        "duration" if lang == "ja" => format!("{}分", value),
+        "duration" => format!("{} min", value),
+        "contest_type" => match value {
+            "0" => "Algorithm".to_string(),
+            _ => "Heuristic".to_string(),
+        },
+        "rating_type" => match value {
+            "0" => "ABC".to_string(),
+            "1" => "ARC".to_string(),
+            "2" => "AGC".to_string(),
+            _ => "-".to_string(),
+        },
+        _ => format!("`{}`", value),
+    }
+}
+
+fn change_embed(change: &ContestChange, lang: &str) -> CreateEmbed {
+    let embed = CreateEmbed::new().title(&change.name).url(change.contest_id.url());
+    match change.kind {
+        ContestChangeKind::Announced => {
+            let end_time = change.start_time + chrono::Duration::minutes(change.duration as i64);
+            embed
+                .field(
+                    if lang == "ja" { "開催時間" } else { "Start" },
+                    format!("<t:{0}:f>(<t:{0}:R>)", change.start_time.timestamp()),
+                    false,
+                )
+                .field(
+                    if lang == "ja" { "終了時間" } else { "End" },
+                    format!("<t:{0}:f>(<t:{0}:R>)", end_time.timestamp()),
+                    false,
+                )
+                .field(
+                    if lang == "ja" { "Rated対象" } else { "Rated range" },
+                    format!("`{}`", change.rating_range_raw),
+                    false,
+                )
+        }
+        ContestChangeKind::Changed => {
+            let mut embed = embed;
+            for (field, old_value, new_value) in &change.fields {
+                let name = match (field.as_str(), lang) {
+                    ("name", "ja") => "コンテスト名",
+                    ("start_time", "ja") => "開催時間",
+                    ("duration", "ja") => "コンテスト時間",
+                    ("contest_type", "ja") => "種別",
+                    ("rating_type", "ja") => "レーティング種別",
+                    ("rating_range", "ja") => "Rated対象",
+                    ("name", _) => "Name",
+                    ("start_time", _) => "Start",
+                    ("duration", _) => "Duration",
+                    ("contest_type", _) => "Contest type",
+                    ("rating_type", _) => "Rating type",
+                    ("rating_range", _) => "Rated range",
+                    (field, _) => field,
+                };
+                embed = embed.field(
+                    name,
+                    format!(
+                        "{} → {}",
+                        format_change_value(field, old_value, lang),
+                        format_change_value(field, new_value, lang)
+                    ),
+                    false,
+                );
+            }
+            embed
+        }
+        ContestChangeKind::Cancelled => embed.description(if lang == "ja" {
+            "このコンテストはAtCoderのコンテスト一覧から削除されました。"
+        } else {
+            "This contest has been removed from the AtCoder contest list."
+        }),
+    }
+}
+
+/// Queue newly announced, changed and cancelled contests for the contest channel or the routed channels of every server.
+pub async fn send_contest_changes(pool: &Arc<Mutex<Pool>>, changes: &[ContestChange]) -> Result<()> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let pool = pool.lock().await;
+    let mut conn = pool.get_conn()?;
+    let channels: Vec<(String, Option<String>, Option<String>)> = conn.query(
+        "SELECT notifications.server_id, notifications.contest_channel_id, server_settings.language
+        FROM notifications
+        LEFT JOIN server_settings ON server_settings.server_id = notifications.server_id
+        WHERE notifications.contest_channel_id IS NOT NULL
+            OR notifications.server_id IN (SELECT server_id FROM notification_routes WHERE event='announced')",
+    )?;
+    let routes = Routes::load(&mut conn, RouteEvent::Announced)?;
+    for (server_id, channel_id, lang) in channels {
+        let Ok(server_id) = server_id.parse::<u64>() else {
+            continue;
+        };
+        let channel_id = channel_id.and_then(|channel_id| channel_id.parse::<u64>().ok());
+        let lang = lang.unwrap_or("ja".to_string());
+        for kind in [ContestChangeKind::Announced, ContestChangeKind::Changed, ContestChangeKind::Cancelled] {
+            let changes: Vec<&ContestChange> = changes.iter().filter(|change| change.kind == kind).collect();
+            let content = match (&kind, lang.as_str()) {
+                (ContestChangeKind::Announced, "ja") => "新しいコンテストが告知されました",
+                (ContestChangeKind::Changed, "ja") => "コンテストの内容が変更されました",
+                (ContestChangeKind::Cancelled, "ja") => "コンテストが中止されました",
+                (ContestChangeKind::Announced, _) => "New contests have been announced",
+                (ContestChangeKind::Changed, _) => "Contests have been rescheduled or changed",
+                (ContestChangeKind::Cancelled, _) => "Contests have been cancelled",
+            };
+            for (channel_id, changes) in routes.group(server_id, channel_id, &changes, |change| (&change.contest_id, Some(change.contest_type))) {
+                let embeds: Vec<CreateEmbed> = changes.iter().map(|change| change_embed(change, &lang)).collect();
+                // Discord accepts at most 10 embeds per message.
+                for chunk in embeds.chunks(10) {
+                    let message = OutboxMessage::new(OutboxKind::ContestChange, Some(server_id), channel_id).content(content).embeds(chunk.to_vec());
+                    outbox::enqueue(&mut conn, message)?;
+                }
             }
-            message.content("今日のコンテストです").add_embeds(embed_vec)
+        }
+    }
+    Ok(())
+}
+
+/// `(server id, contest channel id, language, time zone, weekday, time, last sent at)` of a server with a weekly schedule.
+type WeeklyScheduleTuple = (u64, Option<String>, Option<String>, Option<String>, u32, NaiveTime, Option<NaiveDateTime>);
+
+/// A weekly schedule that is due for longer than this, because the bot was down, waits for the next week.
+const WEEKLY_SCHEDULE_GRACE_MINUTES: i64 = 60;
+
+/// The colors of `◉` in [`create_contest_response`], plus green for heuristic contests.
+fn contest_icon(contest: &contests::Contest) -> &'static str {
+    match (&contest.contest_type, &contest.rating_type) {
+        (_, ContestRatingType::ABC) => "🔵",
+        (_, ContestRatingType::ARC) => "🟠",
+        (_, ContestRatingType::AGC) => "🔴",
+        (ContestType::Heuristic, ContestRatingType::None) => "🟢",
+        (ContestType::Algorithm, ContestRatingType::None) => "⚪",
+    }
+}
+
+/// The contests of the weekly schedule as text, for Discord timestamps and for clients that do not show the image.
+fn weekly_schedule_embed(contests: &[&contests::Contest], lang: &str) -> CreateEmbed {
+    let mut description = String::new();
+    for contest in contests {
+        let line = if lang == "ja" {
+            format!(
+                "{0} [{1}]({2})\n<t:{3}:F> (<t:{3}:R>) | {4}分 | Rated対象: `{5}`\n",
+                contest_icon(contest),
+                contest.name,
+                contest.contest_id.url(),
+                contest.start_time.timestamp(),
+                contest.duration,
+                contest.rating_raw
+            )
+        } else {
+            format!(
+                "{0} [{1}]({2})\n<t:{3}:F> (<t:{3}:R>) | {4} min | Rated range: `{5}`\n",
+                contest_icon(contest),
+                contest.name,
+                contest.contest_id.url(),
+                contest.start_time.timestamp(),
+                contest.duration,
+                contest.rating_raw
+            )
         };
-        let channels: Vec<String> = conn.query("SELECT contest_channel_id FROM notifications WHERE contest_channel_id is not null").unwrap();
-        for i in channels {
-            log::info!("{}", i);
-            if i != "null" {
-                let channel_id = i.parse::<u64>().unwrap();
-                let channel = ChannelId::new(channel_id);
-                let temp = channel.send_message(&ctx.http, response.clone()).await;
-                match temp {
-                    Ok(t) => {
-                        println!("{:?}", t);
-                    }
-                    Err(t) => {
-                        println!("{:?}", t);
-                    }
+        if description.chars().count() + line.chars().count() > MAX_DESCRIPTION_LENGTH {
+            break;
+        }
+        description += &line;
+    }
+    if contests.is_empty() {
+        description = if lang == "ja" {
+            "今後7日間に開催予定のコンテストはありません。".to_string()
+        } else {
+            "No contests are scheduled in the next 7 days.".to_string()
+        };
+    }
+    CreateEmbed::new().title(if lang == "ja" { "今週のコンテスト" } else { "Contests this week" }).description(description)
+}
+
+/// Queue the contests of the next 7 days for every server whose weekly schedule is due. The contests are split by the
+/// `daily` routes, like the daily digest.
+pub async fn send_weekly_schedule(pool: &Arc<Mutex<Pool>>) -> Result<()> {
+    let pool = pool.lock().await.clone();
+    let mut conn = pool.get_conn()?;
+    let servers: Vec<WeeklyScheduleTuple> = conn.query(
+        "SELECT server_settings.server_id, notifications.contest_channel_id, server_settings.language, server_settings.timezone,
+            server_settings.weekly_schedule_weekday, server_settings.weekly_schedule_time, server_settings.weekly_schedule_sent_at
+        FROM server_settings
+        JOIN notifications ON notifications.server_id = server_settings.server_id
+        WHERE server_settings.weekly_schedule_weekday IS NOT NULL
+            AND (notifications.contest_channel_id IS NOT NULL
+                OR server_settings.server_id IN (SELECT server_id FROM notification_routes WHERE event='daily'))",
+    )?;
+    let routes = Routes::load(&mut conn, RouteEvent::Daily)?;
+    let now = Utc::now();
+    // Loaded once, for the first server that is due.
+    let mut contests: Option<Vec<contests::Contest>> = None;
+    // The image of each set of contests, rendered once for every server and channel that gets that set.
+    let mut images: HashMap<Vec<String>, Vec<u8>> = HashMap::new();
+    for (server_id, channel_id, lang, timezone, weekday, time, sent_at) in servers {
+        let timezone = timezone.as_deref().and_then(timezone::parse_timezone).unwrap_or(timezone::DEFAULT_TIMEZONE);
+        let Some(due) = timezone::last_weekly(now, timezone, weekday, time) else {
+            continue;
+        };
+        let due = due.naive_utc();
+        if sent_at.is_some_and(|sent_at| due <= sent_at) || now.naive_utc() - due > chrono::Duration::minutes(WEEKLY_SCHEDULE_GRACE_MINUTES) {
+            continue;
+        }
+        let channel_id = channel_id.and_then(|channel_id| channel_id.parse::<u64>().ok());
+        let lang = lang.unwrap_or("ja".to_string());
+        let contests = contests.get_or_insert_with(|| load_next_week_contests(&mut conn));
+        let mut groups = routes.group(server_id, channel_id, contests, |contest| {
+            let contest_type = match contest.contest_type {
+                ContestType::Algorithm => 0,
+                ContestType::Heuristic => 1,
+            };
+            (&contest.contest_id, Some(contest_type))
+        });
+        // Without contests the default channel is still told that there are none.
+        if contests.is_empty() {
+            groups.extend(channel_id.map(|channel_id| (channel_id, vec![])));
+        }
+
+        for (channel_id, contests) in groups {
+            let mut message = OutboxMessage::new(OutboxKind::WeeklySchedule, Some(server_id), channel_id)
+                .content(if lang == "ja" {
+                    "今週のコンテストです"
+                } else {
+                    "Here are the contests of this week"
+                })
+                .embeds(vec![weekly_schedule_embed(&contests, &lang)]);
+            if !contests.is_empty() {
+                let key: Vec<String> = contests.iter().map(|contest| contest.contest_id.as_str().to_string()).collect();
+                if !images.contains_key(&key) {
+                    let (_, attachment) = create_contest_response("contests this week", pool.clone(), contests.clone(), vec![], 0, None).await;
+                    images.insert(key.clone(), attachment.data);
                 }
+                message = message.attachment("contests.png", images[&key].clone());
             }
+            outbox::enqueue(&mut conn, message)?;
         }
+        conn.exec_drop(
+            "UPDATE server_settings SET weekly_schedule_sent_at=:sent_at WHERE server_id=:server_id",
+            params! {"server_id" => server_id, "sent_at" => now.naive_utc()},
+        )?;
     }
-    conn.query_drop("delete from messages").unwrap();
     Ok(())
 }
@@ -0,0 +1,56 @@
+//! The time zone and daily digest time of each server, kept in `server_settings.timezone` and
+//! `server_settings.digest_time`.
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use mysql::prelude::*;
+use mysql::*;
+
+/// Used by servers that have not chosen a time zone. AtCoder itself runs on Japan time.
+pub const DEFAULT_TIMEZONE: Tz = chrono_tz::Asia::Tokyo;
+
+/// `name` as an IANA time zone such as `America/New_York`, ignoring case.
+pub fn parse_timezone(name: &str) -> Option<Tz> {
+    let name = name.trim();
+    chrono_tz::TZ_VARIANTS.iter().find(|timezone| timezone.name().eq_ignore_ascii_case(name)).copied()
+}
+
+/// The time zone of a server, falling back to [`DEFAULT_TIMEZONE`].
+pub fn server_timezone<Q: Queryable>(conn: &mut Q, server_id: u64) -> Result<Tz> {
+    let timezone: Option<Option<String>> = conn.exec_first(
+        "SELECT timezone FROM server_settings WHERE server_id=:server_id",
+        params! {"server_id" => server_id},
+    )?;
+    Ok(timezone.flatten().as_deref().and_then(parse_timezone).unwrap_or(DEFAULT_TIMEZONE))
+}
+
+/// The UTC range `[start, end)` of `date` in `timezone`, for comparing with the UTC datetime columns.
+pub fn day_range(date: NaiveDate, timezone: Tz) -> (NaiveDateTime, NaiveDateTime) {
+    let start = |date: NaiveDate| {
+        timezone
+            .from_local_datetime(&date.and_time(NaiveTime::MIN))
+            .earliest()
+            // Some zones skip midnight when they switch to summer time.
+            .or_else(|| timezone.from_local_datetime(&date.and_hms_opt(1, 0, 0).unwrap()).earliest())
+            .map(|start| start.naive_utc())
+            .unwrap_or(date.and_time(NaiveTime::MIN))
+    };
+    (start(date), start(date.succ_opt().unwrap_or(date)))
+}
+
+/// The last time at or before `now` that a daily `time` in `timezone` came.
+pub fn last_daily(now: DateTime<Utc>, timezone: Tz, time: NaiveTime) -> Option<DateTime<Tz>> {
+    let now = now.with_timezone(&timezone);
+    let due = timezone.from_local_datetime(&now.date_naive().and_time(time)).earliest()?;
+    if now < due {
+        return timezone.from_local_datetime(&(now.date_naive().pred_opt()?).and_time(time)).earliest();
+    }
+    Some(due)
+}
+
+/// The last time at or before `now` that a weekly `time` in `timezone` came. `weekday` counts from Monday = 0.
+pub fn last_weekly(now: DateTime<Utc>, timezone: Tz, weekday: u32, time: NaiveTime) -> Option<DateTime<Tz>> {
+    let due = last_daily(now, timezone, time)?;
+    let days_back = (due.weekday().num_days_from_monday() + 7 - weekday % 7) % 7;
+    timezone.from_local_datetime(&(due.date_naive() - chrono::Duration::days(days_back as i64)).and_time(time)).earliest()
+}
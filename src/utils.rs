@@ -1,3 +1,7 @@
+pub mod ical;
 pub mod image_to_buffer;
 pub mod svg;
 pub mod svg_to_png;
+
+/// Discord rejects embed descriptions longer than 4096 characters.
+pub const MAX_DESCRIPTION_LENGTH: usize = 4096;
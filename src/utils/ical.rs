@@ -0,0 +1,169 @@
+//! iCalendar ([RFC 5545](https://www.rfc-editor.org/rfc/rfc5545)) feeds of contests.
+
+use chrono::{DateTime, Utc};
+
+use crate::commands::contests::Contest;
+use crate::scraping::contest_type::{ContestRatingType, ContestType};
+
+/// Content lines longer than 75 octets have to be folded.
+const MAX_LINE_OCTETS: usize = 75;
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\").replace(';', "\\;").replace(',', "\\,").replace("\r\n", "\\n").replace(['\r', '\n'], "\\n")
+}
+
+/// Contest names are scraped with `inner_html`, which keeps the entities that the contest pages escape.
+fn unescape_html(text: &str) -> String {
+    text.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&nbsp;", "\u{a0}").replace("&amp;", "&")
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Append `line` with CRLF, folded so that no physical line exceeds [`MAX_LINE_OCTETS`] octets.
+fn push_line(calendar: &mut String, line: &str) {
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > MAX_LINE_OCTETS {
+            calendar.push_str("\r\n ");
+            // The leading space of the continuation counts towards the limit.
+            octets = 1;
+        }
+        calendar.push(c);
+        octets += c.len_utf8();
+    }
+    calendar.push_str("\r\n");
+}
+
+/// A calendar with one event per contest. The UID of an event only depends on the contest, so calendar apps update
+/// the event when the contest is rescheduled.
+pub fn contests_calendar(contests: &[&Contest], name: &str, lang: &str, now: DateTime<Utc>) -> String {
+    let mut calendar = String::new();
+    push_line(&mut calendar, "BEGIN:VCALENDAR");
+    push_line(&mut calendar, "VERSION:2.0");
+    push_line(&mut calendar, "PRODID:-//atcoder-notify.com//AtCoder Notify Bot//EN");
+    push_line(&mut calendar, "CALSCALE:GREGORIAN");
+    push_line(&mut calendar, "METHOD:PUBLISH");
+    push_line(&mut calendar, &format!("X-WR-CALNAME:{}", escape_text(name)));
+    // Ask subscribers to refresh twice a day.
+    push_line(&mut calendar, "REFRESH-INTERVAL;VALUE=DURATION:PT12H");
+    push_line(&mut calendar, "X-PUBLISHED-TTL:PT12H");
+    for contest in contests {
+        let contest_type = match contest.contest_type {
+            ContestType::Algorithm => "Algorithm",
+            ContestType::Heuristic => "Heuristic",
+        };
+        let rating_type = match contest.rating_type {
+            ContestRatingType::ABC => "ABC",
+            ContestRatingType::ARC => "ARC",
+            ContestRatingType::AGC => "AGC",
+            ContestRatingType::None => "Unrated",
+        };
+        let description = if lang == "ja" {
+            format!("Rated対象: {}\n時間: {}分\n{}", contest.rating_raw, contest.duration, contest.contest_id.url())
+        } else {
+            format!(
+                "Rated target: {}\nDuration: {} minutes\n{}",
+                contest.rating_raw,
+                contest.duration,
+                contest.contest_id.url()
+            )
+        };
+        push_line(&mut calendar, "BEGIN:VEVENT");
+        push_line(&mut calendar, &format!("UID:{}@atcoder-notify.com", contest.contest_id));
+        push_line(&mut calendar, &format!("DTSTAMP:{}", format_time(now)));
+        push_line(&mut calendar, &format!("DTSTART:{}", format_time(contest.start_time)));
+        push_line(&mut calendar, &format!("DTEND:{}", format_time(contest.end_time)));
+        push_line(&mut calendar, &format!("SUMMARY:{}", escape_text(&unescape_html(&contest.name))));
+        push_line(&mut calendar, &format!("DESCRIPTION:{}", escape_text(&description)));
+        push_line(&mut calendar, &format!("URL:{}", contest.contest_id.url()));
+        push_line(&mut calendar, &format!("CATEGORIES:{},{}", contest_type, rating_type));
+        push_line(&mut calendar, "TRANSP:TRANSPARENT");
+        push_line(&mut calendar, "END:VEVENT");
+    }
+    push_line(&mut calendar, "END:VCALENDAR");
+    calendar
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraping::contest_id::ContestId;
+
+    fn physical_lines(calendar: &str) -> Vec<&str> {
+        calendar.strip_suffix("\r\n").unwrap().split("\r\n").collect()
+    }
+
+    fn unfold(calendar: &str) -> String {
+        calendar.replace("\r\n ", "")
+    }
+
+    #[test]
+    fn short_lines_are_not_folded() {
+        let mut calendar = String::new();
+        push_line(&mut calendar, "BEGIN:VCALENDAR");
+        assert_eq!(calendar, "BEGIN:VCALENDAR\r\n");
+    }
+
+    #[test]
+    fn folds_long_lines_at_75_octets() {
+        let line = format!("DESCRIPTION:{}", "a".repeat(100));
+        let mut calendar = String::new();
+        push_line(&mut calendar, &line);
+        let lines = physical_lines(&calendar);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].len(), 75);
+        assert!(lines[1].starts_with(' '));
+        assert_eq!(unfold(&calendar), format!("{}\r\n", line));
+    }
+
+    #[test]
+    fn folds_multi_byte_names_between_characters() {
+        // 8 octets of `SUMMARY:` and 3 octets per character, so the 23rd character crosses the 75th octet.
+        let line = format!("SUMMARY:{}", "コンテスト".repeat(10));
+        let mut calendar = String::new();
+        push_line(&mut calendar, &line);
+        let lines = physical_lines(&calendar);
+        assert!(lines.len() > 1);
+        assert!(lines.iter().all(|line| line.len() <= MAX_LINE_OCTETS), "{:?}", lines);
+        assert_eq!(lines[0], format!("SUMMARY:{}", "コンテスト".repeat(10).chars().take(22).collect::<String>()));
+        assert_eq!(unfold(&calendar), format!("{}\r\n", line));
+    }
+
+    #[test]
+    fn escapes_text_values() {
+        assert_eq!(escape_text(r"a\b;c,d"), r"a\\b\;c\,d");
+        assert_eq!(escape_text("a\nb\r\nc\rd"), r"a\nb\nc\nd");
+    }
+
+    #[test]
+    fn unescapes_contest_names() {
+        assert_eq!(unescape_html("Xmas &amp; New Year &lt;2024&gt;"), "Xmas & New Year <2024>");
+        assert_eq!(unescape_html("&amp;lt;"), "&lt;");
+    }
+
+    #[test]
+    fn uid_only_depends_on_the_contest() {
+        let start_time = DateTime::parse_from_rfc3339("2024-10-19T12:00:00Z").unwrap().with_timezone(&Utc);
+        let mut contest = Contest {
+            contest_id: ContestId::new("abc376"),
+            start_time,
+            end_time: start_time + chrono::Duration::minutes(100),
+            contest_type: ContestType::Algorithm,
+            rating_type: ContestRatingType::ABC,
+            name: "AtCoder Beginner Contest 376 &amp; more".to_string(),
+            rating_raw: "~ 1999".to_string(),
+            rating_range: (-998244353, 1999),
+            duration: 100,
+        };
+        let first = contests_calendar(&[&contest], "AtCoder", "en", start_time);
+        contest.start_time += chrono::Duration::days(7);
+        contest.end_time += chrono::Duration::days(7);
+        let second = contests_calendar(&[&contest], "AtCoder", "en", start_time + chrono::Duration::days(1));
+        let uid = |calendar: &str| unfold(calendar).lines().find(|line| line.starts_with("UID:")).map(str::to_string);
+        assert_eq!(uid(&first).as_deref(), Some("UID:abc376@atcoder-notify.com"));
+        assert_eq!(uid(&first), uid(&second));
+        assert!(unfold(&first).contains("SUMMARY:AtCoder Beginner Contest 376 & more\r\n"));
+    }
+}
@@ -12,10 +12,16 @@ use tera::{Context, Tera};
 use tokio::sync::Mutex;
 
 use crate::{
-    scraping::contest_type::ContestType,
+    commands::contests::{load_contests, load_performances, ContestFilter},
+    routing::{RouteEvent, Routes},
+    scraping::contest_id::ContestId,
+    scraping::contest_type::{ContestRatingType, ContestType},
+    timezone::{parse_timezone, server_timezone},
+    utils::ical::contests_calendar,
     utils::svg::create_user_rating::{CreateUserRating, Theme},
 };
 use actix_web::web::Bytes;
+use chrono_tz::Tz;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[allow(non_snake_case)]
@@ -233,7 +239,7 @@ async fn get_history(pool: web::Data<Pool>, id: web::Path<String>, query: web::Q
         .exec(
             "SELECT
             user_ratings.contest, user_ratings.performance, LEAST(user_ratings.performance,contests.rating_range_end+401), user_ratings.rating,
-            contests.start_time,
+            DATE_FORMAT(CONVERT_TZ(contests.start_at, '+00:00', '+09:00'), '%Y-%m-%d %H:%i:%s+0900'),
             contests.duration
         FROM
             user_ratings
@@ -241,7 +247,7 @@ async fn get_history(pool: web::Data<Pool>, id: web::Path<String>, query: web::Q
             contests
         ON
             contests.contest_id = user_ratings.contest
-        WHERE user_ratings.user_name=:atcoder_id and user_ratings.type=:contest_type ORDER BY contests.start_time",
+        WHERE user_ratings.user_name=:atcoder_id and user_ratings.type=:contest_type ORDER BY contests.start_at",
             params! {"atcoder_id" => id.to_string(), "contest_type" => contest_type},
         )
         .unwrap();
@@ -275,6 +281,171 @@ async fn get_history(pool: web::Data<Pool>, id: web::Path<String>, query: web::Q
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ContestListElement {
+    contest_id: ContestId,
+    name: String,
+    start_time: String,
+    duration: i64,
+    contest_type: String,
+    rating_type: String,
+    rating_range: String,
+    performance: Option<i32>,
+}
+
+/// The contest filter given by `contest_type` (algo/heuristic), `rating_type` (abc/arc/agc/none), `name`, `from` and
+/// `to` (`YYYY-MM-DD`, in the IANA time zone `tz`), `user` (contests that AtCoder user took part in) and `rating`
+/// (contests rated for that rating). An unknown `contest_type` or `rating_type` is returned as an error message.
+fn contest_filter(query: &BTreeMap<String, String>) -> Result<ContestFilter, String> {
+    let contest_type = match query.get("contest_type").map(|x| x.to_lowercase()).as_deref() {
+        None => None,
+        Some("algo") => Some(0),
+        Some("heuristic") => Some(1),
+        Some(_) => return Err(format!("invalid contest_type: {}", query["contest_type"])),
+    };
+    let rating_type = match query.get("rating_type").map(|x| x.to_lowercase()).as_deref() {
+        None => None,
+        Some("abc") => Some(0),
+        Some("arc") => Some(1),
+        Some("agc") => Some(2),
+        Some("none") => Some(3),
+        Some(_) => return Err(format!("invalid rating_type: {}", query["rating_type"])),
+    };
+    Ok(ContestFilter {
+        contest_type,
+        rating_type,
+        name: query.get("name").cloned(),
+        from: query.get("from").cloned(),
+        to: query.get("to").cloned(),
+        participant: query.get("user").cloned(),
+        rated_for: query.get("rating").and_then(|x| x.trim().parse::<i32>().ok()),
+        timezone: query.get("tz").and_then(|x| parse_timezone(x)),
+    })
+}
+
+/// Past and future contests, narrowed down as in [`contest_filter`].
+#[get("/api/atcoder/contests")]
+async fn get_contests(pool: web::Data<Pool>, query: web::Query<BTreeMap<String, String>>) -> HttpResponse {
+    let filter = match contest_filter(&query) {
+        Ok(filter) => filter,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+    if let Some(date) = filter.invalid_date() {
+        return HttpResponse::BadRequest().body(format!("invalid date: {}", date));
+    }
+    let mut conn = pool.get_conn().unwrap();
+    let contests = load_contests(&mut conn);
+    let performances = filter.participant.as_deref().map(|user| load_performances(&mut conn, user)).unwrap_or_default();
+    let mut contests: Vec<_> = contests.into_iter().filter(|contest| filter.matches(contest, &performances)).collect();
+    contests.sort_by_key(|contest| contest.start_time);
+    let data: Vec<ContestListElement> = contests
+        .into_iter()
+        .map(|contest| ContestListElement {
+            performance: performances.get(&contest.contest_id).copied(),
+            contest_id: contest.contest_id,
+            name: contest.name,
+            start_time: contest.start_time.to_rfc3339(),
+            duration: contest.duration,
+            contest_type: match contest.contest_type {
+                ContestType::Algorithm => "algo",
+                ContestType::Heuristic => "heuristic",
+            }
+            .to_string(),
+            rating_type: match contest.rating_type {
+                ContestRatingType::ABC => "abc",
+                ContestRatingType::ARC => "arc",
+                ContestRatingType::AGC => "agc",
+                ContestRatingType::None => "none",
+            }
+            .to_string(),
+            rating_range: contest.rating_raw,
+        })
+        .collect();
+    HttpResponse::Ok().content_type(ContentType::json()).body(serde_json::to_string(&data).unwrap())
+}
+
+/// `(server id, default channel, routes)` whose filters a calendar feed follows, as in [`Routes::includes`].
+type ServerRoutes = (u64, Option<u64>, Routes);
+
+/// Calendar feed of the contests, narrowed down as in [`contest_filter`] and by `server_routes` if given. Without
+/// `from`, contests that ended more than 30 days ago are left out to keep the feed small.
+fn contests_calendar_response(
+    conn: &mut PooledConn,
+    query: &BTreeMap<String, String>,
+    lang: &str,
+    timezone: Option<Tz>,
+    server_routes: Option<ServerRoutes>,
+) -> HttpResponse {
+    let mut filter = match contest_filter(query) {
+        Ok(filter) => filter,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+    filter.timezone = filter.timezone.or(timezone);
+    if let Some(date) = filter.invalid_date() {
+        return HttpResponse::BadRequest().body(format!("invalid date: {}", date));
+    }
+    let now = chrono::Utc::now();
+    let contests = load_contests(conn);
+    let performances = filter.participant.as_deref().map(|user| load_performances(conn, user)).unwrap_or_default();
+    let mut contests: Vec<_> = contests
+        .iter()
+        .filter(|contest| filter.matches(contest, &performances))
+        .filter(|contest| filter.from.is_some() || now - chrono::Duration::days(30) <= contest.end_time)
+        .filter(|contest| {
+            server_routes.as_ref().is_none_or(|(server_id, default_channel, routes)| {
+                let contest_type = match contest.contest_type {
+                    ContestType::Algorithm => 0,
+                    ContestType::Heuristic => 1,
+                };
+                routes.includes(*server_id, *default_channel, &contest.contest_id, Some(contest_type))
+            })
+        })
+        .collect();
+    contests.sort_by_key(|contest| contest.start_time);
+    let name = if lang == "ja" { "AtCoder コンテスト" } else { "AtCoder Contests" };
+    HttpResponse::Ok().content_type("text/calendar; charset=utf-8").body(contests_calendar(&contests, name, lang, now))
+}
+
+#[get("/api/contests.ics")]
+async fn get_contests_calendar(pool: web::Data<Pool>, query: web::Query<BTreeMap<String, String>>) -> HttpResponse {
+    let mut conn = pool.get_conn().unwrap();
+    let lang = if query.get("lang").is_some_and(|lang| lang == "en") { "en" } else { "ja" };
+    contests_calendar_response(&mut conn, &query, lang, None, None)
+}
+
+/// The calendar feed of a server, in the language and time zone of the server. It has the contests the server is
+/// reminded of: its `reminder` routes decide them as in [`Routes::includes`], with the contest channel as the default
+/// channel, and a server without `reminder` routes gets every contest.
+#[get("/api/guilds/{guild_id}/contests.ics")]
+async fn get_guild_contests_calendar(pool: web::Data<Pool>, guild_id: web::Path<u64>, query: web::Query<BTreeMap<String, String>>) -> HttpResponse {
+    let mut conn = pool.get_conn().unwrap();
+    let selected_data: Vec<Option<String>> = conn
+        .exec(
+            r"SELECT language FROM server_settings WHERE server_id=:server_id",
+            params! {"server_id" => *guild_id},
+        )
+        .unwrap();
+    let Some(lang) = selected_data.into_iter().next() else {
+        return HttpResponse::NotFound().body("unknown guild");
+    };
+    let timezone = server_timezone(&mut conn, *guild_id).unwrap();
+    let default_channel: Option<Option<String>> = conn
+        .exec_first(
+            r"SELECT contest_channel_id FROM notifications WHERE server_id=:server_id",
+            params! {"server_id" => *guild_id},
+        )
+        .unwrap();
+    let default_channel = default_channel.flatten().and_then(|channel_id| channel_id.parse::<u64>().ok());
+    let routes = Routes::load(&mut conn, RouteEvent::Reminder).unwrap();
+    contests_calendar_response(
+        &mut conn,
+        &query,
+        lang.as_deref().unwrap_or("ja"),
+        Some(timezone),
+        Some((*guild_id, default_channel, routes)),
+    )
+}
+
 async fn default_handler(req_method: Method) -> Result<impl Responder> {
     match req_method {
         Method::GET => {
@@ -315,6 +486,9 @@ pub async fn start() {
             .app_data(web::Data::new(pool.clone()))
             .service(get_rating)
             .service(get_history)
+            .service(get_contests)
+            .service(get_contests_calendar)
+            .service(get_guild_contests_calendar)
             .service(get_user_image)
             .service(home)
             .service(icon)